@@ -0,0 +1,191 @@
+//! Merging the file-system changes of one debounce window.
+//!
+//! A `git pull` or branch switch touches hundreds of paths, often several
+//! times each. The watcher folds them into one `ChangeBatch`, keeping a single
+//! change per path whose kind describes the net effect of the window.
+
+use crate::FsChangeEvent;
+use std::collections::HashMap;
+
+/// The changes of one debounce window, at most one per path, ordered by
+/// each path's latest change.
+#[derive(Default)]
+pub struct ChangeBatch {
+    /// Merged changes; None where a change cancelled out
+    changes: Vec<Option<FsChangeEvent>>,
+    /// Index into `changes` of the live entry for each path
+    by_path: HashMap<String, usize>,
+}
+
+impl ChangeBatch {
+    /// Add a change, merging it with the one already recorded for its path.
+    /// `from` is the old path of a "rename".
+    pub fn push(&mut self, kind: &str, from: Option<String>, path: String) {
+        let mut kind = kind.to_string();
+        let mut from = from;
+
+        // A rename carries over whatever happened to the old path
+        if let Some(old) = from.clone() {
+            match self.take(&old).map(|prev| (prev.kind, prev.from)) {
+                Some((prev_kind, _)) if prev_kind == "create" => {
+                    kind = "create".to_string();
+                    from = None;
+                }
+                // A -> B -> C is a rename from A to C
+                Some((prev_kind, prev_from)) if prev_kind == "rename" => from = prev_from,
+                _ => {}
+            }
+        }
+
+        let prev = match self.take(&path) {
+            Some(prev) => prev,
+            None => return self.insert(kind, from, path),
+        };
+        let kind = match (prev.kind.as_str(), kind.as_str()) {
+            (_, "rename") => kind,
+            ("create", "remove") => return,
+            ("create", _) => prev.kind,
+            // Renamed here and then deleted: the original is what's gone
+            ("rename", "remove") => match prev.from {
+                Some(original) => return self.push("remove", None, original),
+                None => kind,
+            },
+            ("rename", _) => return self.insert(prev.kind, prev.from, path),
+            ("remove", "create" | "modify") => "modify".to_string(),
+            (_, "create") => "modify".to_string(),
+            _ => kind,
+        };
+        self.insert(kind, from, path);
+    }
+
+    /// Number of distinct paths in the batch.
+    pub fn len(&self) -> usize {
+        self.by_path.len()
+    }
+
+    /// The merged changes, oldest first.
+    pub fn into_changes(self) -> Vec<FsChangeEvent> {
+        self.changes.into_iter().flatten().collect()
+    }
+
+    fn take(&mut self, path: &str) -> Option<FsChangeEvent> {
+        let index = self.by_path.remove(path)?;
+        self.changes[index].take()
+    }
+
+    fn insert(&mut self, kind: String, from: Option<String>, path: String) {
+        self.by_path.insert(path.clone(), self.changes.len());
+        self.changes.push(Some(FsChangeEvent {
+            path,
+            kind,
+            from,
+            real_path: None,
+            real_from: None,
+            hash: None,
+            title: None,
+            mtime: None,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(changes: &[(&str, Option<&str>, &str)]) -> Vec<(String, Option<String>, String)> {
+        let mut batch = ChangeBatch::default();
+        for (kind, from, path) in changes {
+            batch.push(kind, from.map(str::to_string), path.to_string());
+        }
+        let mut out = Vec::new();
+        for change in batch.into_changes() {
+            out.push((change.kind, change.from, change.path));
+        }
+        out
+    }
+
+    fn change(kind: &str, from: Option<&str>, path: &str) -> (String, Option<String>, String) {
+        (kind.to_string(), from.map(str::to_string), path.to_string())
+    }
+
+    #[test]
+    fn repeated_changes_keep_one_entry_in_latest_order() {
+        let changes = merged(&[
+            ("modify", None, "/hq/a.md"),
+            ("modify", None, "/hq/b.md"),
+            ("modify", None, "/hq/a.md"),
+        ]);
+        assert_eq!(
+            changes,
+            vec![
+                change("modify", None, "/hq/b.md"),
+                change("modify", None, "/hq/a.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn create_then_remove_cancels_out() {
+        let changes = merged(&[
+            ("create", None, "/hq/tmp.md"),
+            ("modify", None, "/hq/tmp.md"),
+            ("remove", None, "/hq/tmp.md"),
+        ]);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn create_stays_a_create_and_recreate_is_a_modify() {
+        let changes = merged(&[
+            ("create", None, "/hq/new.md"),
+            ("modify", None, "/hq/new.md"),
+            ("remove", None, "/hq/old.md"),
+            ("create", None, "/hq/old.md"),
+        ]);
+        assert_eq!(
+            changes,
+            vec![
+                change("create", None, "/hq/new.md"),
+                change("modify", None, "/hq/old.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_chain_and_absorb_a_create() {
+        let chained = merged(&[
+            ("rename", Some("/hq/a.md"), "/hq/b.md"),
+            ("rename", Some("/hq/b.md"), "/hq/c.md"),
+            ("modify", None, "/hq/c.md"),
+        ]);
+        assert_eq!(
+            chained,
+            vec![change("rename", Some("/hq/a.md"), "/hq/c.md")]
+        );
+
+        let created = merged(&[
+            ("create", None, "/hq/draft.md"),
+            ("rename", Some("/hq/draft.md"), "/hq/final.md"),
+        ]);
+        assert_eq!(created, vec![change("create", None, "/hq/final.md")]);
+    }
+
+    #[test]
+    fn removing_a_renamed_path_removes_the_original() {
+        let changes = merged(&[
+            ("rename", Some("/hq/a.md"), "/hq/b.md"),
+            ("remove", None, "/hq/b.md"),
+        ]);
+        assert_eq!(changes, vec![change("remove", None, "/hq/a.md")]);
+    }
+
+    #[test]
+    fn len_counts_distinct_paths() {
+        let mut batch = ChangeBatch::default();
+        assert_eq!(batch.len(), 0);
+        batch.push("modify", None, "/hq/a.md".to_string());
+        batch.push("modify", None, "/hq/a.md".to_string());
+        batch.push("create", None, "/hq/b.md".to_string());
+        assert_eq!(batch.len(), 2);
+    }
+}
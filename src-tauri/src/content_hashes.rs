@@ -0,0 +1,301 @@
+//! Content hashes of watched documents.
+//!
+//! Editors and sync tools often touch a file (rewriting it unchanged, or just
+//! bumping its mtime). The watcher hashes every document a change reports and
+//! drops "modify" changes whose content matches the last hash seen, so the
+//! frontend doesn't re-read and re-render for nothing.
+
+use crate::formats;
+use crate::scan_cache::fnv1a;
+use crate::FsChangeEvent;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+struct Entry {
+    hash: u64,
+    /// When the hash was seeded at startup rather than taken from a reported
+    /// change (see `ContentHashes::seed`)
+    seeded_at: Option<Instant>,
+}
+
+/// A document's state after a change, as reported in its `FsChangeEvent`.
+struct DocState {
+    hash: u64,
+    title: Option<String>,
+    /// Modified time in milliseconds since epoch
+    mtime_ms: Option<u64>,
+}
+
+/// The last known content hash of each watched document, by on-disk path.
+pub struct ContentHashes {
+    hashes: HashMap<String, Entry>,
+    /// How much older than a change's events a seeded hash must be to be
+    /// trusted: longer than events can take to arrive, polling included
+    margin: Duration,
+}
+
+impl ContentHashes {
+    pub fn new(margin: Duration) -> Self {
+        ContentHashes {
+            hashes: HashMap::new(),
+            margin,
+        }
+    }
+
+    /// Record the hash of a document read before any change to it was seen.
+    ///
+    /// Seeding runs alongside the watcher, so it may read content whose
+    /// change event is still being debounced; dropping that event would hide
+    /// the change. Seeded hashes are therefore only trusted for changes whose
+    /// events arrived well after the document was read.
+    /// `read_at` is when reading the document started.
+    pub fn seed(&mut self, path: String, hash: u64, read_at: Instant) {
+        self.hashes.entry(path).or_insert(Entry {
+            hash,
+            seeded_at: Some(read_at),
+        });
+    }
+
+    /// Hash the documents `changes` touch, filling in their hash, title and
+    /// mtime, and drop "modify" changes whose content is unchanged.
+    /// `since` is when the earliest event of the batch arrived.
+    pub fn filter(&mut self, changes: &mut Vec<FsChangeEvent>, since: Instant) {
+        changes.retain_mut(|change| {
+            let path = change.real_path.as_ref().unwrap_or(&change.path).clone();
+            if change.kind == "remove" {
+                self.forget(&path);
+                return true;
+            }
+            if let Some(from) = change.real_from.as_ref().or(change.from.as_ref()) {
+                self.rename(from, &path);
+            }
+
+            let doc = match read_doc(Path::new(&path)) {
+                Some(doc) => doc,
+                None => return true,
+            };
+            let previous = self.hashes.insert(
+                path,
+                Entry {
+                    hash: doc.hash,
+                    seeded_at: None,
+                },
+            );
+            let unchanged = previous.is_some_and(|entry| {
+                let trusted = entry
+                    .seeded_at
+                    .is_none_or(|seeded_at| seeded_at + self.margin < since);
+                trusted && entry.hash == doc.hash
+            });
+            if change.kind == "modify" && unchanged {
+                return false;
+            }
+            change.hash = Some(format!("{:016x}", doc.hash));
+            change.title = doc.title;
+            change.mtime = doc.mtime_ms;
+            true
+        });
+    }
+
+    /// Forget the hashes of the paths `changes` touch, for a batch too large
+    /// to hash. The next change to each is reported whatever its content.
+    pub fn forget_all(&mut self, changes: &[FsChangeEvent]) {
+        for change in changes {
+            self.forget(change.real_path.as_ref().unwrap_or(&change.path));
+            if let Some(from) = change.real_from.as_ref().or(change.from.as_ref()) {
+                self.forget(from);
+            }
+        }
+    }
+
+    /// Drop the hashes of `path` and everything below it.
+    fn forget(&mut self, path: &str) {
+        let prefix = format!("{}/", path);
+        self.hashes
+            .retain(|p, _| p != path && !p.starts_with(&prefix));
+    }
+
+    /// Move the hashes of `from` and everything below it to `to`.
+    fn rename(&mut self, from: &str, to: &str) {
+        let prefix = format!("{}/", from);
+        let moved: Vec<String> = self
+            .hashes
+            .keys()
+            .filter(|p| *p == from || p.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for old in moved {
+            if let Some(entry) = self.hashes.remove(&old) {
+                self.hashes
+                    .insert(format!("{}{}", to, &old[from.len()..]), entry);
+            }
+        }
+    }
+}
+
+/// Content hash of a file (FNV-1a over its bytes).
+pub fn file_hash(path: &Path) -> Option<u64> {
+    fs::read(path).ok().map(|bytes| fnv1a(&bytes))
+}
+
+/// Hash a document and read its title and mtime. Returns None for anything
+/// that isn't a readable document.
+fn read_doc(path: &Path) -> Option<DocState> {
+    let name = path.file_name()?.to_str()?;
+    let spec = formats::for_file_name(name)?;
+    let metadata = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    let hash = file_hash(path)?;
+    let mtime_ms = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64);
+    Some(DocState {
+        hash,
+        title: (spec.read_info)(path).title,
+        mtime_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const MARGIN: Duration = Duration::from_secs(2);
+
+    fn change(kind: &str, path: &Path) -> FsChangeEvent {
+        FsChangeEvent {
+            path: path.to_string_lossy().to_string(),
+            kind: kind.to_string(),
+            from: None,
+            real_path: None,
+            real_from: None,
+            hash: None,
+            title: None,
+            mtime: None,
+        }
+    }
+
+    /// The changes of `batch` that `hashes` lets through.
+    fn filtered(hashes: &mut ContentHashes, batch: Vec<FsChangeEvent>) -> Vec<FsChangeEvent> {
+        let mut batch = batch;
+        hashes.filter(&mut batch, Instant::now());
+        batch
+    }
+
+    fn doc(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn unchanged_rewrites_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = doc(dir.path(), "a.md", "# One");
+        let mut hashes = ContentHashes::new(MARGIN);
+
+        let kept = filtered(&mut hashes, vec![change("create", &path)]);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].title.as_deref(), Some("One"));
+        assert!(kept[0].hash.is_some() && kept[0].mtime.is_some());
+
+        fs::write(&path, "# One").unwrap();
+        assert!(filtered(&mut hashes, vec![change("modify", &path)]).is_empty());
+        // Creates are reported even when the content is known
+        assert_eq!(
+            filtered(&mut hashes, vec![change("create", &path)]).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn content_changes_pass_with_their_new_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = doc(dir.path(), "a.md", "# One");
+        let mut hashes = ContentHashes::new(MARGIN);
+        let before = filtered(&mut hashes, vec![change("create", &path)]);
+
+        fs::write(&path, "# Two").unwrap();
+        let kept = filtered(&mut hashes, vec![change("modify", &path)]);
+        assert_eq!(kept.len(), 1);
+        assert_ne!(kept[0].hash, before[0].hash);
+        assert_eq!(kept[0].title.as_deref(), Some("Two"));
+
+        // Files that aren't documents pass untouched
+        let other = doc(dir.path(), "image.png", "png");
+        let kept = filtered(&mut hashes, vec![change("modify", &other)]);
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].hash.is_none());
+    }
+
+    #[test]
+    fn renames_carry_their_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("old")).unwrap();
+        let path = doc(&dir.path().join("old"), "a.md", "# One");
+        let mut hashes = ContentHashes::new(MARGIN);
+        filtered(&mut hashes, vec![change("create", &path)]);
+
+        let new_dir = dir.path().join("new");
+        fs::rename(dir.path().join("old"), &new_dir).unwrap();
+        let mut rename = change("rename", &new_dir);
+        rename.from = Some(dir.path().join("old").to_string_lossy().to_string());
+        assert_eq!(filtered(&mut hashes, vec![rename]).len(), 1);
+
+        let moved = new_dir.join("a.md");
+        assert!(filtered(&mut hashes, vec![change("modify", &moved)]).is_empty());
+        // The old path no longer has a hash
+        fs::create_dir(dir.path().join("old")).unwrap();
+        let old = doc(&dir.path().join("old"), "a.md", "# One");
+        assert_eq!(filtered(&mut hashes, vec![change("modify", &old)]).len(), 1);
+    }
+
+    #[test]
+    fn removed_and_forgotten_paths_are_reported_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = doc(dir.path(), "a.md", "# One");
+        let mut hashes = ContentHashes::new(MARGIN);
+        filtered(&mut hashes, vec![change("create", &path)]);
+        filtered(&mut hashes, vec![change("remove", dir.path())]);
+        assert_eq!(
+            filtered(&mut hashes, vec![change("modify", &path)]).len(),
+            1
+        );
+
+        hashes.forget_all(&[change("modify", &path)]);
+        assert_eq!(
+            filtered(&mut hashes, vec![change("modify", &path)]).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn seeded_hashes_are_trusted_after_the_margin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = doc(dir.path(), "a.md", "# One");
+        let key = path.to_string_lossy().to_string();
+        let hash = file_hash(&path).unwrap();
+        let read_at = Instant::now();
+
+        // Events that may have been debounced while the file was read pass
+        let mut hashes = ContentHashes::new(MARGIN);
+        hashes.seed(key.clone(), hash, read_at);
+        let mut batch = vec![change("modify", &path)];
+        hashes.filter(&mut batch, read_at + MARGIN);
+        assert_eq!(batch.len(), 1);
+
+        let mut hashes = ContentHashes::new(MARGIN);
+        hashes.seed(key.clone(), hash, read_at);
+        let mut batch = vec![change("modify", &path)];
+        hashes.filter(&mut batch, read_at + MARGIN + Duration::from_millis(1));
+        assert!(batch.is_empty());
+
+        // A seed never replaces a hash taken from a change
+        hashes.seed(key, 0, read_at);
+        assert!(filtered(&mut hashes, vec![change("modify", &path)]).is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+//! Scan diagnostics: why entries were left out of the tree, and how long
+//! each scope took.
+//!
+//! The scanner never fails on a single bad entry; it skips it and records a
+//! `SkippedEntry` here so a missing document can be explained. Every skip is
+//! counted, but only a sample of each reason is listed: a normal HQ has
+//! thousands of ignored dotfiles and build folders.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Skipped entries listed per reason; the rest are only counted.
+const MAX_SKIPPED_PER_REASON: usize = 50;
+
+/// Why an entry was left out of the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SkipReason {
+    /// A symlink whose target does not exist
+    BrokenSymlink,
+    /// The entry (or its directory listing) could not be read due to permissions
+    PermissionDenied,
+    /// A directory below the maximum scan depth
+    DepthLimit,
+    /// A directory with more entries than the per-directory limit
+    EntryLimit,
+    /// A directory where documents were dropped by a file limit
+    FileLimit,
+    /// Hidden by an ignore rule or a `!` scope pattern
+    ExcludedName,
+    /// A file name that is not valid UTF-8
+    NonUtf8Name,
+    /// A scope pattern or scope directory that yielded no documents
+    EmptyScope,
+    /// Any other I/O error
+    Unreadable,
+}
+
+/// An entry the scanner skipped.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedEntry {
+    /// Absolute path, or the scope pattern for patterns that matched nothing
+    pub path: String,
+    pub reason: SkipReason,
+    /// Extra context, e.g. the ignore rule or the I/O error
+    pub detail: Option<String>,
+}
+
+/// How long one scope root took to scan.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeTiming {
+    /// Scope directory path
+    pub path: String,
+    /// Wall-clock time; scopes are scanned in parallel, so these overlap
+    pub duration_ms: u64,
+    /// Documents found in the scope
+    pub file_count: u32,
+}
+
+/// The `scanDiagnostics` section of a scan result.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanDiagnostics {
+    /// A sample of the skipped entries (the first few of each reason by
+    /// path), sorted by path
+    pub skipped: Vec<SkippedEntry>,
+    /// Number of skipped entries per reason, sampled or not
+    pub skipped_counts: BTreeMap<SkipReason, u32>,
+    /// One entry per scanned scope root, in scope order
+    pub scopes: Vec<ScopeTiming>,
+    /// Wall-clock time of the whole scan
+    pub total_ms: u64,
+}
+
+/// Skipped entries collected during a scan: all counted, and the first
+/// `MAX_SKIPPED_PER_REASON` of each reason by path kept, so the sample
+/// doesn't depend on the order workers finish in.
+#[derive(Default)]
+pub struct SkipLog {
+    counts: BTreeMap<SkipReason, u32>,
+    /// Path to detail, per reason
+    samples: BTreeMap<SkipReason, BTreeMap<String, Option<String>>>,
+}
+
+impl SkipLog {
+    pub fn record(&mut self, path: String, reason: SkipReason, detail: Option<String>) {
+        *self.counts.entry(reason).or_default() += 1;
+        let sample = self.samples.entry(reason).or_default();
+        sample.insert(path, detail);
+        if sample.len() > MAX_SKIPPED_PER_REASON {
+            sample.pop_last();
+        }
+    }
+
+    /// The sampled entries sorted by path, and the count per reason.
+    pub fn finish(self) -> (Vec<SkippedEntry>, BTreeMap<SkipReason, u32>) {
+        let mut skipped: Vec<SkippedEntry> = self
+            .samples
+            .into_iter()
+            .flat_map(|(reason, sample)| {
+                sample.into_iter().map(move |(path, detail)| SkippedEntry {
+                    path,
+                    reason,
+                    detail,
+                })
+            })
+            .collect();
+        skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        (skipped, self.counts)
+    }
+}
+
+/// Classify an I/O error on `path` (an entry that was listed but could not
+/// be opened or stat'ed).
+pub fn reason_for_error(path: &Path, error: &io::Error) -> SkipReason {
+    let is_symlink = fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false);
+    match error.kind() {
+        io::ErrorKind::NotFound if is_symlink => SkipReason::BrokenSymlink,
+        io::ErrorKind::PermissionDenied => SkipReason::PermissionDenied,
+        _ => SkipReason::Unreadable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_log_counts_everything_and_samples_lowest_paths() {
+        let mut log = SkipLog::default();
+        // Recorded in reverse, as racing workers might
+        for i in (0..120).rev() {
+            log.record(
+                format!("/hq/.hidden-{:03}", i),
+                SkipReason::ExcludedName,
+                None,
+            );
+        }
+        log.record("/hq/a/broken".to_string(), SkipReason::BrokenSymlink, None);
+
+        let (skipped, counts) = log.finish();
+        assert_eq!(counts[&SkipReason::ExcludedName], 120);
+        assert_eq!(counts[&SkipReason::BrokenSymlink], 1);
+        assert_eq!(skipped.len(), MAX_SKIPPED_PER_REASON + 1);
+        assert_eq!(skipped[0].path, "/hq/.hidden-000");
+        assert_eq!(
+            skipped[MAX_SKIPPED_PER_REASON - 1].path,
+            format!("/hq/.hidden-{:03}", MAX_SKIPPED_PER_REASON - 1)
+        );
+        assert_eq!(skipped.last().unwrap().path, "/hq/a/broken");
+    }
+}
@@ -0,0 +1,318 @@
+//! Document format registry.
+//!
+//! Decides which files the scanner includes (by extension) and how each
+//! format's title is found. The format is also reported on every file node so
+//! the frontend can pick a renderer.
+
+use crate::front_matter::{self, Fence, FrontMatter};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Maximum number of lines read looking for the end of a front matter block.
+const MAX_FRONT_MATTER_LINES: usize = 500;
+
+/// Number of lines searched for a title (after any front matter).
+const TITLE_SCAN_LINES: usize = 50;
+
+/// Notebooks larger than this are listed without a title rather than parsed.
+const MAX_NOTEBOOK_BYTES: u64 = 16 * 1024 * 1024;
+
+/// A supported document format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocFormat {
+    Markdown,
+    Mdx,
+    Text,
+    Rst,
+    Org,
+    Notebook,
+}
+
+/// A registry entry: which extensions map to a format and how to read it.
+pub struct FormatSpec {
+    pub format: DocFormat,
+    /// Lowercase extensions without the leading dot
+    pub extensions: &'static [&'static str],
+    /// Reads the title (and front matter, where supported)
+    pub read_info: fn(&Path) -> DocInfo,
+}
+
+/// All formats the scanner includes.
+pub const FORMATS: &[FormatSpec] = &[
+    FormatSpec {
+        format: DocFormat::Markdown,
+        extensions: &["md", "markdown"],
+        read_info: extract_md_info,
+    },
+    FormatSpec {
+        format: DocFormat::Mdx,
+        extensions: &["mdx"],
+        read_info: extract_md_info,
+    },
+    FormatSpec {
+        format: DocFormat::Text,
+        extensions: &["txt"],
+        read_info: |_| DocInfo::default(),
+    },
+    FormatSpec {
+        format: DocFormat::Rst,
+        extensions: &["rst"],
+        read_info: extract_rst_info,
+    },
+    FormatSpec {
+        format: DocFormat::Org,
+        extensions: &["org"],
+        read_info: extract_org_info,
+    },
+    FormatSpec {
+        format: DocFormat::Notebook,
+        extensions: &["ipynb"],
+        read_info: extract_notebook_info,
+    },
+];
+
+/// Look up the format for a file name by its extension (case-insensitive).
+pub fn for_file_name(name: &str) -> Option<&'static FormatSpec> {
+    let (_, ext) = name.rsplit_once('.')?;
+    let ext = ext.to_ascii_lowercase();
+    FORMATS
+        .iter()
+        .find(|spec| spec.extensions.contains(&ext.as_str()))
+}
+
+/// What the scanner reads out of a document. Cached per file in the scan cache.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocInfo {
+    /// Front matter `title`, else the format's own heading
+    pub title: Option<String>,
+    /// Parsed YAML/TOML front matter, if the file has a well-formed block
+    pub front_matter: Option<FrontMatter>,
+    /// Problem found while reading the file (e.g. malformed front matter)
+    pub warning: Option<String>,
+}
+
+/// Open a file as an iterator of lines, stopping at the first read error.
+fn read_lines(path: &Path) -> Option<impl Iterator<Item = String>> {
+    let file = fs::File::open(path).ok()?;
+    Some(BufReader::new(file).lines().map_while(Result::ok))
+}
+
+/// First `# ` heading in `lines`.
+fn first_md_heading<I: IntoIterator<Item = S>, S: AsRef<str>>(lines: I) -> Option<String> {
+    lines.into_iter().find_map(|line| {
+        let title = line.as_ref().trim().strip_prefix("# ")?.trim();
+        (!title.is_empty()).then(|| title.to_string())
+    })
+}
+
+/// Read a markdown file's front matter and title.
+///
+/// A `---` (YAML) or `+++` (TOML) block on the first line is parsed as front
+/// matter; its `title` wins over the first `# ` heading. Only reads the front
+/// matter plus the first 50 lines after it, for performance.
+pub fn extract_md_info(path: &Path) -> DocInfo {
+    match read_lines(path) {
+        Some(lines) => md_info(lines),
+        None => DocInfo::default(),
+    }
+}
+
+/// Front matter and title of markdown `lines`. An opening fence that is
+/// never closed is a thematic break, so the lines after it are body text.
+fn md_info(mut lines: impl Iterator<Item = String>) -> DocInfo {
+    let mut info = DocInfo::default();
+
+    let mut head: Vec<String> = lines.next().into_iter().collect();
+    if let Some(fence) = head.first().and_then(|line| Fence::detect(line)) {
+        let mut closed = false;
+        for line in lines.by_ref().take(MAX_FRONT_MATTER_LINES) {
+            if fence.closes(&line) {
+                closed = true;
+                break;
+            }
+            head.push(line);
+        }
+
+        if closed {
+            let mut body = String::new();
+            for line in head.drain(..).skip(1) {
+                body.push_str(&line);
+                body.push('\n');
+            }
+            match front_matter::parse(fence, &body) {
+                Ok(fm) => info.front_matter = Some(fm),
+                Err(e) => info.warning = Some(e),
+            }
+        }
+    }
+
+    info.title = info.front_matter.as_ref().and_then(|fm| fm.title.clone());
+    if info.title.is_none() {
+        info.title = first_md_heading(head.into_iter().chain(lines).take(TITLE_SCAN_LINES));
+    }
+
+    info
+}
+
+/// Read the title of a reStructuredText file: the first section heading,
+/// i.e. a line of text underlined (and optionally overlined) by a run of one
+/// punctuation character at least as long as the text.
+pub fn extract_rst_info(path: &Path) -> DocInfo {
+    let lines: Vec<String> = match read_lines(path) {
+        Some(lines) => lines.take(TITLE_SCAN_LINES).collect(),
+        None => return DocInfo::default(),
+    };
+
+    let title = lines.windows(2).find_map(|pair| {
+        let text = pair[0].trim();
+        let underline = pair[1].trim_end();
+        let is_underline =
+            is_rst_adornment(underline) && underline.chars().count() >= text.chars().count();
+        (is_underline && !text.is_empty() && !is_rst_adornment(text)).then(|| text.to_string())
+    });
+
+    DocInfo {
+        title,
+        ..Default::default()
+    }
+}
+
+/// Whether `line` is itself an adornment line (e.g. an overline).
+fn is_rst_adornment(line: &str) -> bool {
+    let mut chars = line.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_punctuation() => chars.all(|d| d == c),
+        _ => false,
+    }
+}
+
+/// Read the title of an Org file: `#+TITLE:`, else the first `* ` headline.
+pub fn extract_org_info(path: &Path) -> DocInfo {
+    let lines: Vec<String> = match read_lines(path) {
+        Some(lines) => lines.take(TITLE_SCAN_LINES).collect(),
+        None => return DocInfo::default(),
+    };
+
+    let keyword = lines.iter().find_map(|line| {
+        let line = line.trim();
+        let (key, value) = line.split_once(':')?;
+        let value = value.trim();
+        (key.eq_ignore_ascii_case("#+title") && !value.is_empty()).then(|| value.to_string())
+    });
+    let title = keyword.or_else(|| {
+        lines.iter().find_map(|line| {
+            let title = line.strip_prefix("* ")?.trim();
+            (!title.is_empty()).then(|| title.to_string())
+        })
+    });
+
+    DocInfo {
+        title,
+        ..Default::default()
+    }
+}
+
+/// A notebook cell; only the fields needed for the title are read.
+#[derive(Deserialize)]
+struct NotebookCell {
+    cell_type: String,
+    #[serde(default)]
+    source: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Notebook {
+    #[serde(default)]
+    cells: Vec<NotebookCell>,
+}
+
+/// Read the title of a Jupyter notebook from its first markdown cell: the
+/// first `# ` heading in that cell, else its first non-empty line.
+pub fn extract_notebook_info(path: &Path) -> DocInfo {
+    let too_large = fs::metadata(path)
+        .map(|m| m.len() > MAX_NOTEBOOK_BYTES)
+        .unwrap_or(true);
+    if too_large {
+        return DocInfo::default();
+    }
+
+    let notebook: Notebook = match fs::File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|f| serde_json::from_reader(BufReader::new(f)).map_err(|e| e.to_string()))
+    {
+        Ok(notebook) => notebook,
+        Err(e) => {
+            return DocInfo {
+                warning: Some(format!("Invalid notebook: {}", e)),
+                ..Default::default()
+            }
+        }
+    };
+
+    // `source` is either a string or a list of line strings
+    let source = notebook
+        .cells
+        .iter()
+        .find(|cell| cell.cell_type == "markdown")
+        .map(|cell| match &cell.source {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Array(parts) => parts.iter().filter_map(|p| p.as_str()).collect(),
+            _ => String::new(),
+        })
+        .unwrap_or_default();
+
+    let title = first_md_heading(source.lines()).or_else(|| {
+        source
+            .lines()
+            .map(|line| line.trim().trim_start_matches('#').trim())
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+    });
+
+    DocInfo {
+        title,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(text: &str) -> DocInfo {
+        md_info(text.lines().map(str::to_string))
+    }
+
+    #[test]
+    fn front_matter_title_wins_over_heading() {
+        let doc = info("---\ntitle: Roadmap\ntags: [a, b]\n---\n# Heading\n");
+        assert_eq!(doc.title.as_deref(), Some("Roadmap"));
+        assert_eq!(doc.front_matter.unwrap().tags, vec!["a", "b"]);
+        assert_eq!(doc.warning, None);
+    }
+
+    #[test]
+    fn unclosed_fence_is_a_thematic_break() {
+        let doc = info("---\n\n# Notes\n\nSome text\n");
+        assert_eq!(doc.title.as_deref(), Some("Notes"));
+        assert!(doc.front_matter.is_none());
+        assert_eq!(doc.warning, None);
+    }
+
+    #[test]
+    fn malformed_front_matter_is_reported() {
+        let doc = info("+++\ntitle = \n+++\n# Fallback\n");
+        assert!(doc.front_matter.is_none());
+        let warning = doc.warning.unwrap();
+        assert!(
+            warning.starts_with("Invalid TOML front matter"),
+            "{}",
+            warning
+        );
+        assert_eq!(doc.title.as_deref(), Some("Fallback"));
+    }
+}
@@ -0,0 +1,214 @@
+//! Front matter parsing for scanned documents.
+//!
+//! Supports YAML blocks fenced by `---` and TOML blocks fenced by `+++` at the
+//! very top of a file. The block is converted to JSON for the frontend, and a
+//! few well-known keys (`title`, `tags`, `description`, `order`, `draft`) are
+//! lifted into typed fields.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Well-known front matter fields lifted out of the raw block.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrontMatter {
+    /// The whole block as JSON (always an object)
+    pub raw: Value,
+    pub title: Option<String>,
+    /// `tags` as a list, or a comma-separated string
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    /// Sort key for sidebar ordering
+    pub order: Option<f64>,
+    pub draft: bool,
+}
+
+/// Front matter syntax, detected from the opening fence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fence {
+    Yaml,
+    Toml,
+}
+
+impl Fence {
+    /// Detect an opening fence from the first line of a file.
+    pub fn detect(first_line: &str) -> Option<Fence> {
+        match first_line.trim_start_matches('\u{feff}').trim_end() {
+            "---" => Some(Fence::Yaml),
+            "+++" => Some(Fence::Toml),
+            _ => None,
+        }
+    }
+
+    /// Whether `line` closes a block opened by this fence.
+    /// YAML blocks may also be closed with `...`.
+    pub fn closes(self, line: &str) -> bool {
+        matches!(
+            (self, line.trim_end()),
+            (Fence::Yaml, "---" | "...") | (Fence::Toml, "+++")
+        )
+    }
+}
+
+/// Parse the body of a front matter block (without its fences).
+pub fn parse(fence: Fence, body: &str) -> Result<FrontMatter, String> {
+    let raw: Value = match fence {
+        Fence::Yaml => {
+            if body.trim().is_empty() {
+                Value::Object(Default::default())
+            } else {
+                serde_yaml_ng::from_str(body)
+                    .map_err(|e| format!("Invalid YAML front matter: {}", e))?
+            }
+        }
+        Fence::Toml => {
+            let table: toml::Table =
+                toml::from_str(body).map_err(|e| format!("Invalid TOML front matter: {}", e))?;
+            toml_to_json(toml::Value::Table(table))
+        }
+    };
+
+    let map = match &raw {
+        Value::Object(map) => map,
+        _ => return Err("Front matter must be a set of key/value pairs".to_string()),
+    };
+
+    let title = map
+        .get("title")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string);
+
+    let tags = match map.get("tags") {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|t| match t {
+                Value::String(s) => Some(s.trim().to_string()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .filter(|t| !t.is_empty())
+            .collect(),
+        Some(Value::String(s)) => s
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let description = map
+        .get("description")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(str::to_string);
+
+    let order = map.get("order").and_then(Value::as_f64);
+    let draft = map.get("draft").and_then(Value::as_bool).unwrap_or(false);
+
+    Ok(FrontMatter {
+        raw,
+        title,
+        tags,
+        description,
+        order,
+        draft,
+    })
+}
+
+/// Convert a TOML value to JSON. Dates and times become their TOML text
+/// (e.g. "2024-01-01"), as YAML dates do; non-finite floats become null.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => serde_json::Number::from_f64(f).map_or(Value::Null, Value::Number),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn yaml_block_lifts_well_known_keys() {
+        let body = "title: ' Roadmap '\ntags: [plans, 2024]\ndescription: Next steps\n\
+                    order: 2.5\ndraft: true\nowner: {name: Ada}\n";
+        let fm = parse(Fence::Yaml, body).unwrap();
+        assert_eq!(fm.title.as_deref(), Some("Roadmap"));
+        assert_eq!(fm.tags, ["plans", "2024"]);
+        assert_eq!(fm.description.as_deref(), Some("Next steps"));
+        assert_eq!(fm.order, Some(2.5));
+        assert!(fm.draft);
+        assert_eq!(fm.raw["owner"], json!({"name": "Ada"}));
+    }
+
+    #[test]
+    fn toml_block_keeps_dates_as_strings() {
+        let body = "title = \"Release\"\ntags = \"a, b,,c \"\norder = 3\n\
+                    date = 2024-01-01\nupdated = 2024-05-06T07:08:09Z\n\
+                    [meta]\nreviewed = 2024-02-03\n";
+        let fm = parse(Fence::Toml, body).unwrap();
+        assert_eq!(fm.title.as_deref(), Some("Release"));
+        assert_eq!(fm.tags, ["a", "b", "c"]);
+        assert_eq!(fm.order, Some(3.0));
+        assert!(!fm.draft);
+        assert_eq!(fm.raw["date"], json!("2024-01-01"));
+        assert_eq!(fm.raw["updated"], json!("2024-05-06T07:08:09Z"));
+        assert_eq!(fm.raw["meta"], json!({"reviewed": "2024-02-03"}));
+    }
+
+    #[test]
+    fn empty_and_unusable_values_are_left_out() {
+        let fm = parse(Fence::Yaml, "  \n").unwrap();
+        assert_eq!(fm.raw, json!({}));
+        assert_eq!(
+            fm,
+            FrontMatter {
+                raw: json!({}),
+                ..FrontMatter::default()
+            }
+        );
+
+        let fm = parse(
+            Fence::Yaml,
+            "title: ''\ntags: [[x], ' ']\norder: soon\ndraft: yes\n",
+        )
+        .unwrap();
+        assert_eq!(fm.title, None);
+        assert!(fm.tags.is_empty());
+        assert_eq!(fm.order, None);
+        assert!(!fm.draft);
+    }
+
+    #[test]
+    fn invalid_blocks_are_errors() {
+        let yaml = parse(Fence::Yaml, "title: [unclosed\n").unwrap_err();
+        assert!(yaml.starts_with("Invalid YAML front matter"), "{}", yaml);
+        let toml = parse(Fence::Toml, "title = \n").unwrap_err();
+        assert!(toml.starts_with("Invalid TOML front matter"), "{}", toml);
+        let list = parse(Fence::Yaml, "- a\n- b\n").unwrap_err();
+        assert_eq!(list, "Front matter must be a set of key/value pairs");
+    }
+
+    #[test]
+    fn fences_are_detected_and_closed() {
+        assert_eq!(Fence::detect("\u{feff}---"), Some(Fence::Yaml));
+        assert_eq!(Fence::detect("+++  "), Some(Fence::Toml));
+        assert_eq!(Fence::detect("----"), None);
+        assert!(Fence::Yaml.closes("..."));
+        assert!(!Fence::Toml.closes("---"));
+    }
+}
@@ -0,0 +1,322 @@
+//! The shared ignore engine used by both the scanner and the file watcher.
+//!
+//! Rules come from three places, checked from most to least specific:
+//! 1. `.hqdocsignore` at the HQ root (gitignore syntax)
+//! 2. `.gitignore` files from the path's own directory up to the HQ root
+//! 3. Built-in defaults (build output, VCS folders, dotfiles)
+//!
+//! The first source with a matching rule decides, so a `!pattern` in
+//! `.hqdocsignore` can re-include something a `.gitignore` hides.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// HQ-level ignore file name.
+pub const HQDOCS_IGNORE_FILE: &str = ".hqdocsignore";
+
+/// Built-in exclusions, in gitignore syntax.
+const BUILTIN_RULES: &[&str] = &[
+    "node_modules",
+    ".git",
+    "dist",
+    ".next",
+    ".turbo",
+    ".vercel",
+    "target",
+    ".DS_Store",
+    "thumbs.db",
+    ".*",
+];
+
+/// The rule that excluded a path.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IgnoreMatch {
+    /// The pattern as written in its source
+    pub rule: String,
+    /// Path of the ignore file the rule came from, or "built-in"
+    pub source: String,
+}
+
+/// A path excluded during a scan, reported when the caller asks for it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExcludedPath {
+    /// Absolute path as seen through the scope
+    pub path: String,
+    #[serde(flatten)]
+    pub matched: IgnoreMatch,
+}
+
+/// Gitignore-style exclusion rules for one HQ root.
+pub struct IgnoreEngine {
+    hq: PathBuf,
+    builtin: Gitignore,
+    hqdocs: Gitignore,
+    /// Parsed `.gitignore` per HQ-relative directory (None if the dir has none)
+    gitignores: Mutex<HashMap<String, Option<Arc<Gitignore>>>>,
+}
+
+impl IgnoreEngine {
+    /// Build the engine for `hq`, loading built-ins and `.hqdocsignore`.
+    /// Nested `.gitignore` files are loaded lazily as paths are checked.
+    pub fn new(hq: &Path) -> Self {
+        let mut builtin = GitignoreBuilder::new(hq);
+        for rule in BUILTIN_RULES {
+            let _ = builtin.add_line(None, rule);
+        }
+
+        IgnoreEngine {
+            hq: hq.to_path_buf(),
+            builtin: builtin.build().unwrap_or_else(|_| Gitignore::empty()),
+            hqdocs: load_ignore_file(hq, &hq.join(HQDOCS_IGNORE_FILE))
+                .unwrap_or_else(Gitignore::empty),
+            gitignores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check a single HQ-relative path (`/`-separated) against all rules.
+    /// Ancestors are not checked; callers walking a tree prune excluded
+    /// directories as they go.
+    pub fn check(&self, rel: &str, is_dir: bool) -> Option<IgnoreMatch> {
+        if rel.is_empty() {
+            return None;
+        }
+
+        if let Some(decision) = decide(&self.hqdocs, Path::new(rel), is_dir) {
+            return decision;
+        }
+
+        // Walk .gitignore files from the path's own directory up to the HQ root
+        let mut dir = parent_rel(rel);
+        loop {
+            if let Some(gitignore) = self.gitignore_for(dir) {
+                let below = rel[dir.len()..].trim_start_matches('/');
+                if let Some(decision) = decide(&gitignore, Path::new(below), is_dir) {
+                    return decision;
+                }
+            }
+            if dir.is_empty() {
+                break;
+            }
+            dir = parent_rel(dir);
+        }
+
+        decide(&self.builtin, Path::new(rel), is_dir).flatten()
+    }
+
+    /// Check a listed entry whose type isn't known yet. The entry at `path`
+    /// is only stat'ed when a directory-only rule (`name/`) decides, so
+    /// ignored entries cost no syscall and an ignored broken symlink is just
+    /// ignored.
+    pub fn check_entry(&self, rel: &str, path: &Path) -> Option<IgnoreMatch> {
+        match (self.check(rel, false), self.check(rel, true)) {
+            (Some(matched), Some(_)) => Some(matched),
+            (None, None) => None,
+            (as_file, as_dir) => {
+                if path.is_dir() {
+                    as_dir
+                } else {
+                    as_file
+                }
+            }
+        }
+    }
+
+    /// Check `rel` and each of its ancestors below `scope_rel` (the scope
+    /// root itself is never excluded). Used by the watcher, which sees
+    /// arbitrary paths rather than walking down the tree.
+    pub fn check_below(&self, scope_rel: &str, rel: &str, is_dir: bool) -> Option<IgnoreMatch> {
+        let below = rel
+            .strip_prefix(scope_rel)
+            .map(|r| r.trim_start_matches('/'))
+            .unwrap_or(rel);
+        let base_len = rel.len() - below.len();
+
+        let mut end = base_len;
+        for segment in below.split('/').filter(|s| !s.is_empty()) {
+            end += segment.len();
+            let is_last = end >= rel.len();
+            if let Some(matched) = self.check(&rel[..end], !is_last || is_dir) {
+                return Some(matched);
+            }
+            end += 1;
+        }
+        None
+    }
+
+    /// Forget the cached `.gitignore` for an HQ-relative directory, so the
+    /// next check re-reads it (called when the watcher sees it change).
+    pub fn invalidate(&self, dir_rel: &str) {
+        if let Ok(mut cache) = self.gitignores.lock() {
+            cache.remove(dir_rel);
+        }
+    }
+
+    fn gitignore_for(&self, dir_rel: &str) -> Option<Arc<Gitignore>> {
+        let mut cache = self.gitignores.lock().ok()?;
+        cache
+            .entry(dir_rel.to_string())
+            .or_insert_with(|| {
+                let dir = self.hq.join(dir_rel);
+                load_ignore_file(&dir, &dir.join(".gitignore")).map(Arc::new)
+            })
+            .clone()
+    }
+}
+
+/// Evaluate one rule source: `Some(Some(_))` excluded, `Some(None)` explicitly
+/// re-included by a `!` rule, `None` no rule matched.
+fn decide(gitignore: &Gitignore, path: &Path, is_dir: bool) -> Option<Option<IgnoreMatch>> {
+    match gitignore.matched(path, is_dir) {
+        Match::None => None,
+        Match::Whitelist(_) => Some(None),
+        Match::Ignore(glob) => Some(Some(IgnoreMatch {
+            rule: glob.original().to_string(),
+            source: glob
+                .from()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|| "built-in".to_string()),
+        })),
+    }
+}
+
+/// Parse a gitignore-syntax file rooted at `root`. Returns None if the file
+/// does not exist or has no usable rules.
+fn load_ignore_file(root: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    // Invalid lines are skipped; the rest of the file still applies
+    let _ = builder.add(file);
+    builder.build().ok().filter(|g| !g.is_empty())
+}
+
+/// Parent of an HQ-relative path ("" for top-level entries).
+fn parent_rel(rel: &str) -> &str {
+    rel.rfind('/').map(|i| &rel[..i]).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn check_entry_ignores_without_stat() {
+        let hq = tempfile::tempdir().unwrap();
+        fs::write(hq.path().join(HQDOCS_IGNORE_FILE), "drafts/\n").unwrap();
+        fs::create_dir(hq.path().join("drafts")).unwrap();
+        fs::write(hq.path().join("notes.md"), "").unwrap();
+        let engine = IgnoreEngine::new(hq.path());
+
+        // A broken symlink matched by a built-in rule is simply ignored
+        #[cfg(unix)]
+        {
+            let link = hq.path().join(".broken");
+            std::os::unix::fs::symlink(hq.path().join("missing"), &link).unwrap();
+            let matched = engine.check_entry(".broken", &link).unwrap();
+            assert_eq!(matched.rule, ".*");
+        }
+
+        // A directory-only rule needs the entry's type
+        let matched = engine
+            .check_entry("drafts", &hq.path().join("drafts"))
+            .unwrap();
+        assert_eq!(matched.rule, "drafts/");
+        assert!(engine
+            .check_entry("drafts", &hq.path().join("drafts.md"))
+            .is_none());
+        assert!(engine
+            .check_entry("notes.md", &hq.path().join("notes.md"))
+            .is_none());
+        assert!(engine
+            .check_entry("node_modules", &hq.path().join("node_modules"))
+            .is_some());
+    }
+
+    /// An engine for a new HQ with the given ignore files.
+    fn engine_with(files: &[(&str, &str)]) -> (tempfile::TempDir, IgnoreEngine) {
+        let hq = tempfile::tempdir().unwrap();
+        for (path, rules) in files {
+            let path = hq.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, rules).unwrap();
+        }
+        let engine = IgnoreEngine::new(hq.path());
+        (hq, engine)
+    }
+
+    #[test]
+    fn file_in_ignored_directory_contents_can_be_kept() {
+        // The README example: `vendor/` would prune the whole directory, so
+        // its contents are ignored instead
+        let (_hq, engine) = engine_with(&[(HQDOCS_IGNORE_FILE, "vendor/*\n!vendor/README.md\n")]);
+        assert!(engine.check("vendor", true).is_none());
+        assert!(engine.check("vendor/README.md", false).is_none());
+        let matched = engine.check("vendor/lib.md", false).unwrap();
+        assert_eq!(matched.rule, "vendor/*");
+        assert!(engine.check_below("", "vendor/README.md", false).is_none());
+    }
+
+    #[test]
+    fn hqdocsignore_overrides_gitignore_and_builtins() {
+        let (hq, engine) = engine_with(&[
+            (".gitignore", "build/\nout/\n"),
+            (HQDOCS_IGNORE_FILE, "!build/\n!dist/\nnotes.md\n"),
+        ]);
+        assert!(engine.check("build", true).is_none());
+        assert!(engine.check("dist", true).is_none());
+        // Without a re-include, the .gitignore rule applies
+        assert!(engine.check("out", true).is_some());
+        let matched = engine.check("docs/notes.md", false).unwrap();
+        assert_eq!(matched.rule, "notes.md");
+        let source = hq.path().join(HQDOCS_IGNORE_FILE);
+        assert_eq!(matched.source, source.to_string_lossy());
+    }
+
+    #[test]
+    fn nearest_gitignore_decides() {
+        let (hq, engine) = engine_with(&[
+            (".gitignore", "*.log.md\n!.github/\n"),
+            ("docs/.gitignore", "!keep.log.md\nscratch/\n"),
+        ]);
+        assert!(engine.check("docs/keep.log.md", false).is_none());
+        let matched = engine.check("docs/other.log.md", false).unwrap();
+        let source = hq.path().join(".gitignore");
+        assert_eq!(matched.source, source.to_string_lossy());
+        // Rules of a nested .gitignore only apply below its directory
+        assert!(engine.check("docs/scratch", true).is_some());
+        assert!(engine.check("scratch", true).is_none());
+        // A .gitignore can re-include what a built-in rule hides
+        assert!(engine.check(".github", true).is_none());
+        assert_eq!(engine.check(".cache", true).unwrap().source, "built-in");
+    }
+
+    #[test]
+    fn check_below_checks_ancestors_but_not_the_scope_root() {
+        let (_hq, engine) = engine_with(&[]);
+        let matched = engine.check_below("docs", "docs/node_modules/a.md", false);
+        assert_eq!(matched.unwrap().rule, "node_modules");
+        assert!(engine.check_below("dist", "dist/a.md", false).is_none());
+        assert!(engine
+            .check_below("dist", "dist/.hidden/a.md", false)
+            .is_some());
+    }
+
+    #[test]
+    fn invalidate_rereads_gitignore() {
+        let (hq, engine) = engine_with(&[]);
+        assert!(engine.check("docs/draft.md", false).is_none());
+        fs::create_dir(hq.path().join("docs")).unwrap();
+        fs::write(hq.path().join("docs/.gitignore"), "draft.md\n").unwrap();
+        assert!(engine.check("docs/draft.md", false).is_none());
+        engine.invalidate("docs");
+        assert!(engine.check("docs/draft.md", false).is_some());
+    }
+}
@@ -1,43 +1,54 @@
-use notify_debouncer_mini::{new_debouncer, DebouncedEventKind};
+mod change_batch;
+mod content_hashes;
+mod diagnostics;
+mod formats;
+mod front_matter;
+mod ignore_rules;
+mod qmd_index;
+mod qmd_install;
+mod result_paths;
+mod scan_cache;
+mod scopes;
+mod search_backend;
+mod search_index;
+mod tree_patch;
+mod watch_backend;
+mod watch_plan;
+
+use change_batch::ChangeBatch;
+use content_hashes::ContentHashes;
+use diagnostics::{ScanDiagnostics, ScopeTiming, SkipLog, SkipReason};
+use formats::DocFormat;
+use ignore_rules::{ExcludedPath, IgnoreEngine};
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify_debouncer_full::{DebounceEventResult, DebouncedEvent};
+use qmd_index::{IndexError, IndexTask, QmdIndexer, QmdProgressEvent, AUTO_INDEX_DELAY};
+use qmd_install::{QmdInfo, QmdLocator};
+use rayon::prelude::*;
+use result_paths::{ResultPaths, ScopeDir};
+use scan_cache::{ScanCache, ScanContext};
+use scopes::{ScopePatternError, ScopeSet};
+use search_backend::{
+    BuiltinSearch, Collection, LineSink, QmdCli, SearchBackend, SearchErrorKind, SearchStatus,
+};
+use search_index::{IndexedDoc, SearchIndex};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::Mutex;
-use std::time::{Duration, SystemTime};
-
-/// Resolve the full path to the `qmd` binary.
-/// Tauri .app bundles don't inherit the user's shell PATH, so we check common locations.
-fn resolve_qmd_path() -> Option<PathBuf> {
-    // 1. Try bare "qmd" in case PATH is set (e.g. dev mode)
-    if let Ok(output) = Command::new("qmd").arg("--version").output() {
-        if output.status.success() {
-            return Some(PathBuf::from("qmd"));
-        }
-    }
-    // 2. Check well-known install locations
-    let home = std::env::var("HOME").unwrap_or_default();
-    let candidates = [
-        format!("{}/.bun/bin/qmd", home),
-        format!("{}/bin/qmd", home),
-        format!("{}/.local/bin/qmd", home),
-        "/usr/local/bin/qmd".to_string(),
-        "/opt/homebrew/bin/qmd".to_string(),
-    ];
-    for path in &candidates {
-        let p = PathBuf::from(path);
-        if p.exists() {
-            return Some(p);
-        }
-    }
-    None
-}
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tree_patch::TreePatch;
+use watch_backend::{EventHandler, WatchMode, WatchOptions, Watchers};
+use watch_plan::WatchPlan;
+
 use tauri::menu::{MenuBuilder, MenuItemBuilder, PredefinedMenuItem, SubmenuBuilder};
-use tauri::{Emitter, Manager, State};
+use tauri::{Emitter, Manager, State, WebviewUrl, WebviewWindowBuilder};
 
 /// A node in the file tree returned by the scanner.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileTreeNode {
     /// Display name (filename or directory name)
@@ -46,65 +57,309 @@ pub struct FileTreeNode {
     pub path: String,
     /// Whether this node is a directory
     pub is_directory: bool,
-    /// Title extracted from the first `# ` heading in .md files
+    /// Title from front matter, else the format's own heading (e.g. the first `# ` in markdown)
     pub title: Option<String>,
     /// Child nodes (populated for directories)
     pub children: Vec<FileTreeNode>,
     /// Depth in the tree (0 = root scope directory)
     pub depth: u32,
-    /// Number of .md files in this subtree (for directories)
+    /// Number of documents in this subtree (for directories)
     pub file_count: u32,
     /// Last modified timestamp (seconds since epoch)
     pub modified: Option<u64>,
+    /// Full YAML/TOML front matter block as JSON (files only)
+    pub front_matter: Option<serde_json::Value>,
+    /// Front matter `tags`
+    pub tags: Vec<String>,
+    /// Front matter `description`
+    pub description: Option<String>,
+    /// Front matter `order`, for manual sidebar ordering
+    pub order: Option<f64>,
+    /// Front matter `draft`
+    pub draft: bool,
+    /// Set on symlinked directories that are listed but not expanded:
+    /// "cycle" (resolves to an ancestor) or "alias" (content shown elsewhere)
+    pub link_kind: Option<String>,
+    /// For "cycle"/"alias" nodes, the path of the node that shows the content
+    pub link_target: Option<String>,
+    /// Document format, so the frontend can pick a renderer (files only)
+    pub format: Option<DocFormat>,
+    /// Set on directories whose subtree was cut short by a depth, entry or
+    /// file limit; `children` and `file_count` are then incomplete
+    pub truncated: bool,
+    /// Number of direct children (for directories), kept when `children` is
+    /// left out by `list_directory`
+    pub child_count: u32,
+    /// Set on directories returned by `list_directory` below the requested
+    /// depth: `children` is empty, and `file_count` and `child_count` come
+    /// from the last scan (or only count the directory's own entries)
+    pub collapsed: bool,
+}
+
+/// A non-fatal problem with a single file found during a scan.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanWarning {
+    /// Absolute path of the file
+    pub path: String,
+    /// Human-readable description of the problem
+    pub message: String,
 }
 
-/// Payload emitted to the frontend when a file-system change is detected.
+/// A single file-system change, as part of an `FsChangeBatch`.
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FsChangeEvent {
     /// Absolute path that changed
     pub path: String,
-    /// "modify" | "create" | "remove" — indicates type of change
+    /// "modify" | "create" | "remove" | "rename" — indicates type of change
     pub kind: String,
+    /// For "rename", the path before the rename (`path` is the new one)
+    pub from: Option<String>,
+    /// Where `path` is on disk, if it is inside a symlinked scope directory
+    pub real_path: Option<String>,
+    /// Where `from` is on disk, if it is inside a symlinked scope directory
+    pub real_from: Option<String>,
+    /// For documents (not on "remove"): hex content hash after the change
+    pub hash: Option<String>,
+    /// For documents (not on "remove"): title after the change
+    pub title: Option<String>,
+    /// For documents (not on "remove"): modified time in milliseconds since epoch
+    pub mtime: Option<u64>,
+}
+
+/// Payload of `"fs-change"` events: the changes of one debounce window,
+/// merged to one per path. Documents that were touched without their content
+/// changing are left out.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FsChangeBatch {
+    pub changes: Vec<FsChangeEvent>,
+    /// Set when the batch is large enough (e.g. a `git pull` or branch switch)
+    /// that a full rescan beats applying the changes one by one. No
+    /// `"tree-patch"` event is sent for such a batch.
+    pub rescan_recommended: bool,
 }
 
-/// Directories to exclude from file watching (same as scan exclusions).
-const WATCH_EXCLUDES: &[&str] = &[
-    "node_modules",
-    ".git",
-    "dist",
-    ".next",
-    ".turbo",
-    ".vercel",
-    "target",
-    ".DS_Store",
-    "thumbs.db",
-];
-
-/// Shared state that holds the active file-watcher handle.
-/// When the handle is dropped the watcher thread is stopped.
+/// How long after the watcher first changes a built-in search index it is
+/// saved. Changes made meanwhile are saved along with it.
+const INDEX_SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// Batches touching more paths than this recommend a full rescan.
+const RESCAN_THRESHOLD: usize = 100;
+
+/// Result of `start_watching`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchStartResult {
+    /// Scope patterns that failed to parse (the rest are still watched)
+    pub pattern_errors: Vec<ScopePatternError>,
+    /// Directories that could not be watched (the rest are still watched)
+    pub watch_errors: Vec<WatcherError>,
+}
+
+/// A directory that could not be watched, or an error reported by the
+/// watcher while running. Payload of `"watcher-error"` events.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherError {
+    /// Path the error concerns, if known
+    pub path: Option<String>,
+    /// Human-readable description of the problem
+    pub message: String,
+}
+
+/// A directory being watched, as it is on disk.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedDir {
+    pub path: String,
+    /// Whether subdirectories are watched too
+    pub recursive: bool,
+    /// Why the directory is polled rather than watched with OS events
+    /// (null if it isn't)
+    pub poll_reason: Option<String>,
+}
+
+/// Health of the file watcher. Returned by `watcher_status` and sent as
+/// `"watcher-status"` events when the watcher starts or stops, its watched
+/// directories change, or it reports an error. Counts are since the last
+/// `start_watching`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherStatus {
+    pub watching: bool,
+    pub hq_path: Option<String>,
+    pub mode: WatchMode,
+    pub watched: Vec<WatchedDir>,
+    /// Directories that could not be watched
+    pub failed: Vec<WatcherError>,
+    /// Debounced file-system events received
+    pub event_count: u64,
+    /// `"fs-change"` batches emitted
+    pub batch_count: u64,
+    /// Watch failures and runtime errors
+    pub error_count: u64,
+    pub last_error: Option<WatcherError>,
+}
+
+impl WatcherStatus {
+    fn record_error(&mut self, error: WatcherError) {
+        self.error_count += 1;
+        self.last_error = Some(error);
+    }
+}
+
+/// The file watcher of one window. When its handles are dropped the watcher
+/// threads are stopped.
+#[derive(Default)]
 struct WatcherState {
-    /// We only need to keep the debouncer alive; dropping it stops the watcher.
-    _debouncer: Option<notify_debouncer_mini::Debouncer<notify::RecommendedWatcher>>,
-}
-
-/// Extract the title from a markdown file by reading the first `# ` heading.
-/// Only reads first 50 lines for performance.
-fn extract_md_title(path: &Path) -> Option<String> {
-    let file = fs::File::open(path).ok()?;
-    let reader = BufReader::new(file);
-    for line in reader.lines().take(50) {
-        if let Ok(line) = line {
-            let trimmed = line.trim();
-            if let Some(title) = trimmed.strip_prefix("# ") {
-                let title = title.trim();
-                if !title.is_empty() {
-                    return Some(title.to_string());
-                }
-            }
-        }
+    /// Dropping the watchers stops them; their own callback also uses them
+    /// to watch scope directories that appear later.
+    watchers: Option<Watchers>,
+    /// Bumped by each `start_watching` and `stop_watching`, so a replaced
+    /// watcher's callback leaves the new watchers and status alone
+    generation: u64,
+    status: WatcherStatus,
+}
+
+/// Each window's file watcher, keyed by window label. Entries are removed
+/// when their window is destroyed.
+type WatcherStates = Mutex<HashMap<String, WatcherState>>;
+
+/// The tree from each window's last completed scan, keyed by window label.
+/// The window's watcher keeps it up to date and reports changes to it as
+/// `"tree-patch"` events.
+type TreeStates = Mutex<HashMap<String, ScannedTree>>;
+
+/// The built-in search index of each HQ in use, keyed by HQ path and shared
+/// by the windows connected to it. Only built when qmd isn't installed.
+type SearchIndexes = Arc<Mutex<HashMap<String, SearchIndex>>>;
+
+/// The search each window has running, keyed by window label: its request
+/// ID and the flag that cancels it.
+type SearchesInFlight = Mutex<HashMap<String, (u64, Arc<AtomicBool>)>>;
+
+/// Root directory of each qmd collection that reports one, used to locate
+/// search results. None until collections are first listed; cleared when
+/// the qmd binary changes.
+type CollectionRoots = Mutex<Option<HashMap<String, PathBuf>>>;
+
+/// A scan result along with what is needed to rescan parts of it.
+struct ScannedTree {
+    hq: PathBuf,
+    options: ScanOptions,
+    scope_set: ScopeSet,
+    ignore: IgnoreEngine,
+    scope_roots: Vec<ScopeRoot>,
+    /// Root nodes, each with the scope pattern it was matched by
+    roots: Vec<(FileTreeNode, String)>,
+}
+
+impl ScannedTree {
+    /// The scope directories shown as roots, with the pattern of each.
+    fn scope_dirs(&self) -> Vec<ScopeDir> {
+        self.roots
+            .iter()
+            .filter_map(|(node, pattern)| {
+                let root = self
+                    .scope_roots
+                    .iter()
+                    .find(|root| root.path.to_string_lossy() == node.path)?;
+                Some(ScopeDir {
+                    path: root.path.clone(),
+                    canonical: root.canonical.clone(),
+                    pattern: pattern.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Background scans that can still be cancelled, keyed by scan ID.
+#[derive(Default)]
+struct ScanState {
+    /// Counter for generating scan IDs
+    next_id: u64,
+    /// Label of the window that started each running scan, and its
+    /// cancellation flag
+    active: HashMap<String, (String, Arc<AtomicBool>)>,
+}
+
+/// Payload of `"scan-progress"` events, emitted while a background scan runs.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgressEvent {
+    pub scan_id: String,
+    /// Directories listed so far
+    pub dirs_visited: u32,
+    /// Documents found so far
+    pub files_found: u32,
+    /// Scope root of the most recently visited directory
+    pub current_scope: String,
+}
+
+/// Payload of `"scan-root"` events, emitted as each scope root finishes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanRootEvent {
+    pub scan_id: String,
+    /// Position of this root among the expanded scope directories
+    pub index: usize,
+    pub root: FileTreeNode,
+}
+
+/// Payload of the `"scan-finished"` event that ends every background scan.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanFinishedEvent {
+    pub scan_id: String,
+    /// The full result (null if the scan failed or was cancelled)
+    pub result: Option<ScanResult>,
+    /// Why the scan failed (null on success or cancellation)
+    pub error: Option<String>,
+    /// Whether the scan was stopped by `cancel_scan`
+    pub cancelled: bool,
+}
+
+/// Minimum time between two progress reports of one scan.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Receives updates from a running scan.
+trait ScanReporter: Sync {
+    /// Called periodically with running totals.
+    fn progress(&self, dirs_visited: u32, files_found: u32, current_scope: &str);
+    /// Called as each scope root finishes, with its index among the scope roots.
+    fn root_done(&self, index: usize, root: &FileTreeNode);
+}
+
+/// Forwards scan updates to the window that started the scan, as events
+/// tagged with the scan ID.
+struct EventReporter {
+    app: tauri::AppHandle,
+    label: String,
+    scan_id: String,
+}
+
+impl ScanReporter for EventReporter {
+    fn progress(&self, dirs_visited: u32, files_found: u32, current_scope: &str) {
+        let payload = ScanProgressEvent {
+            scan_id: self.scan_id.clone(),
+            dirs_visited,
+            files_found,
+            current_scope: current_scope.to_string(),
+        };
+        let _ = self.app.emit_to(&self.label, "scan-progress", payload);
+    }
+
+    fn root_done(&self, index: usize, root: &FileTreeNode) {
+        let payload = ScanRootEvent {
+            scan_id: self.scan_id.clone(),
+            index,
+            root: root.clone(),
+        };
+        let _ = self.app.emit_to(&self.label, "scan-root", payload);
     }
-    None
 }
 
 /// Get modified time as seconds since epoch.
@@ -116,93 +371,348 @@ fn get_modified_secs(path: &Path) -> Option<u64> {
         .map(|d| d.as_secs())
 }
 
-/// Check if a path should be excluded from scanning.
-fn should_exclude(name: &str) -> bool {
-    matches!(
-        name,
-        "node_modules"
-            | ".git"
-            | "dist"
-            | ".next"
-            | ".turbo"
-            | ".vercel"
-            | "target"
-            | ".DS_Store"
-            | "thumbs.db"
-    ) || name.starts_with('.')
+/// Shared state for one `scan_hq_directory` run, borrowed by every worker.
+struct ScanRun<'a> {
+    /// Parsed scopes, consulted for `!` exclusions inside scope roots
+    scopes: &'a ScopeSet,
+    /// Persistent scan cache for this HQ root
+    cache: &'a ScanContext,
+    /// Built-in, `.gitignore` and `.hqdocsignore` rules
+    ignore: &'a IgnoreEngine,
+    /// Excluded paths and the rule that hid them (only when the caller asked)
+    exclusions: Option<Mutex<Vec<ExcludedPath>>>,
+    /// Per-file problems, such as malformed front matter
+    warnings: Mutex<Vec<ScanWarning>>,
+    /// Entries left out of the tree and why
+    skipped: Mutex<SkipLog>,
+    /// Scope roots being expanded; symlinks resolving into these are aliases
+    roots: &'a [ScopeRoot],
+    /// Set by `cancel_scan`; once true, workers stop descending
+    cancel: Option<&'a AtomicBool>,
+    /// Progress listener (background scans only)
+    reporter: Option<&'a dyn ScanReporter>,
+    /// Where a `list_directory` walk stops descending (None for scans)
+    collapse: Option<Collapse<'a>>,
+    /// Running totals for progress reports
+    dirs_visited: AtomicU32,
+    files_found: AtomicU32,
+    /// When the last progress report went out
+    last_report: Mutex<Instant>,
 }
 
-/// Recursively scan a directory and build a file tree.
-/// Follows symlinks transparently. Only includes .md files and directories
-/// that contain .md files (directly or in subdirectories).
-fn scan_dir_recursive(path: &Path, depth: u32, max_depth: u32) -> Option<FileTreeNode> {
-    if depth > max_depth {
-        return None;
+impl<'a> ScanRun<'a> {
+    fn new(
+        scopes: &'a ScopeSet,
+        cache: &'a ScanContext,
+        ignore: &'a IgnoreEngine,
+        roots: &'a [ScopeRoot],
+        options: &ScanOptions,
+        cancel: Option<&'a AtomicBool>,
+        reporter: Option<&'a dyn ScanReporter>,
+    ) -> Self {
+        ScanRun {
+            scopes,
+            cache,
+            ignore,
+            exclusions: options.explain_exclusions.then(|| Mutex::new(Vec::new())),
+            warnings: Mutex::new(Vec::new()),
+            skipped: Mutex::new(SkipLog::default()),
+            roots,
+            cancel,
+            reporter,
+            collapse: None,
+            dirs_visited: AtomicU32::new(0),
+            files_found: AtomicU32::new(0),
+            last_report: Mutex::new(Instant::now()),
+        }
     }
 
-    // Resolve symlinks to canonical path for reading
-    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
-
-    let entries = match fs::read_dir(&canonical) {
-        Ok(entries) => entries,
-        Err(_) => return None,
-    };
+    /// Consume the run, returning the exclusions and warnings it collected,
+    /// each sorted by path, and the log of skipped entries.
+    fn into_reports(self) -> (Option<Vec<ExcludedPath>>, Vec<ScanWarning>, SkipLog) {
+        let exclusions = self.exclusions.map(|e| {
+            let mut exclusions = e.into_inner().unwrap_or_else(|e| e.into_inner());
+            exclusions.sort_by(|a, b| a.path.cmp(&b.path));
+            exclusions
+        });
+        let mut warnings = self.warnings.into_inner().unwrap_or_else(|e| e.into_inner());
+        // Workers finish in any order; keep the report stable between scans
+        warnings.sort_by(|a, b| a.path.cmp(&b.path));
+        let skipped = self.skipped.into_inner().unwrap_or_else(|e| e.into_inner());
+        (exclusions, warnings, skipped)
+    }
 
-    let mut children: Vec<FileTreeNode> = Vec::new();
-    let mut file_count: u32 = 0;
+    /// Record an entry that was left out of the tree.
+    fn skip(&self, path: String, reason: SkipReason, detail: Option<String>) {
+        if let Ok(mut skipped) = self.skipped.lock() {
+            skipped.record(path, reason, detail);
+        }
+    }
 
-    let mut entries_vec: Vec<_> = entries
-        .filter_map(|e| e.ok())
-        .collect();
+    fn is_cancelled(&self) -> bool {
+        self.cancel.is_some_and(|c| c.load(Ordering::Relaxed))
+    }
 
-    // Sort entries: directories first, then alphabetically
-    entries_vec.sort_by(|a, b| {
-        let a_is_dir = a.file_type().map(|t| t.is_dir()).unwrap_or(false);
-        let b_is_dir = b.file_type().map(|t| t.is_dir()).unwrap_or(false);
-        match (a_is_dir, b_is_dir) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.file_name().cmp(&b.file_name()),
+    /// Count a listed directory and report progress if enough time has passed.
+    fn visit_dir(&self, frame: &DirFrame) {
+        let dirs_visited = self.dirs_visited.fetch_add(1, Ordering::Relaxed) + 1;
+        let reporter = match self.reporter {
+            Some(reporter) => reporter,
+            None => return,
+        };
+        // try_lock: a worker that loses the race just skips this report
+        if let Ok(mut last) = self.last_report.try_lock() {
+            if last.elapsed() >= PROGRESS_INTERVAL {
+                *last = Instant::now();
+                let files_found = self.files_found.load(Ordering::Relaxed);
+                reporter.progress(dirs_visited, files_found, frame.scope_path());
+            }
         }
-    });
+    }
+}
 
-    for entry in entries_vec {
-        let name = entry.file_name().to_string_lossy().to_string();
+/// Default for `ScanOptions::max_depth`.
+const DEFAULT_MAX_DEPTH: u32 = 15;
+
+/// Traversal limits for one scope root, resolved from `ScanOptions`.
+struct ScopeBudget {
+    /// Deepest directory level scanned below the scope root
+    max_depth: u32,
+    /// Entries listed per directory (directories first, then alphabetical)
+    max_entries_per_dir: Option<usize>,
+    /// Cap on documents in this scope, applied by `trim_to_file_limit`
+    max_files: Option<u32>,
+}
+
+impl ScopeBudget {
+    /// Cap on documents for a scan of one subtree of the scope: the lower
+    /// of the scope and total file limits.
+    fn file_limit(&self, options: &ScanOptions) -> Option<u32> {
+        self.max_files
+            .into_iter()
+            .chain(options.max_total_files)
+            .min()
+    }
+}
 
-        if should_exclude(&name) {
+/// Keep the first `*remaining` documents below the directory `node` in
+/// listing order (directories first, then by name), counting them off
+/// `remaining`, and drop the rest. Directories that lose documents are
+/// marked `truncated` and reported; ones left without documents are dropped
+/// unless truncated. Applied after the parallel walk, so the documents kept
+/// don't depend on worker timing.
+fn trim_to_file_limit(node: &mut FileTreeNode, remaining: &mut u32, run: &ScanRun) {
+    let mut dropped = false;
+    node.file_count = 0;
+    for mut child in std::mem::take(&mut node.children) {
+        if child.collapsed {
+            // Only counted: its documents aren't in the tree
+            *remaining = remaining.saturating_sub(child.file_count);
+            node.file_count += child.file_count;
+        } else if child.is_directory {
+            // Cycle and alias nodes hold no documents of their own
+            if child.link_kind.is_none() {
+                trim_to_file_limit(&mut child, remaining, run);
+                if child.file_count == 0 && !child.truncated {
+                    continue;
+                }
+            }
+            node.file_count += child.file_count;
+            node.truncated |= child.truncated;
+        } else if *remaining > 0 {
+            *remaining -= 1;
+            node.file_count += 1;
+        } else {
+            dropped = true;
             continue;
         }
+        node.children.push(child);
+    }
+    node.child_count = node.children.len() as u32;
 
-        let entry_path = entry.path();
+    if dropped {
+        node.truncated = true;
+        let detail = Some("documents over the file limit were left out".to_string());
+        run.skip(node.path.clone(), SkipReason::FileLimit, detail);
+    }
+}
 
-        // Follow symlinks: check the target type
-        let metadata = match fs::metadata(&entry_path) {
-            Ok(m) => m,
-            Err(_) => continue, // Broken symlink or permission denied
-        };
+/// A directory on the path currently being scanned. Frames are chained
+/// innermost-first so each worker can see its own ancestors.
+struct DirFrame<'a> {
+    /// Resolved directory the entries are read from
+    canonical: &'a Path,
+    /// HQ-relative path as seen through the scope
+    rel: &'a str,
+    /// Node path shown in the tree
+    path: &'a str,
+    parent: Option<&'a DirFrame<'a>>,
+    /// Set when a limit left some of this directory's entries out
+    truncated: AtomicBool,
+}
 
-        if metadata.is_dir() {
-            if let Some(child) = scan_dir_recursive(&entry_path, depth + 1, max_depth) {
-                if child.file_count > 0 {
-                    file_count += child.file_count;
-                    children.push(child);
-                }
+impl DirFrame<'_> {
+    /// Node path of the ancestor (or self) that resolves to `canonical`, if any.
+    fn find_ancestor(&self, canonical: &Path) -> Option<&str> {
+        let mut frame = Some(self);
+        while let Some(f) = frame {
+            if f.canonical == canonical {
+                return Some(f.path);
             }
-        } else if metadata.is_file() && name.ends_with(".md") {
-            let title = extract_md_title(&entry_path);
-            let modified = get_modified_secs(&entry_path);
+            frame = f.parent;
+        }
+        None
+    }
+
+    /// Node path of the scope root this frame descends from.
+    fn scope_path(&self) -> &str {
+        let mut frame = self;
+        while let Some(parent) = frame.parent {
+            frame = parent;
+        }
+        frame.path
+    }
+}
+
+/// Where a `list_directory` walk stops descending.
+struct Collapse<'a> {
+    /// Directories at this depth or deeper are sent collapsed
+    depth: u32,
+    /// Collapsed copies of the window's scanned directories at `depth`, by path
+    known: &'a HashMap<String, FileTreeNode>,
+}
+
+/// A concrete scope directory and the directory it resolves to.
+struct ScopeRoot {
+    path: PathBuf,
+    canonical: PathBuf,
+}
+
+impl ScopeRoot {
+    /// Node path at which `canonical` (inside this root) is shown.
+    fn node_path_for(&self, canonical: &Path) -> Option<String> {
+        if canonical == self.canonical {
+            Some(self.path.to_string_lossy().to_string())
+        } else if canonical.starts_with(&self.canonical) {
+            // Entries below a root are listed under their resolved path
+            Some(canonical.to_string_lossy().to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Build a directory node that is shown but not expanded, because its
+/// content already appears at `target` ("cycle" or "alias").
+fn link_node(path: &Path, depth: u32, kind: &str, target: String) -> FileTreeNode {
+    FileTreeNode {
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string()),
+        path: path.to_string_lossy().to_string(),
+        is_directory: true,
+        depth,
+        modified: get_modified_secs(path),
+        link_kind: Some(kind.to_string()),
+        link_target: Some(target),
+        ..Default::default()
+    }
+}
+
+/// Recursively scan a directory and build a file tree.
+/// Follows symlinks, except those that loop back to an ancestor or into a scope root.
+/// Only includes documents in a registered format (see `formats::FORMATS`)
+/// and directories that contain them (directly or in subdirectories).
+/// Directory listings and titles are reused from the scan cache when unchanged.
+///
+/// Entries of a directory are scanned in parallel on the current rayon pool
+/// (subdirectories and title extraction alike); results are collected back in
+/// listing order, so the output is identical to a sequential walk.
+///
+/// `rel` is the HQ-relative path of `path` as seen through the scope (before
+/// resolving symlinks), used to match `!` scope exclusions and ignore rules.
+/// `parent` is the chain of directories above `path`, used to break symlink cycles.
+///
+/// Entries beyond the scope's depth and entry limits are left out and the
+/// directories they were left out of (and their ancestors) are marked
+/// `truncated`. File limits are applied to the result by `trim_to_file_limit`.
+/// A `list_directory` walk stops at `run.collapse` (see `collapsed_dir`).
+fn scan_dir_recursive(
+    path: &Path,
+    rel: &str,
+    depth: u32,
+    budget: &ScopeBudget,
+    run: &ScanRun,
+    parent: Option<&DirFrame>,
+) -> Option<FileTreeNode> {
+    if run.is_cancelled() {
+        return None;
+    }
+    if depth > budget.max_depth {
+        if let Some(parent) = parent {
+            parent.truncated.store(true, Ordering::Relaxed);
+        }
+        let detail = format!("deeper than {} levels below the scope", budget.max_depth);
+        run.skip(path.to_string_lossy().to_string(), SkipReason::DepthLimit, Some(detail));
+        return None;
+    }
+
+    // Resolve symlinks to canonical path for reading
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if let Some(collapse) = run.collapse.as_ref().filter(|c| depth >= c.depth) {
+        return collapsed_dir(path, &canonical, rel, depth, collapse, run);
+    }
+    let node_path = path.to_string_lossy().to_string();
+    let frame = DirFrame {
+        canonical: &canonical,
+        rel,
+        path: &node_path,
+        parent,
+        truncated: AtomicBool::new(false),
+    };
+
+    // Entry names come back sorted: directories first, then alphabetically
+    let listing = match run.cache.list_dir(&canonical) {
+        Ok(listing) => listing,
+        Err(e) => {
+            let reason = diagnostics::reason_for_error(path, &e);
+            run.skip(node_path, reason, Some(e.to_string()));
+            return None;
+        }
+    };
+    run.visit_dir(&frame);
+
+    for name in listing.non_utf8 {
+        let entry_path = format!("{}/{}", node_path, name);
+        run.skip(entry_path, SkipReason::NonUtf8Name, None);
+    }
+
+    let mut entries = listing.entries;
+    if let Some(max) = budget.max_entries_per_dir.filter(|max| entries.len() > *max) {
+        let detail = format!("listed {} of {} entries", max, entries.len());
+        entries.truncate(max);
+        frame.truncated.store(true, Ordering::Relaxed);
+        run.skip(node_path.clone(), SkipReason::EntryLimit, Some(detail));
+    }
+
+    let scanned: Vec<Option<FileTreeNode>> = entries
+        .into_par_iter()
+        .map(|name| scan_entry(&frame, name, depth, budget, run))
+        .collect();
+
+    let mut children: Vec<FileTreeNode> = Vec::new();
+    let mut file_count: u32 = 0;
+    let mut truncated = frame.truncated.load(Ordering::Relaxed);
+
+    for child in scanned.into_iter().flatten() {
+        if child.is_directory {
+            file_count += child.file_count;
+            truncated |= child.truncated;
+        } else {
             file_count += 1;
-            children.push(FileTreeNode {
-                name,
-                path: entry_path.to_string_lossy().to_string(),
-                is_directory: false,
-                title,
-                children: Vec::new(),
-                depth: depth + 1,
-                file_count: 0,
-                modified,
-            });
         }
+        children.push(child);
     }
 
     let dir_name = path
@@ -212,181 +722,1645 @@ fn scan_dir_recursive(path: &Path, depth: u32, max_depth: u32) -> Option<FileTre
 
     Some(FileTreeNode {
         name: dir_name,
-        path: path.to_string_lossy().to_string(),
+        path: node_path,
         is_directory: true,
         title: None,
+        child_count: children.len() as u32,
         children,
         depth,
         file_count,
         modified: get_modified_secs(path),
+        truncated,
+        ..Default::default()
     })
 }
 
-/// Tauri command: scan the HQ directory for .md files within the given scoped paths.
+/// A directory at the depth where a `list_directory` walk stops, without its
+/// children. Its counts come from the window's last scan; a directory that
+/// scan doesn't have is counted from its own listing, so its `file_count`
+/// only covers the documents directly in it. None if it has no entries to show.
+fn collapsed_dir(
+    path: &Path,
+    canonical: &Path,
+    rel: &str,
+    depth: u32,
+    collapse: &Collapse,
+    run: &ScanRun,
+) -> Option<FileTreeNode> {
+    let node_path = path.to_string_lossy().to_string();
+    if let Some(known) = collapse.known.get(&node_path) {
+        return Some(known.clone());
+    }
+
+    let listing = run.cache.list_dir(canonical).ok()?;
+    let mut child_count = 0;
+    let mut file_count = 0;
+    for name in listing.entries {
+        let entry_rel = if rel.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", rel, name)
+        };
+        let entry_path = canonical.join(&name);
+        if run.scopes.is_excluded(&entry_rel)
+            || run.ignore.check_entry(&entry_rel, &entry_path).is_some()
+        {
+            continue;
+        }
+        if entry_path.is_dir() {
+            child_count += 1;
+        } else if formats::for_file_name(&name).is_some() && entry_path.is_file() {
+            child_count += 1;
+            file_count += 1;
+        }
+    }
+    (child_count > 0).then(|| FileTreeNode {
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| node_path.clone()),
+        path: node_path,
+        is_directory: true,
+        depth,
+        file_count,
+        modified: get_modified_secs(path),
+        child_count,
+        collapsed: true,
+        ..Default::default()
+    })
+}
+
+/// Scan a single entry of the directory `dir` (at `depth`).
+/// Returns None for ignored or excluded entries, files in unsupported
+/// formats, and directories without any documents (unless truncated, as
+/// they may hold more).
+///
+/// A symlinked directory that resolves to one of its own ancestors is
+/// returned as a "cycle" node, and one that resolves into a scope root
+/// (where its content is already shown) as an "alias" node.
+fn scan_entry(
+    dir: &DirFrame,
+    name: String,
+    depth: u32,
+    budget: &ScopeBudget,
+    run: &ScanRun,
+) -> Option<FileTreeNode> {
+    if run.is_cancelled() {
+        return None;
+    }
+
+    let rel = if dir.rel.is_empty() {
+        name.clone()
+    } else {
+        format!("{}/{}", dir.rel, name)
+    };
+    let entry_path = dir.canonical.join(&name);
+    if run.scopes.is_excluded(&rel) {
+        let detail = Some("excluded by a `!` scope pattern".to_string());
+        run.skip(entry_path.to_string_lossy().to_string(), SkipReason::ExcludedName, detail);
+        return None;
+    }
+
+    if let Some(matched) = run.ignore.check_entry(&rel, &entry_path) {
+        let detail = format!("{} ({})", matched.rule, matched.source);
+        run.skip(entry_path.to_string_lossy().to_string(), SkipReason::ExcludedName, Some(detail));
+        if let Some(exclusions) = &run.exclusions {
+            if let Ok(mut exclusions) = exclusions.lock() {
+                exclusions.push(ExcludedPath {
+                    path: entry_path.to_string_lossy().to_string(),
+                    matched,
+                });
+            }
+        }
+        return None;
+    }
+
+    // Follow symlinks: check the target type.
+    // Broken symlinks and permission errors are skipped (and reported).
+    let metadata = match fs::metadata(&entry_path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            let reason = diagnostics::reason_for_error(&entry_path, &e);
+            // An entry deleted since the listing was read is simply gone
+            if reason != SkipReason::Unreadable || e.kind() != std::io::ErrorKind::NotFound {
+                run.skip(entry_path.to_string_lossy().to_string(), reason, Some(e.to_string()));
+            }
+            return None;
+        }
+    };
+
+    if metadata.is_dir() {
+        let is_symlink = fs::symlink_metadata(&entry_path)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        if is_symlink {
+            if let Ok(target) = fs::canonicalize(&entry_path) {
+                if let Some(ancestor) = dir.find_ancestor(&target) {
+                    return Some(link_node(&entry_path, depth + 1, "cycle", ancestor.to_string()));
+                }
+                if let Some(shown_at) = run.roots.iter().find_map(|r| r.node_path_for(&target)) {
+                    return Some(link_node(&entry_path, depth + 1, "alias", shown_at));
+                }
+            }
+        }
+
+        scan_dir_recursive(&entry_path, &rel, depth + 1, budget, run, Some(dir))
+            .filter(|child| child.file_count > 0 || child.truncated || child.collapsed)
+    } else if let Some(spec) = formats::for_file_name(&name).filter(|_| metadata.is_file()) {
+        run.files_found.fetch_add(1, Ordering::Relaxed);
+        let info = run.cache.file_info(&entry_path, &metadata, spec.read_info);
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        let path = entry_path.to_string_lossy().to_string();
+
+        if let Some(message) = info.warning {
+            if let Ok(mut warnings) = run.warnings.lock() {
+                warnings.push(ScanWarning {
+                    path: path.clone(),
+                    message,
+                });
+            }
+        }
+
+        let fm = info.front_matter.unwrap_or_default();
+        Some(FileTreeNode {
+            name,
+            path,
+            is_directory: false,
+            title: info.title,
+            children: Vec::new(),
+            depth: depth + 1,
+            file_count: 0,
+            modified,
+            front_matter: Some(fm.raw).filter(|raw| !raw.is_null()),
+            tags: fm.tags,
+            description: fm.description,
+            order: fm.order,
+            draft: fm.draft,
+            format: Some(spec.format),
+            ..Default::default()
+        })
+    } else {
+        None
+    }
+}
+
+/// Scan one concrete scope directory (already expanded from a pattern).
+/// A scope root that is missing or has no documents is reported as skipped.
+fn scan_scope_root(
+    hq: &Path,
+    scope_path: &Path,
+    budget: &ScopeBudget,
+    run: &ScanRun,
+) -> Option<FileTreeNode> {
+    let rel = scopes::relative_str(hq, scope_path);
+    let shown = scope_path.to_string_lossy().to_string();
+
+    let node = if !scope_path.is_dir() {
+        // Try following symlink
+        let canonical = match fs::canonicalize(scope_path) {
+            Ok(canonical) => canonical,
+            Err(e) => {
+                let reason = match diagnostics::reason_for_error(scope_path, &e) {
+                    SkipReason::Unreadable => SkipReason::EmptyScope,
+                    reason => reason,
+                };
+                run.skip(shown, reason, Some(e.to_string()));
+                return None;
+            }
+        };
+        if !canonical.is_dir() {
+            run.skip(shown, SkipReason::EmptyScope, Some("not a directory".to_string()));
+            return None;
+        }
+        // Use the canonical path for scanning but keep the original name
+        let mut node = scan_dir_recursive(scope_path, &rel, 0, budget, run, None)?;
+        // Use the relative scope path as the display path
+        node.path = shown.clone();
+        node
+    } else {
+        scan_dir_recursive(scope_path, &rel, 0, budget, run, None)?
+    };
+
+    if node.file_count == 0 && !node.truncated && !run.is_cancelled() {
+        run.skip(shown, SkipReason::EmptyScope, Some("no documents".to_string()));
+        return None;
+    }
+    Some(node)
+}
+
+/// Options for `scan_hq_directory`. All fields are optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanOptions {
+    /// Maximum number of scanner worker threads (default: one per CPU core)
+    pub max_threads: Option<usize>,
+    /// Report every ignored path and the rule that excluded it
+    #[serde(default)]
+    pub explain_exclusions: bool,
+    /// Deepest directory level scanned below each scope root (default: 15)
+    pub max_depth: Option<u32>,
+    /// Entries listed per directory, directories first (default: no limit)
+    pub max_entries_per_dir: Option<usize>,
+    /// Cap on documents across all scopes, kept in scope order (default: no limit)
+    pub max_total_files: Option<u32>,
+    /// Per-scope overrides, keyed by scope pattern as passed in `scopes`
+    #[serde(default)]
+    pub scope_limits: HashMap<String, ScopeLimits>,
+}
+
+/// Traversal limits for the directories matched by one scope pattern.
+/// Unset fields fall back to the call-wide options.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeLimits {
+    pub max_depth: Option<u32>,
+    pub max_entries_per_dir: Option<usize>,
+    /// Cap on documents in each directory the pattern matches
+    pub max_files: Option<u32>,
+}
+
+impl ScanOptions {
+    /// Resolve the limits for a scope root matched by `pattern`.
+    fn budget_for(&self, pattern: &str) -> ScopeBudget {
+        let scope = self.scope_limits.get(pattern).cloned().unwrap_or_default();
+        ScopeBudget {
+            max_depth: scope.max_depth.or(self.max_depth).unwrap_or(DEFAULT_MAX_DEPTH),
+            max_entries_per_dir: scope.max_entries_per_dir.or(self.max_entries_per_dir),
+            max_files: scope.max_files,
+        }
+    }
+}
+
+/// Result of `scan_hq_directory`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanResult {
+    /// One FileTreeNode root per matched scope directory
+    pub roots: Vec<FileTreeNode>,
+    /// Whether a persisted scan cache for this HQ root was found and used
+    pub cache_used: bool,
+    /// Number of files re-read and directories re-listed (not served from cache)
+    pub refreshed: u32,
+    /// Scope patterns that failed to parse (the rest are still scanned)
+    pub pattern_errors: Vec<ScopePatternError>,
+    /// Ignored paths with the rule that excluded each (null unless `explainExclusions` was set)
+    pub exclusions: Option<Vec<ExcludedPath>>,
+    /// Per-file problems (e.g. malformed front matter, or a scan cache that
+    /// couldn't be saved); affected documents are still listed
+    pub warnings: Vec<ScanWarning>,
+    /// Entries left out of the tree (and why) plus per-scope timing
+    pub scan_diagnostics: ScanDiagnostics,
+}
+
+/// Tauri command: scan the HQ directory for documents within the given scoped paths.
 ///
 /// `hq_path`: Absolute path to the HQ root folder.
-/// `scopes`: List of relative scope patterns to scan (e.g., "knowledge/public", "companies/*/knowledge").
-///           Glob `*` in a single path segment expands to all subdirectories at that level.
+/// `scopes`: List of relative scope glob patterns to scan (e.g., "knowledge/public",
+///           "companies/*/knowledge", "**/runbooks", "knowledge/{public,shared}").
+///           Patterns starting with `!` exclude matching directories (e.g. "!companies/personal/**").
+/// `options`: Optional scan settings (e.g. `maxThreads` to cap worker threads).
+///
+/// Paths matched by built-in exclusions, `.gitignore` files along the tree, or
+/// the HQ-level `.hqdocsignore` are skipped.
+///
+/// Unchanged files and directories are served from the on-disk scan cache
+/// (one per HQ root in the app data dir), which is rewritten after each scan.
+/// Scope roots and subdirectories are walked in parallel on a work-stealing pool.
+///
+/// Resolves when the scan is done, without progress events; see `start_scan`
+/// for a background scan that reports progress. The scan itself runs off the
+/// main thread.
 ///
 /// Returns a flat list of FileTreeNode roots, one per matched scope directory.
 #[tauri::command]
-fn scan_hq_directory(hq_path: String, scopes: Vec<String>) -> Result<Vec<FileTreeNode>, String> {
-    let hq = PathBuf::from(&hq_path);
+async fn scan_hq_directory(
+    hq_path: String,
+    scopes: Vec<String>,
+    options: Option<ScanOptions>,
+    app: tauri::AppHandle,
+    window: tauri::Window,
+) -> Result<ScanResult, String> {
+    let label = window.label().to_string();
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        run_scan(&hq_path, &scopes, options, &app, &label, None, None)
+    })
+    .await
+    .map_err(|e| format!("Scan failed: {}", e))?
+}
+
+/// Tauri command: start a scan (same arguments as `scan_hq_directory`) on a
+/// background thread and return its scan ID right away.
+///
+/// While it runs, the scan emits `"scan-progress"` events with running totals
+/// and a `"scan-root"` event for each scope root as it completes. It always
+/// ends with one `"scan-finished"` event carrying the full result, the error,
+/// or `cancelled: true`. All events carry the scan ID and go only to the
+/// window that started the scan.
+#[tauri::command]
+fn start_scan(
+    hq_path: String,
+    scopes: Vec<String>,
+    options: Option<ScanOptions>,
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    state: State<'_, Mutex<ScanState>>,
+) -> Result<String, String> {
+    let label = window.label().to_string();
+    if !Path::new(&hq_path).is_dir() {
+        return Err(format!("HQ path is not a directory: {}", hq_path));
+    }
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let scan_id = {
+        let mut guard = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        guard.next_id += 1;
+        let scan_id = format!("scan-{}", guard.next_id);
+        guard
+            .active
+            .insert(scan_id.clone(), (label.clone(), cancel.clone()));
+        scan_id
+    };
+
+    let thread_scan_id = scan_id.clone();
+    let spawned = std::thread::Builder::new()
+        .name("hq-scan".to_string())
+        .spawn(move || {
+            let scan_id = thread_scan_id;
+            let reporter = EventReporter {
+                app: app.clone(),
+                label: label.clone(),
+                scan_id: scan_id.clone(),
+            };
+            let outcome = run_scan(
+                &hq_path,
+                &scopes,
+                options.unwrap_or_default(),
+                &app,
+                &label,
+                Some(&cancel),
+                Some(&reporter),
+            );
+
+            if let Ok(mut guard) = app.state::<Mutex<ScanState>>().lock() {
+                guard.active.remove(&scan_id);
+            }
+
+            let cancelled = cancel.load(Ordering::Relaxed);
+            let (result, error) = match outcome {
+                Ok(result) => (Some(result), None),
+                Err(_) if cancelled => (None, None),
+                Err(e) => (None, Some(e)),
+            };
+            let payload = ScanFinishedEvent {
+                scan_id,
+                result,
+                error,
+                cancelled,
+            };
+            let _ = app.emit_to(&label, "scan-finished", payload);
+        });
+
+    if let Err(e) = spawned {
+        if let Ok(mut guard) = state.lock() {
+            guard.active.remove(&scan_id);
+        }
+        return Err(format!("Failed to start scan: {}", e));
+    }
+
+    Ok(scan_id)
+}
+
+/// Tauri command: cancel a background scan started with `start_scan`.
+///
+/// Workers stop at the next directory or entry they reach; the scan then ends
+/// with a `"scan-finished"` event with `cancelled: true`, and the scan cache is
+/// left untouched. Returns false if no scan with that ID is running.
+#[tauri::command]
+fn cancel_scan(scan_id: String, state: State<'_, Mutex<ScanState>>) -> Result<bool, String> {
+    let guard = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    match guard.active.get(&scan_id) {
+        Some((_, cancel)) => {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Load the persisted scan cache for `hq_path`. Also returns the cache file
+/// to write back to (None if the app data dir is unavailable).
+fn open_scan_cache(app: &tauri::AppHandle, hq_path: &str) -> (Option<PathBuf>, ScanContext) {
+    let cache_path = app
+        .path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| scan_cache::cache_file_path(&dir, hq_path));
+    let previous = cache_path
+        .as_ref()
+        .and_then(|p| ScanCache::load(p, hq_path));
+    (cache_path, ScanContext::new(hq_path, previous))
+}
+
+/// Build the worker pool for one scan.
+fn scan_pool(max_threads: Option<usize>) -> Result<rayon::ThreadPool, String> {
+    // 0 lets rayon pick one thread per CPU core
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(max_threads.unwrap_or(0))
+        .thread_name(|i| format!("hq-scan-{}", i))
+        .build()
+        .map_err(|e| format!("Failed to start scanner threads: {}", e))
+}
+
+/// Resolve expanded scope directories to the roots that get scanned.
+/// Scope directories that resolve to (or into) an earlier root are aliases
+/// of it instead of being scanned twice; the second list holds, index for
+/// index, the node path each alias points to.
+fn resolve_scope_roots(scope_paths: &[PathBuf]) -> (Vec<ScopeRoot>, Vec<Option<String>>) {
+    let mut roots: Vec<ScopeRoot> = Vec::new();
+    let mut alias_of: Vec<Option<String>> = Vec::new();
+    for path in scope_paths {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        let shown_at = roots.iter().find_map(|r| r.node_path_for(&canonical));
+        if shown_at.is_none() {
+            roots.push(ScopeRoot {
+                path: path.clone(),
+                canonical,
+            });
+        }
+        alias_of.push(shown_at);
+    }
+    (roots, alias_of)
+}
+
+/// Run a full scan, shared by `scan_hq_directory` and `start_scan`, for the
+/// window labelled `label`. Returns an error if `cancel` is set before the
+/// scan completes or the window is closed while it runs.
+fn run_scan(
+    hq_path: &str,
+    scopes: &[String],
+    options: ScanOptions,
+    app: &tauri::AppHandle,
+    label: &str,
+    cancel: Option<&AtomicBool>,
+    reporter: Option<&dyn ScanReporter>,
+) -> Result<ScanResult, String> {
+    let hq = PathBuf::from(hq_path);
+
+    if !hq.is_dir() {
+        return Err(format!("HQ path is not a directory: {}", hq_path));
+    }
+    let started = Instant::now();
+
+    let (cache_path, ctx) = open_scan_cache(app, hq_path);
+    let pool = scan_pool(options.max_threads)?;
+
+    let (scope_set, pattern_errors) = ScopeSet::parse(scopes);
+    let ignore = IgnoreEngine::new(&hq);
+    let expansion = scope_set.expand(&hq, &ignore);
+    let scope_paths = expansion.dirs;
+    let budgets: Vec<ScopeBudget> = expansion
+        .patterns
+        .iter()
+        .map(|pattern| options.budget_for(pattern))
+        .collect();
+
+    let (roots, alias_of) = resolve_scope_roots(&scope_paths);
+    let run = ScanRun::new(&scope_set, &ctx, &ignore, &roots, &options, cancel, reporter);
+
+    for pattern in expansion.empty_patterns {
+        let detail = Some("pattern matched no directories".to_string());
+        run.skip(pattern, SkipReason::EmptyScope, detail);
+    }
+
+    // With a cap on documents across scopes, a root is only final once the
+    // roots before it are trimmed, so its `"scan-root"` event waits for that
+    let stream_roots = options.max_total_files.is_none();
+    let emit_root = |index: usize, node: &FileTreeNode| {
+        if let Some(reporter) = run.reporter.filter(|_| !run.is_cancelled()) {
+            reporter.root_done(index, node);
+        }
+    };
+
+    let scanned: Vec<(Option<FileTreeNode>, Option<ScopeTiming>)> = pool.install(|| {
+        scope_paths
+            .par_iter()
+            .zip(alias_of.into_par_iter())
+            .zip(budgets.par_iter())
+            .enumerate()
+            .map(|(index, ((scope_path, alias_of), budget))| {
+                let (node, timing) = match alias_of {
+                    Some(target) => (Some(link_node(scope_path, 0, "alias", target)), None),
+                    None => {
+                        let scope_started = Instant::now();
+                        let mut node = scan_scope_root(&hq, scope_path, budget, &run);
+                        if let (Some(node), Some(mut max_files)) = (&mut node, budget.max_files) {
+                            trim_to_file_limit(node, &mut max_files, &run);
+                        }
+                        let timing = ScopeTiming {
+                            path: scope_path.to_string_lossy().to_string(),
+                            duration_ms: scope_started.elapsed().as_millis() as u64,
+                            file_count: node.as_ref().map_or(0, |n| n.file_count),
+                        };
+                        (node, Some(timing))
+                    }
+                };
+                if let Some(node) = node.as_ref().filter(|_| stream_roots) {
+                    emit_root(index, node);
+                }
+                (node, timing)
+            })
+            .collect()
+    });
+    let (mut results, mut timings): (Vec<_>, Vec<_>) = scanned.into_iter().unzip();
+
+    if let Some(mut remaining) = options.max_total_files {
+        for (index, (node, timing)) in results.iter_mut().zip(&mut timings).enumerate() {
+            if let (Some(node), Some(timing)) = (node.as_mut(), timing.as_mut()) {
+                trim_to_file_limit(node, &mut remaining, &run);
+                timing.file_count = node.file_count;
+            }
+            if let Some(node) = node {
+                emit_root(index, node);
+            }
+        }
+    }
+    let results: Vec<(FileTreeNode, String)> = results
+        .into_iter()
+        .zip(expansion.patterns)
+        .filter_map(|(node, pattern)| Some((node?, pattern)))
+        .collect();
+
+    // A cancelled scan is incomplete: don't return it or let it into the cache
+    if run.is_cancelled() {
+        return Err("Scan cancelled".to_string());
+    }
+    if let Some(reporter) = reporter {
+        let dirs_visited = run.dirs_visited.load(Ordering::Relaxed);
+        let files_found = run.files_found.load(Ordering::Relaxed);
+        reporter.progress(dirs_visited, files_found, "");
+    }
+
+    let (exclusions, mut warnings, skipped) = run.into_reports();
+    let (skipped, skipped_counts) = skipped.finish();
+    let scan_diagnostics = ScanDiagnostics {
+        skipped,
+        skipped_counts,
+        scopes: timings.into_iter().flatten().collect(),
+        total_ms: started.elapsed().as_millis() as u64,
+    };
+    let cache_used = ctx.cache_used;
+    let refreshed = ctx.refreshed();
+
+    // Persisting the cache is best-effort; a failed write only costs the next scan time
+    if let Some(path) = cache_path {
+        if let Err(message) = ctx.into_cache().save(&path) {
+            warnings.push(ScanWarning {
+                path: path.to_string_lossy().to_string(),
+                message,
+            });
+        }
+    }
+
+    // Keep the tree so the watcher can patch it instead of rescanning
+    let roots_out: Vec<FileTreeNode> = results.iter().map(|(node, _)| node.clone()).collect();
+    let mut documents = Vec::new();
+    for root in &roots_out {
+        collect_documents(root, &mut documents);
+    }
+    let mut window_gone = false;
+    if let Ok(mut trees) = app.state::<TreeStates>().lock() {
+        // Checked under the lock: `forget_window` cancels a closing window's
+        // scans before dropping its tree, so that tree can't be put back
+        window_gone = cancel.is_some_and(|c| c.load(Ordering::Relaxed))
+            || app.get_webview_window(label).is_none();
+        if !window_gone {
+            let tree = ScannedTree {
+                hq,
+                options,
+                scope_set,
+                ignore,
+                scope_roots: roots,
+                roots: results,
+            };
+            trees.insert(label.to_string(), tree);
+        }
+    }
+    if window_gone {
+        return Err("Scan cancelled".to_string());
+    }
+    sync_search_index(app, hq_path, documents);
+
+    Ok(ScanResult {
+        roots: roots_out,
+        cache_used,
+        refreshed,
+        pattern_errors,
+        exclusions,
+        warnings,
+        scan_diagnostics,
+    })
+}
+
+/// Bring the built-in search index of `hq_path` in line with the documents of
+/// a completed scan, on a background thread. Only documents whose mtime or
+/// size changed since they were indexed are re-read. Skipped when qmd is
+/// known to be installed, since searches then go to qmd; a scan doesn't
+/// wait for qmd to be looked up.
+fn sync_search_index(app: &tauri::AppHandle, hq_path: &str, documents: Vec<PathBuf>) {
+    let qmd = app.state::<QmdLocator>().cached();
+    if qmd.is_some_and(|qmd| qmd.usable()) {
+        return;
+    }
+    let task_app = app.clone();
+    let task_hq = hq_path.to_string();
+    let spawned = std::thread::Builder::new()
+        .name("hq-search-index".to_string())
+        .spawn(move || {
+            let (app, hq_path) = (task_app, task_hq);
+            let index_path = app
+                .path()
+                .app_data_dir()
+                .ok()
+                .map(|dir| search_index::index_file_path(&dir, &hq_path));
+            let state = app.state::<SearchIndexes>();
+
+            // Load the persisted index the first time this HQ is synced
+            let loaded = state.lock().is_ok_and(|guard| guard.contains_key(&hq_path));
+            let persisted = match &index_path {
+                Some(path) if !loaded => SearchIndex::load(path, &hq_path),
+                _ => None,
+            };
+            let (generation, stale) = match state.lock() {
+                Ok(mut guard) => guard
+                    .entry(hq_path.clone())
+                    .or_insert_with(|| persisted.unwrap_or_else(|| SearchIndex::new(&hq_path)))
+                    .begin_sync(&documents),
+                Err(_) => return,
+            };
+
+            let read: Vec<IndexedDoc> = stale.iter().filter_map(|p| IndexedDoc::read(p)).collect();
+            let mut guard = match state.lock() {
+                Ok(guard) => guard,
+                Err(_) => return,
+            };
+            let index = match guard.get_mut(&hq_path) {
+                Some(index) => index,
+                None => return,
+            };
+            // Persisting is best-effort; the next sync re-reads whatever is missing
+            if index.finish_sync(generation, &documents, read) && index.is_dirty() {
+                if let Some(path) = index_path {
+                    if let Err(message) = index.save(&path) {
+                        drop(guard);
+                        let error = WatcherError {
+                            path: Some(path.to_string_lossy().to_string()),
+                            message,
+                        };
+                        report_hq_error(&app, &hq_path, error);
+                    }
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        let error = WatcherError {
+            path: None,
+            message: format!("Failed to start search indexing: {}", e),
+        };
+        report_hq_error(app, hq_path, error);
+    }
+}
+
+/// Result of `list_directory`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryListing {
+    /// The listed directory, with children down to the requested depth
+    pub node: FileTreeNode,
+    /// Scope patterns that failed to parse
+    pub pattern_errors: Vec<ScopePatternError>,
+    /// Ignored paths in the listed subtree (null unless `explainExclusions` was set)
+    pub exclusions: Option<Vec<ExcludedPath>>,
+    /// Per-file problems in the listed subtree
+    pub warnings: Vec<ScanWarning>,
+    /// Entries left out of the listed subtree (and why) plus its timing
+    pub scan_diagnostics: ScanDiagnostics,
+}
+
+/// Tauri command: list one directory of the tree, so the sidebar can expand
+/// folders on demand instead of receiving the whole tree at once.
+///
+/// `path`: A scope root or a directory node path below one, as returned by an
+///         earlier scan or listing. `hq_path`, `scopes` and `options` should
+///         match that scan so the same exclusions and limits apply.
+/// `depth`: Levels of children to include (default 1). Only those levels are
+///          read. Directories below them are marked `collapsed` and sent
+///          without `children`, with the counts of the calling window's last
+///          scan (or, for directories it doesn't have, of their own entries).
+///
+/// Directories reached through a symlink that leads out of every scope root
+/// can't be listed on their own; list an ancestor with a larger `depth`.
+#[tauri::command]
+async fn list_directory(
+    hq_path: String,
+    scopes: Vec<String>,
+    path: String,
+    depth: Option<u32>,
+    options: Option<ScanOptions>,
+    app: tauri::AppHandle,
+    window: tauri::Window,
+) -> Result<DirectoryListing, String> {
+    let label = window.label().to_string();
+    let depth = depth.unwrap_or(1);
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        list_directory_blocking(&hq_path, &scopes, &path, depth, options, &app, &label)
+    })
+    .await
+    .map_err(|e| format!("Failed to list directory: {}", e))?
+}
+
+/// Body of `list_directory`, run off the main thread.
+fn list_directory_blocking(
+    hq_path: &str,
+    scopes: &[String],
+    path: &str,
+    depth: u32,
+    options: ScanOptions,
+    app: &tauri::AppHandle,
+    label: &str,
+) -> Result<DirectoryListing, String> {
+    let hq = PathBuf::from(hq_path);
+    if !hq.is_dir() {
+        return Err(format!("HQ path is not a directory: {}", hq_path));
+    }
+    let target = Path::new(path);
+    if !target.is_dir() {
+        return Err(format!("Not a directory: {}", path));
+    }
+    let started = Instant::now();
+
+    let (scope_set, pattern_errors) = ScopeSet::parse(scopes);
+    let ignore = IgnoreEngine::new(&hq);
+    let expansion = scope_set.expand(&hq, &ignore);
+    let (roots, _) = resolve_scope_roots(&expansion.dirs);
+
+    let (root, rel, level) = roots
+        .iter()
+        .find_map(|root| {
+            let (rel, level) = locate_in_root(&hq, root, target)?;
+            Some((root, rel, level))
+        })
+        .ok_or_else(|| format!("Not inside a scanned scope: {}", path))?;
+    let root_rel = scopes::relative_str(&hq, &root.path);
+    if level > 0
+        && (scope_set.is_excluded(&rel) || ignore.check_below(&root_rel, &rel, true).is_some())
+    {
+        return Err(format!("Excluded from the scan: {}", path));
+    }
+    let pattern = expansion
+        .dirs
+        .iter()
+        .position(|dir| *dir == root.path)
+        .map(|i| expansion.patterns[i].as_str())
+        .unwrap_or_default();
+    let budget = options.budget_for(pattern);
+
+    // Counts for the directories the walk stops at, from the window's tree
+    let mut known = HashMap::new();
+    if let Ok(trees) = app.state::<TreeStates>().lock() {
+        if let Some(tree) = trees.get(label).filter(|tree| tree.hq == hq) {
+            for (root, _) in &tree.roots {
+                if let Some(node) = find_node(root, path) {
+                    collapsed_copies(node, level + depth, &mut known);
+                    break;
+                }
+            }
+        }
+    }
+
+    // The scan cache isn't written back: a partial walk can't tell deleted
+    // entries from unvisited ones, and a full scan may be writing it
+    let (_, ctx) = open_scan_cache(app, hq_path);
+    let pool = scan_pool(options.max_threads)?;
+    let mut run = ScanRun::new(&scope_set, &ctx, &ignore, &roots, &options, None, None);
+    run.collapse = Some(Collapse {
+        depth: level + depth,
+        known: &known,
+    });
+    let mut node = pool.install(|| scan_dir_recursive(target, &rel, level, &budget, &run, None));
+    if let (Some(node), Some(mut max_files)) = (&mut node, budget.file_limit(&options)) {
+        trim_to_file_limit(node, &mut max_files, &run);
+    }
+    let (exclusions, warnings, skipped) = run.into_reports();
+    let (skipped, skipped_counts) = skipped.finish();
+
+    let node = match node {
+        Some(node) => node,
+        None => {
+            let why = skipped
+                .iter()
+                .find(|entry| entry.path == path)
+                .and_then(|entry| entry.detail.clone())
+                .unwrap_or_else(|| "unreadable".to_string());
+            return Err(format!("Failed to list {}: {}", path, why));
+        }
+    };
+
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let scan_diagnostics = ScanDiagnostics {
+        skipped,
+        skipped_counts,
+        scopes: vec![ScopeTiming {
+            path: path.to_string(),
+            duration_ms,
+            file_count: node.file_count,
+        }],
+        total_ms: duration_ms,
+    };
+
+    Ok(DirectoryListing {
+        node,
+        pattern_errors,
+        exclusions,
+        warnings,
+        scan_diagnostics,
+    })
+}
+
+/// The directory node at `path` in the tree below `node`.
+fn find_node<'a>(node: &'a FileTreeNode, path: &str) -> Option<&'a FileTreeNode> {
+    if node.path == path {
+        return Some(node);
+    }
+    node.children
+        .iter()
+        .filter(|child| child.is_directory && child.link_kind.is_none())
+        .find_map(|child| find_node(child, path))
+}
+
+/// Add collapsed copies of the directories at `depth` below `node` to `out`.
+fn collapsed_copies(node: &FileTreeNode, depth: u32, out: &mut HashMap<String, FileTreeNode>) {
+    if node.depth >= depth {
+        // Directories carry no front matter, so these are all their fields
+        let copy = FileTreeNode {
+            name: node.name.clone(),
+            path: node.path.clone(),
+            is_directory: true,
+            title: node.title.clone(),
+            depth: node.depth,
+            file_count: node.file_count,
+            modified: node.modified,
+            truncated: node.truncated,
+            child_count: node.child_count,
+            collapsed: !node.children.is_empty(),
+            ..Default::default()
+        };
+        out.insert(node.path.clone(), copy);
+        return;
+    }
+    for child in &node.children {
+        if child.is_directory && child.link_kind.is_none() {
+            collapsed_copies(child, depth, out);
+        }
+    }
+}
+
+/// Where `path` sits inside the scope root `root`: its HQ-relative path as
+/// seen through the scope, and its depth below the root. None if outside.
+fn locate_in_root(hq: &Path, root: &ScopeRoot, path: &Path) -> Option<(String, u32)> {
+    let root_rel = scopes::relative_str(hq, &root.path);
+    if path == root.path {
+        return Some((root_rel, 0));
+    }
+    // Directories below a root are shown under their resolved path
+    let canonical = fs::canonicalize(path).ok()?;
+    if !canonical.starts_with(&root.canonical) {
+        return None;
+    }
+    let below = scopes::relative_str(&root.canonical, &canonical);
+    if below.is_empty() {
+        return Some((root_rel, 0));
+    }
+    let level = below.split('/').count() as u32;
+    let rel = if root_rel.is_empty() {
+        below
+    } else {
+        format!("{}/{}", root_rel, below)
+    };
+    Some((rel, level))
+}
 
+/// Start watching scoped directories for file changes, for the calling window.
+///
+/// Each window has its own watcher: starting one replaces only the calling
+/// window's previous watcher, and its events go only to that window.
+///
+/// Resolves scopes the same way as `scan_hq_directory`, then watches each
+/// concrete directory recursively. File-system events are debounced (500 ms)
+/// and each window's changes are emitted to the frontend as one `"fs-change"`
+/// event, merged to one change per path. Events under paths excluded by `!`
+/// scope patterns or the ignore engine are dropped.
+///
+/// Symlinked scope directories are watched at their canonical target, and
+/// their events are reported under the path as seen through the HQ (with
+/// `real_path` set). The parent directories of wildcard scope patterns are
+/// watched too: when a new matching scope directory appears it is watched
+/// and the batch is flagged `rescan_recommended`.
+///
+/// Renames within the watched directories are reported as one "rename" event
+/// carrying both paths; a rename into or out of them (or across an ignore
+/// rule) is reported as a "create" or "remove".
+///
+/// Each batch of changes is also applied to the tree from the last completed
+/// scan of the same HQ, and the resulting node inserts, removals and updates
+/// are emitted as one `"tree-patch"` event (see `tree_patch::TreePatch`).
+/// Batches flagged `rescan_recommended` are not applied.
+///
+/// `options.mode` picks between OS file-system events and polling (see
+/// `watch_backend::WatchMode`); by default directories on network and FUSE
+/// file systems, or that a native watch fails for, are polled. Either way the
+/// events are the same.
+///
+/// With `options.qmdAutoIndex`, document changes also schedule a debounced
+/// qmd reindex of the HQ (see `schedule_qmd_reindex`).
+///
+/// Documents that were touched without their content changing (compared by
+/// hash) are left out of the batches. Hashes are seeded in the background
+/// from the last scanned tree; until a document is hashed, its first change
+/// is always reported.
+///
+/// Directories that can't be watched are reported in the result and the rest
+/// are watched anyway; only if no scope directory can be watched does this
+/// fail. Errors reported while running are emitted as `"watcher-error"`
+/// events, and changes to the watcher's health as `"watcher-status"` events
+/// (see `watcher_status`).
+///
+/// Returns the scope patterns that failed to parse and the directories that
+/// could not be watched.
+#[tauri::command]
+fn start_watching(
+    hq_path: String,
+    scopes: Vec<String>,
+    options: Option<WatchOptions>,
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    state: State<'_, WatcherStates>,
+) -> Result<WatchStartResult, String> {
+    let options = options.unwrap_or_default();
+    let label = window.label().to_string();
+    let hq = PathBuf::from(&hq_path);
     if !hq.is_dir() {
         return Err(format!("HQ path is not a directory: {}", hq_path));
     }
 
-    let mut results: Vec<FileTreeNode> = Vec::new();
+    // Collect concrete directories to watch
+    let (scope_set, pattern_errors) = ScopeSet::parse(&scopes);
+    let ignore = IgnoreEngine::new(&hq);
+    let plan = WatchPlan::new(&hq, &scope_set, &ignore);
+
+    if plan.roots.is_empty() {
+        return Err("No valid directories to watch".to_string());
+    }
+    let generation = {
+        let mut guard = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let watcher = guard.entry(label.clone()).or_default();
+        watcher.generation += 1;
+        watcher.generation
+    };
+
+    // Documents in the last scanned tree, to seed content hashes from
+    let mut documents = Vec::new();
+    if let Ok(trees) = app.state::<TreeStates>().lock() {
+        if let Some(tree) = trees.get(&label).filter(|tree| tree.hq == hq) {
+            for (root, _) in &tree.roots {
+                collect_documents(root, &mut documents);
+            }
+        }
+    }
+    // Seeded hashes are only trusted once events have had time to arrive
+    let margin = Duration::from_secs(1)
+        + match options.mode {
+            WatchMode::Native => Duration::ZERO,
+            _ => options.poll_interval(),
+        };
+    let hashes = Arc::new(Mutex::new(ContentHashes::new(margin)));
+
+    // Handler for the debounced watchers (500 ms debounce)
+    let app_handle = app.clone();
+    let handler_label = label.clone();
+    let handler_hashes = hashes.clone();
+    let auto_index_hq = options.qmd_auto_index.then(|| hq_path.clone());
+    let initial_roots: Vec<PathBuf> = plan.roots.iter().map(|root| root.target.clone()).collect();
+    let initial_parents: Vec<(PathBuf, bool)> = plan
+        .parents
+        .iter()
+        .map(|parent| (parent.dir.clone(), parent.recursive))
+        .collect();
+    let plan = Mutex::new(plan);
+    let handler: EventHandler = Arc::new(move |res: DebounceEventResult| {
+        // Native and polling watchers may report at once; take turns
+        let mut plan = plan.lock().unwrap_or_else(|e| e.into_inner());
+        match res {
+            Ok(events) => {
+                let event_count = events.len() as u64;
+                let window_start = events
+                    .iter()
+                    .map(|event| event.time)
+                    .min()
+                    .unwrap_or_else(Instant::now);
+                let tree_state = app_handle.state::<TreeStates>();
+                let mut tree_guard = tree_state.lock().ok();
+                let mut tree = tree_guard
+                    .as_mut()
+                    .and_then(|trees| trees.get_mut(&handler_label))
+                    .filter(|tree| tree.hq == hq);
+
+                // Whether a change at an HQ path is reported: it must lie in a scope
+                // root and not be hidden by ignore rules below it or a `!` pattern
+                let is_visible = |path: &Path| {
+                    let root = match plan.root_of(path) {
+                        Some(root) => root,
+                        None => return false,
+                    };
+                    let rel = scopes::relative_str(&hq, path);
+                    let scope_rel = scopes::relative_str(&hq, &root.path);
+                    ignore
+                        .check_below(&scope_rel, &rel, path.is_dir())
+                        .is_none()
+                        && !scope_set.is_excluded(&rel)
+                };
+
+                let mut batch = ChangeBatch::default();
+                let mut new_dirs = false;
+                for event in events {
+                    let (kind, from, path) = match change_kind(&event) {
+                        Some(change) => change,
+                        None => continue,
+                    };
+                    let from = from.map(|from| plan.hq_path(&from));
+                    let path = plan.hq_path(&path);
+
+                    // Pick up edits to .gitignore files on the next check
+                    for changed in from.iter().chain([&path]) {
+                        if changed.file_name().is_some_and(|n| n == ".gitignore") {
+                            let rel = scopes::relative_str(&hq, changed);
+                            let dir_rel = rel.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+                            ignore.invalidate(dir_rel);
+                            if let Some(tree) = tree.as_mut() {
+                                tree.ignore.invalidate(dir_rel);
+                            }
+                        }
+                    }
+
+                    // A directory appearing under a wildcard parent may be a new scope root
+                    if matches!(kind, "create" | "rename")
+                        && plan.root_of(&path).is_none()
+                        && plan.under_parent(&path)
+                    {
+                        new_dirs = true;
+                    }
+
+                    // A rename across a scope or ignore boundary is a create or remove
+                    let (kind, from, path) = match from {
+                        Some(from) => match (is_visible(&from), is_visible(&path)) {
+                            (false, false) => continue,
+                            (false, true) => ("create", None, path),
+                            (true, false) => ("remove", None, from),
+                            (true, true) => (kind, Some(from), path),
+                        },
+                        None if !is_visible(&path) => continue,
+                        None => (kind, None, path),
+                    };
+
+                    let from = from.map(|from| from.to_string_lossy().to_string());
+                    batch.push(kind, from, path.to_string_lossy().to_string());
+                }
+
+                let (new_roots, new_parents) = if new_dirs {
+                    plan.refresh(&hq, &scope_set, &ignore)
+                } else {
+                    (0, 0)
+                };
+
+                // A new scope root can't be patched in; the tree needs a rescan
+                let rescan_recommended = new_roots > 0 || batch.len() > RESCAN_THRESHOLD;
+                let mut changes = batch.into_changes();
+                for change in &mut changes {
+                    change.real_path = plan
+                        .real_path(Path::new(&change.path))
+                        .map(|p| p.to_string_lossy().to_string());
+                    change.real_from = change
+                        .from
+                        .as_ref()
+                        .and_then(|from| plan.real_path(Path::new(from)))
+                        .map(|p| p.to_string_lossy().to_string());
+                }
+
+                // Drop documents that were touched but not changed. Big batches
+                // aren't worth reading through; their hashes are forgotten instead.
+                if let Ok(mut hashes) = handler_hashes.lock() {
+                    if rescan_recommended {
+                        hashes.forget_all(&changes);
+                    } else {
+                        hashes.filter(&mut changes, window_start);
+                    }
+                }
+                let emitting = !changes.is_empty() || new_roots > 0;
+
+                // Record the window and watch new scope directories, unless a
+                // newer `start_watching` has replaced this watcher
+                let mut status = None;
+                let mut watch_errors = Vec::new();
+                let watcher_state = app_handle.state::<WatcherStates>();
+                if let Ok(mut guard) = watcher_state.lock() {
+                    let watcher = guard
+                        .get_mut(&handler_label)
+                        .filter(|watcher| watcher.generation == generation);
+                    if let Some(watcher) = watcher {
+                        watcher.status.event_count += event_count;
+                        if emitting {
+                            watcher.status.batch_count += 1;
+                        }
+                        if let Some(watchers) = watcher.watchers.as_mut() {
+                            let roots = &plan.roots[plan.roots.len() - new_roots..];
+                            let parents = &plan.parents[plan.parents.len() - new_parents..];
+                            let dirs = roots
+                                .iter()
+                                .map(|root| (root.target.as_path(), true))
+                                .chain(parents.iter().map(|p| (p.dir.as_path(), p.recursive)));
+                            for (dir, recursive) in dirs {
+                                if let Err(e) = watchers.watch(dir, recursive, &mut watcher.status)
+                                {
+                                    watch_errors.push(e);
+                                }
+                            }
+                        }
+                        if new_roots + new_parents > 0 {
+                            status = Some(watcher.status.clone());
+                        }
+                    }
+                }
+                for error in watch_errors {
+                    let _ = app_handle.emit_to(&handler_label, "watcher-error", error);
+                }
+                if let Some(status) = status {
+                    let _ = app_handle.emit_to(&handler_label, "watcher-status", status);
+                }
 
-    for scope in &scopes {
-        let scope_paths = expand_scope(&hq, scope);
+                if !emitting {
+                    return;
+                }
 
-        for scope_path in scope_paths {
-            if !scope_path.is_dir() {
-                // Try following symlink
-                if let Ok(canonical) = fs::canonicalize(&scope_path) {
-                    if !canonical.is_dir() {
-                        continue;
+                // The frontend rescans big batches, so patching them would be wasted work
+                let mut patch = TreePatch::default();
+                if let Some(tree) = tree.filter(|_| !rescan_recommended) {
+                    for change in &changes {
+                        if let Some(from) = &change.from {
+                            patch_tree(tree, Path::new(from), &mut patch);
+                        }
+                        patch_tree(tree, Path::new(&change.path), &mut patch);
+                    }
+                    update_search_index(&app_handle, tree, &changes);
+                }
+                drop(tree_guard);
+                match &auto_index_hq {
+                    Some(hq_path) if changes_documents(&changes) => {
+                        schedule_qmd_reindex(&app_handle, &handler_label, hq_path);
                     }
-                    // Use the canonical path for scanning but keep the original name
-                    if let Some(mut node) = scan_dir_recursive(&scope_path, 0, 15) {
-                        // Use the relative scope path as the display path
-                        node.path = scope_path.to_string_lossy().to_string();
-                        results.push(node);
+                    _ => {}
+                }
+
+                let payload = FsChangeBatch {
+                    changes,
+                    rescan_recommended,
+                };
+                let _ = app_handle.emit_to(&handler_label, "fs-change", payload);
+                if !patch.is_empty() {
+                    let _ = app_handle.emit_to(&handler_label, "tree-patch", patch);
+                }
+            }
+            Err(errors) => {
+                let errors = errors
+                    .iter()
+                    .map(|e| WatcherError {
+                        path: e
+                            .paths
+                            .first()
+                            .map(|p| plan.hq_path(p).to_string_lossy().to_string()),
+                        message: e.to_string(),
+                    })
+                    .collect();
+                report_watcher_errors(&app_handle, &handler_label, generation, errors);
+            }
+        }
+    });
+
+    // Store the watchers — replaces any previous watcher
+    let mut guard = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let watcher = guard.entry(label.clone()).or_default();
+    watcher.status = WatcherStatus {
+        watching: true,
+        hq_path: Some(hq_path),
+        mode: options.mode,
+        ..Default::default()
+    };
+    let watchers = watcher.watchers.insert(Watchers::new(&options, handler));
+
+    // Watch each scope directory recursively (at its symlink target), and
+    // wildcard parents for new scope directories. A directory that can't be
+    // watched is reported without giving up on the rest.
+    let mut watch_errors = Vec::new();
+    let mut roots_watched = 0;
+    for dir in &initial_roots {
+        match watchers.watch(dir, true, &mut watcher.status) {
+            Ok(()) => roots_watched += 1,
+            Err(e) => watch_errors.push(e),
+        }
+    }
+    for (dir, recursive) in &initial_parents {
+        if let Err(e) = watchers.watch(dir, *recursive, &mut watcher.status) {
+            watch_errors.push(e);
+        }
+    }
+
+    if roots_watched == 0 {
+        watcher.watchers = None;
+        watcher.status.watching = false;
+        let _ = app.emit_to(&label, "watcher-status", watcher.status.clone());
+        let reason = watch_errors
+            .first()
+            .map(|e| e.message.as_str())
+            .unwrap_or("");
+        return Err(format!("Failed to watch any scope directory: {}", reason));
+    }
+    let _ = app.emit_to(&label, "watcher-status", watcher.status.clone());
+    drop(guard);
+
+    // Hash the scanned documents in the background, until replaced
+    let seed_app = app.clone();
+    let seed_label = label.clone();
+    let spawned = std::thread::Builder::new()
+        .name("hq-content-hashes".to_string())
+        .spawn(move || {
+            let state = seed_app.state::<WatcherStates>();
+            for path in documents {
+                let replaced = state
+                    .lock()
+                    .ok()
+                    .and_then(|guard| guard.get(&seed_label).map(|w| w.generation != generation))
+                    .unwrap_or(true);
+                if replaced {
+                    return;
+                }
+                let read_at = Instant::now();
+                if let Some(hash) = content_hashes::file_hash(&path) {
+                    if let Ok(mut hashes) = hashes.lock() {
+                        hashes.seed(path.to_string_lossy().to_string(), hash, read_at);
                     }
                 }
-                continue;
             }
+        });
+    // Without seeded hashes, the first change to each document is reported
+    // whatever its content
+    if let Err(e) = spawned {
+        let error = WatcherError {
+            path: None,
+            message: format!("Failed to start hashing documents: {}", e),
+        };
+        report_watcher_errors(&app, &label, generation, vec![error]);
+    }
+
+    Ok(WatchStartResult {
+        pattern_errors,
+        watch_errors,
+    })
+}
+
+/// Collect the paths of the documents in `node`'s subtree.
+fn collect_documents(node: &FileTreeNode, out: &mut Vec<PathBuf>) {
+    if !node.is_directory {
+        out.push(PathBuf::from(&node.path));
+    }
+    for child in &node.children {
+        collect_documents(child, out);
+    }
+}
+
+/// Apply watcher changes to the built-in search index of `tree`'s HQ, if one
+/// is loaded, and schedule saving it. Documents in new directories are taken
+/// from the already patched tree, so scope and ignore rules carry over.
+fn update_search_index(app: &tauri::AppHandle, tree: &ScannedTree, changes: &[FsChangeEvent]) {
+    let hq_path = tree.hq.to_string_lossy().to_string();
+    let state = app.state::<SearchIndexes>();
+    let mut guard = match state.lock() {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    let index = match guard.get_mut(&hq_path) {
+        Some(index) => index,
+        None => return,
+    };
+    let documents_below = |dir: &Path| {
+        let mut documents = Vec::new();
+        for (root, _) in &tree.roots {
+            collect_documents(root, &mut documents);
+        }
+        documents.retain(|path| path.starts_with(dir));
+        documents
+    };
+    for change in changes {
+        index.apply_change(change, documents_below);
+    }
+    if index.is_dirty() && index.schedule_save() {
+        drop(guard);
+        schedule_index_save(app, &hq_path);
+    }
+}
+
+/// Save the built-in search index of `hq_path` once `INDEX_SAVE_DELAY` has
+/// passed, so changes applied by the watcher survive the app quitting. Saved
+/// right away if the timer can't be started.
+fn schedule_index_save(app: &tauri::AppHandle, hq_path: &str) {
+    let task_app = app.clone();
+    let task_hq = hq_path.to_string();
+    let spawned = std::thread::Builder::new()
+        .name("hq-search-index-save".to_string())
+        .spawn(move || {
+            std::thread::sleep(INDEX_SAVE_DELAY);
+            save_search_index(&task_app, &task_hq);
+        });
+    if spawned.is_err() {
+        save_search_index(app, hq_path);
+    }
+}
+
+/// Save the built-in search index of `hq_path` if it has unsaved changes,
+/// reporting a failure to the windows watching the HQ.
+fn save_search_index(app: &tauri::AppHandle, hq_path: &str) {
+    let index_path = match app.path().app_data_dir() {
+        Ok(dir) => search_index::index_file_path(&dir, hq_path),
+        Err(_) => return,
+    };
+    let saved = match app.state::<SearchIndexes>().lock() {
+        Ok(mut indexes) => match indexes.get_mut(hq_path) {
+            Some(index) => index.save_if_dirty(&index_path),
+            None => Ok(()),
+        },
+        Err(_) => return,
+    };
+    if let Err(message) = saved {
+        let error = WatcherError {
+            path: Some(index_path.to_string_lossy().to_string()),
+            message,
+        };
+        report_hq_error(app, hq_path, error);
+    }
+}
+
+/// Record errors reported by the watcher window `label` started as
+/// `generation` and emit them to it as `"watcher-error"` events, followed by
+/// the updated status. Errors from a watcher that has since been replaced or
+/// stopped are dropped.
+fn report_watcher_errors(
+    app: &tauri::AppHandle,
+    label: &str,
+    generation: u64,
+    errors: Vec<WatcherError>,
+) {
+    let state = app.state::<WatcherStates>();
+    let status = match state.lock() {
+        Ok(mut guard) => match guard.get_mut(label) {
+            Some(watcher) if watcher.generation == generation => {
+                for error in &errors {
+                    watcher.status.record_error(error.clone());
+                }
+                watcher.status.clone()
+            }
+            _ => return,
+        },
+        Err(_) => return,
+    };
+    for error in errors {
+        let _ = app.emit_to(label, "watcher-error", error);
+    }
+    let _ = app.emit_to(label, "watcher-status", status);
+}
 
-            if let Some(node) = scan_dir_recursive(&scope_path, 0, 15) {
-                if node.file_count > 0 {
-                    results.push(node);
-                }
-            }
-        }
+/// Report a background failure concerning HQ `hq_path`, such as persisting
+/// its search index, as a watcher error of every window watching it. The
+/// watcher is what keeps such state current. Dropped if no window is.
+fn report_hq_error(app: &tauri::AppHandle, hq_path: &str, error: WatcherError) {
+    let watching: Vec<(String, u64)> = match app.state::<WatcherStates>().lock() {
+        Ok(guard) => guard
+            .iter()
+            .filter(|(_, watcher)| watcher.status.watching)
+            .filter(|(_, watcher)| watcher.status.hq_path.as_deref() == Some(hq_path))
+            .map(|(label, watcher)| (label.clone(), watcher.generation))
+            .collect(),
+        Err(_) => return,
+    };
+    for (label, generation) in watching {
+        report_watcher_errors(app, &label, generation, vec![error.clone()]);
     }
+}
 
-    Ok(results)
+/// Classify a debounced event as `(kind, from, path)`, where `kind` is
+/// "create", "modify", "remove" or "rename" and `from` is set for renames.
+/// Renames whose other half falls outside the watched directories arrive
+/// unpaired and become a create or remove. Returns None for access events.
+fn change_kind(event: &DebouncedEvent) -> Option<(&'static str, Option<PathBuf>, PathBuf)> {
+    let path = event.paths.first()?.clone();
+    let kind = match event.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            return Some(("rename", Some(path), event.paths[1].clone()));
+        }
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => "create",
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => "remove",
+        EventKind::Modify(ModifyKind::Name(_)) | EventKind::Any | EventKind::Other => {
+            if path.exists() {
+                "create"
+            } else {
+                "remove"
+            }
+        }
+        EventKind::Modify(_) => "modify",
+        EventKind::Access(_) => return None,
+    };
+    Some((kind, None, path))
 }
 
-/// Start watching scoped directories for file changes.
+/// Rescan the entry at `path` and merge it into the stored tree, recording
+/// the changes in `patch`. Only the topmost entry below the deepest directory
+/// already in the tree is rescanned, so a new folder is picked up whole.
+/// Does nothing for paths outside the tree's scope roots.
 ///
-/// Resolves scopes the same way as `scan_hq_directory`, then watches each
-/// concrete directory recursively. File-system events are debounced (500 ms)
-/// and emitted to the frontend as `"fs-change"` events.
-#[tauri::command]
-fn start_watching(
-    hq_path: String,
-    scopes: Vec<String>,
-    app: tauri::AppHandle,
-    state: State<'_, Mutex<WatcherState>>,
-) -> Result<(), String> {
-    let hq = PathBuf::from(&hq_path);
-    if !hq.is_dir() {
-        return Err(format!("HQ path is not a directory: {}", hq_path));
+/// The rescan sees the same ancestor chain as a full scan, so symlink cycles
+/// are caught, and the file limits count the documents already in the tree.
+fn patch_tree(tree: &mut ScannedTree, path: &Path, patch: &mut TreePatch) {
+    let ScannedTree {
+        hq,
+        options,
+        scope_set,
+        ignore,
+        scope_roots,
+        roots,
+    } = tree;
+    let total_files: u32 = roots.iter().map(|(node, _)| node.file_count).sum();
+
+    // Alias roots show no content of their own; the root they point to gets the event too
+    let (root, pattern) = match roots
+        .iter_mut()
+        .filter(|(node, _)| node.link_kind.is_none() && path.starts_with(&node.path))
+        .max_by_key(|(node, _)| node.path.len())
+    {
+        Some(found) => found,
+        None => return,
+    };
+    let segments: Vec<String> = match path.strip_prefix(&root.path) {
+        Ok(below) => below
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => return,
+    };
+    if segments.is_empty() {
+        return;
     }
 
-    // Collect concrete directories to watch
-    let mut dirs: Vec<PathBuf> = Vec::new();
-    for scope in &scopes {
-        let expanded = expand_scope(&hq, scope);
-        for dir in expanded {
-            if dir.is_dir() {
-                dirs.push(dir);
-            } else if let Ok(canonical) = fs::canonicalize(&dir) {
-                if canonical.is_dir() {
-                    dirs.push(dir);
-                }
+    let mut parent: &FileTreeNode = root;
+    let mut ancestors = vec![root.path.clone()];
+    let mut known = 0;
+    while known + 1 < segments.len() {
+        let next = parent
+            .children
+            .iter()
+            .find(|c| c.name == segments[known] && c.is_directory && c.link_kind.is_none());
+        match next {
+            Some(child) => {
+                parent = child;
+                ancestors.push(child.path.clone());
+                known += 1;
             }
+            None => break,
         }
     }
+    let depth = parent.depth;
+    let dirs = &segments[..known];
+    let name = &segments[known];
+    let old_files = match parent.children.iter().find(|c| &c.name == name) {
+        Some(old) if old.is_directory => old.file_count,
+        Some(_) => 1,
+        None => 0,
+    };
 
-    if dirs.is_empty() {
-        return Err("No valid directories to watch".to_string());
-    }
-
-    // Create debounced watcher (500 ms debounce)
-    let app_handle = app.clone();
-    let mut debouncer = new_debouncer(Duration::from_millis(500), move |res: Result<Vec<notify_debouncer_mini::DebouncedEvent>, notify::Error>| {
-        match res {
-            Ok(events) => {
-                for event in events {
-                    let path_str = event.path.to_string_lossy().to_string();
-
-                    // Skip excluded directories
-                    let should_skip = event.path.components().any(|c| {
-                        if let std::path::Component::Normal(name) = c {
-                            let n = name.to_string_lossy();
-                            WATCH_EXCLUDES.iter().any(|ex| n.as_ref() == *ex)
-                                || n.starts_with('.')
-                        } else {
-                            false
-                        }
-                    });
-                    if should_skip {
-                        continue;
-                    }
-
-                    // Only care about .md files for content changes
-                    // but emit all file changes so the frontend can decide
-                    let kind = match event.kind {
-                        DebouncedEventKind::Any => {
-                            // Determine create vs modify vs remove
-                            if event.path.exists() {
-                                "modify"
-                            } else {
-                                "remove"
-                            }
-                        }
-                        DebouncedEventKind::AnyContinuous => "modify",
-                        _ => "modify",
-                    };
-
-                    let payload = FsChangeEvent {
-                        path: path_str,
-                        kind: kind.to_string(),
-                    };
+    let root_rel = scopes::relative_str(hq, Path::new(&root.path));
+    let ancestors: Vec<(String, String)> = ancestors
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let rel = std::iter::once(root_rel.as_str())
+                .chain(segments[..i].iter().map(String::as_str))
+                .filter(|segment| !segment.is_empty())
+                .collect::<Vec<_>>()
+                .join("/");
+            (path, rel)
+        })
+        .collect();
 
-                    let _ = app_handle.emit("fs-change", payload);
-                }
-            }
-            Err(e) => {
-                eprintln!("File watcher error: {:?}", e);
+    // The limits cover what the scope (and all scopes) hold besides the old entry
+    let budget = options.budget_for(pattern);
+    let in_scope = root.file_count.saturating_sub(old_files);
+    let in_all = total_files.saturating_sub(old_files);
+    let scope_left = budget.max_files.map(|max| max.saturating_sub(in_scope));
+    let max_total = options.max_total_files;
+    let total_left = max_total.map(|max| max.saturating_sub(in_all));
+    let limit = scope_left.into_iter().chain(total_left).min();
+
+    let ctx = ScanContext::new(&hq.to_string_lossy(), None);
+    let run = ScanRun::new(scope_set, &ctx, ignore, scope_roots, options, None, None);
+    let mut new = with_ancestor_frames(&ancestors, None, &|frame| {
+        scan_entry(frame, name.clone(), depth, &budget, &run)
+    });
+    if let Some(max_files) = limit {
+        new = new.and_then(|mut node| {
+            let mut remaining = max_files;
+            if node.is_directory {
+                trim_to_file_limit(&mut node, &mut remaining, &run);
+                (node.file_count > 0 || node.truncated || node.link_kind.is_some()).then_some(node)
+            } else {
+                (remaining > 0).then_some(node)
             }
-        }
-    })
-    .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+        });
+    }
+    tree_patch::apply_entry(root, dirs, name, new, patch);
+}
 
-    // Watch each scope directory recursively
-    for dir in &dirs {
-        debouncer
-            .watcher()
-            .watch(dir, notify::RecursiveMode::Recursive)
-            .map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+/// Call `f` with the frame of the last of `ancestors` (node path and
+/// HQ-relative path of each directory from the scope root down), linked to
+/// the frames above it as they are during a full scan.
+fn with_ancestor_frames<R>(
+    ancestors: &[(String, String)],
+    parent: Option<&DirFrame>,
+    f: &dyn Fn(&DirFrame) -> R,
+) -> R {
+    let (path, rel) = &ancestors[0];
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let frame = DirFrame {
+        canonical: &canonical,
+        rel,
+        path,
+        parent,
+        truncated: AtomicBool::new(false),
+    };
+    match &ancestors[1..] {
+        [] => f(&frame),
+        below => with_ancestor_frames(below, Some(&frame), f),
     }
+}
 
-    // Store the debouncer — replaces any previous watcher
+/// Stop the calling window's file watcher.
+#[tauri::command]
+fn stop_watching(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    state: State<'_, WatcherStates>,
+) -> Result<(), String> {
     let mut guard = state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    guard._debouncer = Some(debouncer);
-
+    // Keep the entry so its generation keeps counting up
+    let watcher = guard.entry(window.label().to_string()).or_default();
+    watcher.watchers = None;
+    watcher.generation += 1;
+    watcher.status = WatcherStatus::default();
+    let _ = app.emit_to(window.label(), "watcher-status", watcher.status.clone());
     Ok(())
 }
 
-/// Stop the active file watcher.
+/// Report what the calling window's file watcher is watching, how many
+/// events it has seen, and the last error it ran into.
 #[tauri::command]
-fn stop_watching(state: State<'_, Mutex<WatcherState>>) -> Result<(), String> {
-    let mut guard = state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    guard._debouncer = None;
-    Ok(())
+fn watcher_status(
+    window: tauri::Window,
+    state: State<'_, WatcherStates>,
+) -> Result<WatcherStatus, String> {
+    let guard = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(guard
+        .get(window.label())
+        .map(|watcher| watcher.status.clone())
+        .unwrap_or_default())
 }
 
 // ---------------------------------------------------------------------------
 // qmd search integration
 // ---------------------------------------------------------------------------
 
-/// A single search result returned by qmd.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A single search result returned by qmd. The fields after `snippet` are
+/// filled in by `qmd_search` from the connected HQ.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct QmdSearchResult {
     /// Document ID (e.g. "#abc123")
@@ -404,6 +2378,25 @@ pub struct QmdSearchResult {
     /// Matched text snippet
     #[serde(default)]
     pub snippet: String,
+    /// qmd collection the result is from
+    #[serde(skip_deserializing)]
+    pub collection: Option<String>,
+    /// Absolute path of the file (that of its tree node when it is in a
+    /// scanned scope); None if it can't be found on disk
+    #[serde(skip_deserializing)]
+    pub absolute_path: Option<String>,
+    /// Path relative to the HQ root; None for files outside the HQ
+    #[serde(skip_deserializing)]
+    pub relative_path: Option<String>,
+    /// Scope pattern of the scanned scope the file is in
+    #[serde(skip_deserializing)]
+    pub scope: Option<String>,
+    /// Company ID, for files under `companies/<id>/`
+    #[serde(skip_deserializing)]
+    pub company: Option<String>,
+    /// Whether the file exists and is in a format the viewer can show
+    #[serde(skip_deserializing)]
+    pub openable: bool,
 }
 
 /// Result of the qmd search command.
@@ -413,121 +2406,522 @@ pub struct QmdSearchResponse {
     pub results: Vec<QmdSearchResult>,
     pub total: usize,
     pub error: Option<String>,
+    /// Why the search failed, when `error` is set
+    pub error_kind: Option<SearchErrorKind>,
+}
+
+impl QmdSearchResponse {
+    /// A response without results or error.
+    pub fn empty() -> Self {
+        QmdSearchResponse {
+            results: Vec::new(),
+            total: 0,
+            error: None,
+            error_kind: None,
+        }
+    }
+
+    /// A failed search.
+    pub fn failed(kind: SearchErrorKind, message: String) -> Self {
+        QmdSearchResponse {
+            error: Some(message),
+            error_kind: Some(kind),
+            ..QmdSearchResponse::empty()
+        }
+    }
+}
+
+/// Check if qmd is installed and recent enough to search with.
+#[tauri::command]
+async fn check_qmd_available(app: tauri::AppHandle) -> Result<bool, String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<QmdLocator>().info().usable())
+        .await
+        .map_err(|e| format!("Failed to look for qmd: {}", e))
+}
+
+/// Report the qmd binary in use: its path, version and supported features,
+/// and a warning if it is too old to search with or lacks search modes.
+/// qmd is looked up on the first call and the result cached.
+#[tauri::command]
+async fn qmd_info(app: tauri::AppHandle) -> Result<QmdInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || (*app.state::<QmdLocator>().info()).clone())
+        .await
+        .map_err(|e| format!("Failed to look for qmd: {}", e))
+}
+
+/// Set the qmd binary to use, from the app config. `path` None or empty
+/// looks for qmd on PATH and in the usual install locations. Returns what
+/// `qmd_info` reports for it.
+#[tauri::command]
+async fn set_qmd_path(path: Option<String>, app: tauri::AppHandle) -> Result<QmdInfo, String> {
+    let path = path.filter(|p| !p.trim().is_empty()).map(PathBuf::from);
+    tauri::async_runtime::spawn_blocking(move || {
+        let locator = app.state::<QmdLocator>();
+        locator.set_path(path);
+        if let Ok(mut roots) = app.state::<CollectionRoots>().lock() {
+            *roots = None;
+        }
+        (*locator.info()).clone()
+    })
+    .await
+    .map_err(|e| format!("Failed to look for qmd: {}", e))
+}
+
+/// The search backend for window `label`: the qmd CLI when a usable qmd is
+/// installed, else the built-in index of the window's HQ.
+fn search_backend(app: &tauri::AppHandle, label: &str) -> Box<dyn SearchBackend> {
+    let qmd = app.state::<QmdLocator>().info();
+    if let (true, Some(bin)) = (qmd.usable(), &qmd.path) {
+        return Box::new(QmdCli::new(bin.clone(), qmd.features));
+    }
+    let hq_path = app.state::<TreeStates>().lock().ok().and_then(|trees| {
+        trees
+            .get(label)
+            .map(|tree| tree.hq.to_string_lossy().to_string())
+    });
+    let index_path = hq_path.as_ref().and_then(|hq_path| {
+        let dir = app.path().app_data_dir().ok()?;
+        Some(search_index::index_file_path(&dir, hq_path))
+    });
+    let indexes = app.state::<SearchIndexes>().inner().clone();
+    Box::new(BuiltinSearch::new(indexes, hq_path, index_path))
+}
+
+/// Run `f` with the search state and backend of window `label` on a
+/// blocking thread, so a slow qmd process doesn't hold up other commands.
+async fn with_search_backend<T, F>(app: tauri::AppHandle, label: String, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&SearchContext, &dyn SearchBackend) -> Result<T, String> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(move || {
+        let backend = search_backend(&app, &label);
+        f(&SearchContext::of(&app, &label), &*backend)
+    })
+    .await
+    .map_err(|e| format!("Search task failed: {}", e))?
+}
+
+/// The state the search commands of window `label` work with. The command
+/// bodies live here and take the backend as an argument, so they can run
+/// against a `FakeBackend` in tests.
+struct SearchContext<'a> {
+    label: &'a str,
+    trees: &'a TreeStates,
+    searches: &'a SearchesInFlight,
+    collection_roots: &'a CollectionRoots,
+}
+
+impl<'a> SearchContext<'a> {
+    fn of(app: &'a tauri::AppHandle, label: &'a str) -> Self {
+        SearchContext {
+            label,
+            trees: app.state::<TreeStates>().inner(),
+            searches: app.state::<SearchesInFlight>().inner(),
+            collection_roots: app.state::<CollectionRoots>().inner(),
+        }
+    }
+
+    /// Run a search for `qmd_search` and locate its results.
+    fn search(
+        &self,
+        backend: &dyn SearchBackend,
+        query: &str,
+        mode: &str,
+        collection: Option<String>,
+        limit: Option<u32>,
+        request_id: Option<u64>,
+    ) -> Result<QmdSearchResponse, String> {
+        let cancel = match request_id {
+            Some(id) => match self.begin_search(id) {
+                Some(cancel) => cancel,
+                None => {
+                    let message = "Search cancelled by a newer search".to_string();
+                    let kind = SearchErrorKind::Cancelled;
+                    return Ok(QmdSearchResponse::failed(kind, message));
+                }
+            },
+            None => Arc::new(AtomicBool::new(false)),
+        };
+        let result = search_backend::run_search(backend, query, mode, collection, limit, &cancel);
+        if let Some(id) = request_id {
+            self.end_search(id);
+        }
+
+        let mut response = result?;
+        if !response.results.is_empty() {
+            let paths = self.result_paths(backend);
+            for result in &mut response.results {
+                paths.resolve(result);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Record search `request_id` as the window's running search and cancel
+    /// the one it replaces. Returns the new search's cancel flag, or None if
+    /// the window has already started a newer search.
+    fn begin_search(&self, request_id: u64) -> Option<Arc<AtomicBool>> {
+        let mut searches = match self.searches.lock() {
+            Ok(searches) => searches,
+            Err(_) => return Some(Arc::new(AtomicBool::new(false))),
+        };
+        if let Some((running, cancel)) = searches.get(self.label) {
+            if *running > request_id {
+                return None;
+            }
+            cancel.store(true, Ordering::Relaxed);
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        searches.insert(self.label.to_string(), (request_id, cancel.clone()));
+        Some(cancel)
+    }
+
+    /// Forget search `request_id` once it is done, unless a newer one has
+    /// replaced it.
+    fn end_search(&self, request_id: u64) {
+        if let Ok(mut searches) = self.searches.lock() {
+            if matches!(searches.get(self.label), Some((running, _)) if *running == request_id) {
+                searches.remove(self.label);
+            }
+        }
+    }
+
+    /// Cancel search `request_id` if it is still running. Returns whether it was.
+    fn cancel_search(&self, request_id: u64) -> Result<bool, String> {
+        let mut searches = self
+            .searches
+            .lock()
+            .map_err(|e| format!("Search state lock poisoned: {}", e))?;
+        match searches.get(self.label) {
+            Some((running, cancel)) if *running == request_id => {
+                cancel.store(true, Ordering::Relaxed);
+                searches.remove(self.label);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Where the window's search results are: its HQ, its scanned scopes and
+    /// the roots of `backend`'s collections.
+    fn result_paths(&self, backend: &dyn SearchBackend) -> ResultPaths {
+        let (hq, scopes) = match self.trees.lock() {
+            Ok(trees) => match trees.get(self.label) {
+                Some(tree) => (Some(tree.hq.clone()), tree.scope_dirs()),
+                None => (None, Vec::new()),
+            },
+            Err(_) => (None, Vec::new()),
+        };
+        let cached = self
+            .collection_roots
+            .lock()
+            .ok()
+            .and_then(|roots| roots.clone());
+        let collections = match cached {
+            Some(roots) => roots,
+            None => self.remember_collections(&backend.list_collections().unwrap_or_default()),
+        };
+        ResultPaths::new(hq, scopes, collections)
+    }
+
+    /// List the names of `backend`'s collections for `list_qmd_collections`,
+    /// caching their roots.
+    fn list_collections(&self, backend: &dyn SearchBackend) -> Result<Vec<String>, String> {
+        let collections = backend.list_collections()?;
+        self.remember_collections(&collections);
+        Ok(collections.into_iter().map(|c| c.name).collect())
+    }
+
+    /// Cache the roots of `collections` and return them.
+    fn remember_collections(&self, collections: &[Collection]) -> HashMap<String, PathBuf> {
+        let roots: HashMap<String, PathBuf> = collections
+            .iter()
+            .filter_map(|c| Some((c.name.clone(), c.root.clone()?)))
+            .collect();
+        if let Ok(mut cached) = self.collection_roots.lock() {
+            *cached = Some(roots.clone());
+        }
+        roots
+    }
 }
 
-/// Check if qmd is installed and available.
+/// Report which search backend the calling window uses and what it can do.
 #[tauri::command]
-fn check_qmd_available() -> Result<bool, String> {
-    Ok(resolve_qmd_path().is_some())
+async fn search_status(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+) -> Result<SearchStatus, String> {
+    let label = window.label().to_string();
+    with_search_backend(app, label, |_, backend| Ok(backend.status())).await
 }
 
-/// Execute a qmd search and return parsed JSON results.
+/// Execute a search and return its results. Goes to qmd when it is
+/// installed, else to the built-in index of the calling window's HQ.
 ///
 /// `query`: The search query string.
 /// `mode`: "keyword" | "semantic" | "hybrid" — maps to qmd search/vsearch/query.
 /// `collection`: Optional collection to scope the search (e.g. "hq", "vyg").
 /// `limit`: Max number of results to return.
+/// `request_id`: Optional ID, increasing with each search of the window. A
+/// search cancels the window's search with a lower ID if that is still
+/// running, which then returns a "cancelled" error.
+///
+/// A search that runs past the time limit of its mode is stopped and
+/// returns a "timeout" error.
 #[tauri::command]
-fn qmd_search(
+async fn qmd_search(
     query: String,
     mode: String,
     collection: Option<String>,
     limit: Option<u32>,
+    request_id: Option<u64>,
+    app: tauri::AppHandle,
+    window: tauri::Window,
 ) -> Result<QmdSearchResponse, String> {
-    if query.trim().is_empty() {
-        return Ok(QmdSearchResponse {
-            results: Vec::new(),
-            total: 0,
-            error: None,
-        });
-    }
+    let label = window.label().to_string();
+    with_search_backend(app, label, move |search, backend| {
+        search.search(backend, &query, &mode, collection, limit, request_id)
+    })
+    .await
+}
 
-    // Map mode to qmd subcommand
-    let subcmd = match mode.as_str() {
-        "keyword" => "search",
-        "semantic" => "vsearch",
-        "hybrid" | _ => "query",
-    };
+/// Cancel the calling window's search `request_id` if it is still running.
+/// Returns whether it was.
+#[tauri::command]
+fn cancel_search(
+    request_id: u64,
+    app: tauri::AppHandle,
+    window: tauri::Window,
+) -> Result<bool, String> {
+    SearchContext::of(&app, window.label()).cancel_search(request_id)
+}
 
-    let n = limit.unwrap_or(10);
+/// List the collections searches can be scoped to (none for the built-in
+/// index).
+#[tauri::command]
+async fn list_qmd_collections(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+) -> Result<Vec<String>, String> {
+    let label = window.label().to_string();
+    with_search_backend(app, label, |search, backend| {
+        search.list_collections(backend)
+    })
+    .await
+}
 
-    let qmd_bin = resolve_qmd_path()
-        .ok_or_else(|| "qmd not found. Install qmd for search functionality.".to_string())?;
+/// Rebuild the search index of the calling window's backend and return its
+/// status afterwards.
+#[tauri::command]
+async fn reindex_search(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+) -> Result<SearchStatus, String> {
+    let label = window.label().to_string();
+    with_search_backend(app, label, |_, backend| {
+        search_backend::run_reindex(backend)
+    })
+    .await
+}
 
-    let mut cmd = Command::new(&qmd_bin);
-    cmd.arg(subcmd)
-        .arg(&query)
-        .arg("--json")
-        .arg("-n")
-        .arg(n.to_string());
+// ---------------------------------------------------------------------------
+// qmd index management
+// ---------------------------------------------------------------------------
 
-    // Add collection scoping if provided
-    if let Some(ref coll) = collection {
-        if !coll.is_empty() && coll != "all" {
-            cmd.arg("-c").arg(coll);
-        }
+/// The qmd to run index tasks with, if it is usable.
+fn qmd_for_indexing(app: &tauri::AppHandle) -> Result<(PathBuf, QmdInfo), String> {
+    let qmd = app.state::<QmdLocator>().info();
+    match &qmd.path {
+        Some(bin) if qmd.usable() => Ok((bin.clone(), (*qmd).clone())),
+        _ => Err(qmd
+            .warning
+            .clone()
+            .unwrap_or_else(|| "qmd isn't installed".to_string())),
     }
+}
 
-    let output = cmd.output().map_err(|e| {
-        format!("Failed to execute qmd: {}", e)
-    })?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Ok(QmdSearchResponse {
-            results: Vec::new(),
-            total: 0,
-            error: Some(format!("qmd error: {}", stderr.trim())),
-        });
+/// Run qmd index `task` with `args`, streaming its output to window `label`
+/// as `"qmd-progress"` events, the last of which has `done` set.
+fn run_index_task(
+    app: &tauri::AppHandle,
+    label: &str,
+    task: IndexTask,
+    args: &[&str],
+    automatic: bool,
+) -> Result<(), IndexError> {
+    let (bin, _) = qmd_for_indexing(app).map_err(IndexError::Failed)?;
+    let emitter = app.clone();
+    let target = label.to_string();
+    let on_line: LineSink = Arc::new(move |line: &str| {
+        let payload = QmdProgressEvent {
+            task,
+            line: Some(line.to_string()),
+            done: false,
+            error: None,
+            automatic,
+        };
+        let _ = emitter.emit_to(&target, "qmd-progress", payload);
+    });
+    let result = app.state::<QmdIndexer>().run(task, &bin, args, on_line);
+
+    // A task that found another running never started
+    if !matches!(result, Err(IndexError::Busy(_))) {
+        let payload = QmdProgressEvent {
+            task,
+            line: None,
+            done: true,
+            error: result.as_ref().err().map(IndexError::message),
+            automatic,
+        };
+        let _ = app.emit_to(label, "qmd-progress", payload);
     }
+    result
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
+/// Add the calling window's HQ folder to qmd as a collection and index it,
+/// streaming qmd's output as `"qmd-progress"` events. The collection is
+/// named after the folder unless `name` is given. If a collection already
+/// covers the HQ folder, nothing is run.
+///
+/// Returns the collection's name.
+#[tauri::command]
+async fn create_hq_collection(
+    name: Option<String>,
+    app: tauri::AppHandle,
+    window: tauri::Window,
+) -> Result<String, String> {
+    let label = window.label().to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        let hq = app
+            .state::<TreeStates>()
+            .lock()
+            .ok()
+            .and_then(|trees| trees.get(&label).map(|tree| tree.hq.clone()))
+            .ok_or_else(|| "Scan an HQ folder before adding it to qmd".to_string())?;
+        let (bin, qmd) = qmd_for_indexing(&app)?;
+        if !qmd.features.collections {
+            return Err("This version of qmd has no `collection` command".to_string());
+        }
 
-    // Parse the JSON output — qmd returns an array of result objects
-    let results: Vec<QmdSearchResult> = serde_json::from_str(&stdout).unwrap_or_else(|_| {
-        // Try parsing as newline-delimited JSON
-        stdout
-            .lines()
-            .filter(|line| !line.trim().is_empty())
-            .filter_map(|line| serde_json::from_str::<QmdSearchResult>(line).ok())
-            .collect()
-    });
+        let cli = QmdCli::new(bin, qmd.features);
+        let canonical_hq = fs::canonicalize(&hq).ok();
+        let covers_hq = |root: &PathBuf| {
+            *root == hq || (canonical_hq.is_some() && fs::canonicalize(root).ok() == canonical_hq)
+        };
+        let existing = cli
+            .list_collections()?
+            .into_iter()
+            .find(|c| c.root.as_ref().is_some_and(covers_hq));
+        if let Some(collection) = existing {
+            return Ok(collection.name);
+        }
 
-    let total = results.len();
+        let name = name
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or_else(|| qmd_index::collection_name_for(&hq));
+        let hq_arg = hq.to_string_lossy().to_string();
+        let args = ["collection", "add", &hq_arg, "--name", &name];
+        let result = run_index_task(&app, &label, IndexTask::Collection, &args, false);
+        // Search results of the new collection are located through its root
+        if let Ok(mut roots) = app.state::<CollectionRoots>().lock() {
+            *roots = None;
+        }
+        result.map_err(|e| e.message())?;
+        Ok(name)
+    })
+    .await
+    .map_err(|e| format!("Failed to add collection: {}", e))?
+}
 
-    Ok(QmdSearchResponse {
-        results,
-        total,
-        error: None,
+/// Run `qmd update` or `qmd embed` (`task`), streaming qmd's output to the
+/// calling window as `"qmd-progress"` events. Returns once qmd has finished;
+/// fails right away if another index task is running.
+#[tauri::command]
+async fn run_qmd_index(
+    task: IndexTask,
+    app: tauri::AppHandle,
+    window: tauri::Window,
+) -> Result<(), String> {
+    let args: &[&str] = match task {
+        IndexTask::Update => &["update"],
+        IndexTask::Embed => &["embed"],
+        IndexTask::Collection => {
+            return Err("Collections are added with create_hq_collection".to_string())
+        }
+    };
+    let label = window.label().to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        run_index_task(&app, &label, task, args, false).map_err(|e| e.message())
     })
+    .await
+    .map_err(|e| format!("Failed to run qmd: {}", e))?
 }
 
-/// List available qmd collections.
+/// The qmd index task running, if any.
 #[tauri::command]
-fn list_qmd_collections() -> Result<Vec<String>, String> {
-    let qmd_bin = resolve_qmd_path()
-        .ok_or_else(|| "qmd not found in PATH".to_string())?;
-
-    let output = Command::new(&qmd_bin)
-        .arg("collection")
-        .arg("list")
-        .output()
-        .map_err(|e| {
-            format!("Failed to execute qmd: {}", e)
-        })?;
-
-    if !output.status.success() {
-        return Ok(Vec::new());
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let collections: Vec<String> = stdout
-        .lines()
-        .map(|l| l.trim().to_string())
-        .filter(|l| !l.is_empty())
-        .collect();
+fn qmd_index_running(indexer: State<'_, QmdIndexer>) -> Result<Option<IndexTask>, String> {
+    Ok(indexer.running())
+}
+
+/// Whether `changes` may change what qmd indexes: they touch a document, or
+/// a directory that may hold some.
+fn changes_documents(changes: &[FsChangeEvent]) -> bool {
+    let is_document = |path: &str| {
+        let path = Path::new(path);
+        let name = path.file_name().map(|name| name.to_string_lossy());
+        name.is_some_and(|name| formats::for_file_name(&name).is_some()) || path.is_dir()
+    };
+    changes.iter().any(|change| {
+        let from = change.from.as_deref();
+        is_document(&change.path) || from.is_some_and(is_document)
+    })
+}
 
-    Ok(collections)
+/// Schedule `qmd update` (then `qmd embed`, if qmd does semantic search)
+/// for window `label`'s HQ once its documents have been left alone for
+/// `AUTO_INDEX_DELAY`; each call puts it off again. Progress is emitted to
+/// the window as `"qmd-progress"` events with `automatic` set. A reindex
+/// that finds another index task running is put off too. Nothing is
+/// scheduled until qmd has been looked up and found usable.
+fn schedule_qmd_reindex(app: &tauri::AppHandle, label: &str, hq_path: &str) {
+    let qmd = app.state::<QmdLocator>().cached();
+    if !qmd.is_some_and(|qmd| qmd.usable()) {
+        return;
+    }
+    let indexer = app.state::<QmdIndexer>();
+    if !indexer.schedule(hq_path, AUTO_INDEX_DELAY) {
+        return;
+    }
+    let task_app = app.clone();
+    let task_label = label.to_string();
+    let task_hq = hq_path.to_string();
+    let spawned = std::thread::Builder::new()
+        .name("qmd-auto-index".to_string())
+        .spawn(move || {
+            let (app, label) = (&task_app, task_label.as_str());
+            let indexer = app.state::<QmdIndexer>();
+            indexer.run_scheduled(&task_hq, AUTO_INDEX_DELAY, || {
+                let qmd = app.state::<QmdLocator>().cached();
+                let embed = qmd.is_some_and(|qmd| qmd.features.vsearch);
+                let result = run_index_task(app, label, IndexTask::Update, &["update"], true);
+                if result.is_ok() && embed {
+                    return run_index_task(app, label, IndexTask::Embed, &["embed"], true);
+                }
+                result
+            });
+        });
+    if let Err(e) = spawned {
+        app.state::<QmdIndexer>().unschedule(hq_path);
+        let payload = QmdProgressEvent {
+            task: IndexTask::Update,
+            line: None,
+            done: true,
+            error: Some(format!("Failed to start qmd auto-indexing: {}", e)),
+            automatic: true,
+        };
+        let _ = app.emit_to(label, "qmd-progress", payload);
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -679,57 +3073,90 @@ fn get_git_commit_date(file_path: String) -> Result<Option<String>, String> {
     }
 }
 
-/// Expand a scope pattern like "companies/*/knowledge" into concrete paths.
-/// Supports a single `*` wildcard that matches any subdirectory.
-fn expand_scope(hq: &Path, scope: &str) -> Vec<PathBuf> {
-    let parts: Vec<&str> = scope.split('/').collect();
-
-    // Find position of wildcard
-    let wildcard_pos = parts.iter().position(|&p| p == "*");
-
-    match wildcard_pos {
-        Some(pos) => {
-            // Build prefix path up to the wildcard
-            let prefix: PathBuf = parts[..pos].iter().collect();
-            let prefix_path = hq.join(&prefix);
+/// Counter for the labels of windows opened with "New Window".
+static NEXT_WINDOW: AtomicU32 = AtomicU32::new(2);
+
+/// Open another app window. Each window connects to its own HQ and has its
+/// own scans and file watcher.
+fn open_window(app: &tauri::AppHandle) -> tauri::Result<()> {
+    let label = format!("window-{}", NEXT_WINDOW.fetch_add(1, Ordering::Relaxed));
+    let builder = WebviewWindowBuilder::new(app, label, WebviewUrl::default())
+        .title("Indigo Docs")
+        .inner_size(1200.0, 800.0)
+        .min_inner_size(900.0, 600.0)
+        .resizable(true)
+        .transparent(true);
+    #[cfg(target_os = "macos")]
+    let builder = builder
+        .title_bar_style(tauri::TitleBarStyle::Overlay)
+        .hidden_title(true);
+    let window = builder.build()?;
+    apply_window_effects(&window);
+    Ok(())
+}
 
-            // Read directory entries at the wildcard level
-            let entries = match fs::read_dir(&prefix_path) {
-                Ok(entries) => entries,
-                Err(_) => return Vec::new(),
-            };
+/// Apply the platform's translucent window background.
+#[cfg_attr(
+    not(any(target_os = "macos", target_os = "windows")),
+    allow(unused_variables)
+)]
+fn apply_window_effects(window: &tauri::WebviewWindow) {
+    // Apply macOS vibrancy effect
+    #[cfg(target_os = "macos")]
+    {
+        use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
+        apply_vibrancy(window, NSVisualEffectMaterial::Sidebar, None, None)
+            .expect("Failed to apply vibrancy");
+    }
 
-            let suffix_parts = &parts[pos + 1..];
+    // Apply Windows acrylic blur
+    #[cfg(target_os = "windows")]
+    {
+        use window_vibrancy::apply_acrylic;
+        let _ = apply_acrylic(window, Some((18, 18, 18, 200)));
+    }
+}
 
-            entries
-                .filter_map(|e| e.ok())
-                .filter(|e| {
-                    e.file_type()
-                        .map(|t| t.is_dir() || t.is_symlink())
-                        .unwrap_or(false)
-                })
-                .filter(|e| {
-                    let name = e.file_name().to_string_lossy().to_string();
-                    !should_exclude(&name)
-                })
-                .map(|e| {
-                    let mut full = e.path();
-                    for part in suffix_parts {
-                        full = full.join(part);
-                    }
-                    full
-                })
-                .filter(|p| {
-                    // Check if the expanded path exists (follow symlinks)
-                    p.is_dir() || fs::canonicalize(p).map(|c| c.is_dir()).unwrap_or(false)
-                })
-                .collect()
+/// Cancel a closed window's scans, drop its file watcher and scanned tree,
+/// and the search index of its HQ once no other window uses it (saving it
+/// first).
+fn forget_window(app: &tauri::AppHandle, label: &str) {
+    if let Ok(scans) = app.state::<Mutex<ScanState>>().lock() {
+        for (scan_label, cancel) in scans.active.values() {
+            if scan_label == label {
+                cancel.store(true, Ordering::Relaxed);
+            }
         }
-        None => {
-            // No wildcard - just join directly
-            vec![hq.join(scope)]
+    }
+    if let Ok(mut watchers) = app.state::<WatcherStates>().lock() {
+        watchers.remove(label);
+    }
+    if let Ok(mut searches) = app.state::<SearchesInFlight>().lock() {
+        if let Some((_, cancel)) = searches.remove(label) {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+    let hq_in_use = match app.state::<TreeStates>().lock() {
+        Ok(mut trees) => trees.remove(label).map(|tree| {
+            let in_use = trees.values().any(|other| other.hq == tree.hq);
+            (tree.hq.to_string_lossy().to_string(), in_use)
+        }),
+        Err(_) => None,
+    };
+    let (hq_path, in_use) = match hq_in_use {
+        Some(found) => found,
+        None => return,
+    };
+    // Only windows still connected to the HQ can be told if this fails
+    save_search_index(app, &hq_path);
+    if !in_use {
+        if let Ok(mut indexes) = app.state::<SearchIndexes>().lock() {
+            indexes.remove(&hq_path);
         }
     }
+    if !in_use {
+        app.state::<QmdIndexer>().unschedule(&hq_path);
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -739,8 +3166,15 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_store::Builder::default().build())
         .plugin(tauri_plugin_fs::init())
-        .manage(Mutex::new(WatcherState { _debouncer: None }))
-        .invoke_handler(tauri::generate_handler![scan_hq_directory, start_watching, stop_watching, check_qmd_available, qmd_search, list_qmd_collections, get_file_metadata, get_git_commit_date])
+        .manage(WatcherStates::default())
+        .manage(Mutex::new(ScanState::default()))
+        .manage(TreeStates::default())
+        .manage(SearchIndexes::default())
+        .manage(SearchesInFlight::default())
+        .manage(QmdLocator::default())
+        .manage(CollectionRoots::default())
+        .manage(QmdIndexer::default())
+        .invoke_handler(tauri::generate_handler![scan_hq_directory, start_scan, cancel_scan, list_directory, start_watching, stop_watching, watcher_status, check_qmd_available, qmd_info, set_qmd_path, search_status, qmd_search, cancel_search, list_qmd_collections, reindex_search, create_hq_collection, run_qmd_index, qmd_index_running, get_file_metadata, get_git_commit_date])
         .setup(|app| {
             let window = app.get_webview_window("main").unwrap();
 
@@ -750,6 +3184,9 @@ pub fn run() {
             let preferences_item = MenuItemBuilder::with_id("preferences", "Preferences...")
                 .accelerator("CmdOrCtrl+,")
                 .build(app)?;
+            let new_window_item = MenuItemBuilder::with_id("new_window", "New Window")
+                .accelerator("CmdOrCtrl+N")
+                .build(app)?;
             let separator = PredefinedMenuItem::separator(app)?;
             let quit_item = PredefinedMenuItem::quit(app, Some("Quit Indigo Docs"))?;
 
@@ -761,6 +3198,10 @@ pub fn run() {
                 .item(&quit_item)
                 .build()?;
 
+            let file_submenu = SubmenuBuilder::new(app, "File")
+                .item(&new_window_item)
+                .build()?;
+
             let edit_submenu = SubmenuBuilder::new(app, "Edit")
                 .undo()
                 .redo()
@@ -782,6 +3223,7 @@ pub fn run() {
 
             let menu = MenuBuilder::new(app)
                 .item(&app_submenu)
+                .item(&file_submenu)
                 .item(&edit_submenu)
                 .item(&view_submenu)
                 .item(&window_submenu)
@@ -789,28 +3231,259 @@ pub fn run() {
 
             app.set_menu(menu)?;
 
-            // Apply macOS vibrancy effect
-            #[cfg(target_os = "macos")]
-            {
-                use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
-                apply_vibrancy(&window, NSVisualEffectMaterial::Sidebar, None, None)
-                    .expect("Failed to apply vibrancy");
-            }
-
-            // Apply Windows acrylic blur
-            #[cfg(target_os = "windows")]
-            {
-                use window_vibrancy::apply_acrylic;
-                let _ = apply_acrylic(&window, Some((18, 18, 18, 200)));
-            }
+            apply_window_effects(&window);
 
             Ok(())
         })
         .on_menu_event(|app, event| {
             let id = event.id().0.as_str();
-            // Emit menu item click to the frontend
-            let _ = app.emit("menu-item-click", id.to_string());
+            if id == "new_window" {
+                if let Err(e) = open_window(app) {
+                    eprintln!("Failed to open window: {}", e);
+                }
+                return;
+            }
+            // Emit menu item click to the focused window
+            let focused = app
+                .webview_windows()
+                .into_values()
+                .find(|window| window.is_focused().unwrap_or(false));
+            let _ = match focused {
+                Some(window) => app.emit_to(window.label(), "menu-item-click", id.to_string()),
+                None => app.emit("menu-item-click", id.to_string()),
+            };
+        })
+        .on_window_event(|window, event| {
+            // A closed window's watcher and tree go with it
+            if let tauri::WindowEvent::Destroyed = event {
+                forget_window(window.app_handle(), window.label());
+            }
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use search_backend::FakeBackend;
+
+    /// Scan `scopes` of `hq` the way `run_scan` does, keeping the tree.
+    fn scanned(hq: &Path, scopes: &[&str], options: ScanOptions) -> ScannedTree {
+        let scopes: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+        let (scope_set, _) = ScopeSet::parse(&scopes);
+        let ignore = IgnoreEngine::new(hq);
+        let expansion = scope_set.expand(hq, &ignore);
+        let (scope_roots, _) = resolve_scope_roots(&expansion.dirs);
+        let ctx = ScanContext::new(&hq.to_string_lossy(), None);
+        let run = ScanRun::new(
+            &scope_set,
+            &ctx,
+            &ignore,
+            &scope_roots,
+            &options,
+            None,
+            None,
+        );
+        let mut roots = Vec::new();
+        for (dir, pattern) in expansion.dirs.iter().zip(&expansion.patterns) {
+            let budget = options.budget_for(pattern);
+            if let Some(node) = scan_scope_root(hq, dir, &budget, &run) {
+                roots.push((node, pattern.clone()));
+            }
+        }
+        ScannedTree {
+            hq: hq.to_path_buf(),
+            options,
+            scope_set,
+            ignore,
+            scope_roots,
+            roots,
+        }
+    }
+
+    fn child<'a>(node: &'a FileTreeNode, name: &str) -> &'a FileTreeNode {
+        match node.children.iter().find(|c| c.name == name) {
+            Some(child) => child,
+            None => panic!("{} has no child {}", node.path, name),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn patched_in_symlink_loop_is_a_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let hq = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir_all(hq.join("docs/a/b")).unwrap();
+        fs::write(hq.join("docs/a/b/one.md"), "# One").unwrap();
+        let mut tree = scanned(&hq, &["docs"], ScanOptions::default());
+
+        // A new folder whose link leads back two levels up
+        fs::create_dir(hq.join("docs/a/b/c")).unwrap();
+        fs::write(hq.join("docs/a/b/c/two.md"), "# Two").unwrap();
+        std::os::unix::fs::symlink(hq.join("docs/a"), hq.join("docs/a/b/c/loop")).unwrap();
+        let mut patch = TreePatch::default();
+        patch_tree(&mut tree, &hq.join("docs/a/b/c/two.md"), &mut patch);
+
+        let root = &tree.roots[0].0;
+        let c = child(child(child(root, "a"), "b"), "c");
+        let link = child(c, "loop");
+        assert_eq!(link.link_kind.as_deref(), Some("cycle"));
+        let target = hq.join("docs/a").to_string_lossy().to_string();
+        assert_eq!(link.link_target, Some(target));
+        assert!(link.children.is_empty());
+        assert_eq!(root.file_count, 2);
+        assert_eq!(patch.inserted.len(), 1);
+    }
+
+    #[test]
+    fn patches_count_against_the_scope_file_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let hq = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir_all(hq.join("docs")).unwrap();
+        fs::write(hq.join("docs/a.md"), "# A").unwrap();
+        fs::write(hq.join("docs/b.md"), "# B").unwrap();
+        let limits = ScopeLimits {
+            max_files: Some(3),
+            ..ScopeLimits::default()
+        };
+        let options = ScanOptions {
+            scope_limits: HashMap::from([("docs".to_string(), limits)]),
+            ..ScanOptions::default()
+        };
+        let mut tree = scanned(&hq, &["docs"], options);
+        assert_eq!(tree.roots[0].0.file_count, 2);
+
+        fs::create_dir(hq.join("docs/new")).unwrap();
+        fs::write(hq.join("docs/new/x.md"), "# X").unwrap();
+        fs::write(hq.join("docs/new/y.md"), "# Y").unwrap();
+        let new_dir = hq.join("docs/new");
+        patch_tree(&mut tree, &new_dir, &mut TreePatch::default());
+        let root = &tree.roots[0].0;
+        assert_eq!(root.file_count, 3);
+        let new = child(root, "new");
+        assert!(new.truncated);
+        assert_eq!(new.children.len(), 1);
+        assert_eq!(new.children[0].name, "x.md");
+
+        // Patching the same folder again doesn't count its documents twice
+        fs::write(hq.join("docs/new/x.md"), "# X, edited").unwrap();
+        patch_tree(&mut tree, &new_dir, &mut TreePatch::default());
+        let root = &tree.roots[0].0;
+        assert_eq!(root.file_count, 3);
+        assert_eq!(child(root, "new").children.len(), 1);
+    }
+
+    /// The search state of one window, owned by the test instead of the app.
+    #[derive(Default)]
+    struct SearchState {
+        trees: TreeStates,
+        searches: SearchesInFlight,
+        collection_roots: CollectionRoots,
+    }
+
+    impl SearchState {
+        fn context(&self) -> SearchContext<'_> {
+            SearchContext {
+                label: "main",
+                trees: &self.trees,
+                searches: &self.searches,
+                collection_roots: &self.collection_roots,
+            }
+        }
+    }
+
+    fn search_notes(
+        search: &SearchContext,
+        backend: &FakeBackend,
+        request_id: u64,
+    ) -> Result<QmdSearchResponse, String> {
+        search.search(backend, "notes", "keyword", None, None, Some(request_id))
+    }
+
+    #[test]
+    fn newer_searches_cancel_older_ones() {
+        let state = SearchState::default();
+        let search = state.context();
+        let backend = FakeBackend::new();
+
+        let first = search.begin_search(1).unwrap();
+        let second = search.begin_search(2).unwrap();
+        assert!(first.load(Ordering::Relaxed));
+        assert!(!second.load(Ordering::Relaxed));
+
+        // A search that arrives after a newer one never reaches the backend
+        let response = search_notes(&search, &backend, 1).unwrap();
+        assert_eq!(response.error_kind, Some(SearchErrorKind::Cancelled));
+        assert!(backend.requests().is_empty());
+
+        // Finishing the older search leaves the newer one cancellable
+        search.end_search(1);
+        assert!(search.cancel_search(2).unwrap());
+        assert!(second.load(Ordering::Relaxed));
+        assert!(!search.cancel_search(2).unwrap());
+    }
+
+    #[test]
+    fn backend_errors_reach_the_caller() {
+        let state = SearchState::default();
+        let search = state.context();
+        let backend = FakeBackend::new();
+
+        backend.script(Err("qmd crashed".to_string()));
+        let error = search_notes(&search, &backend, 1).unwrap_err();
+        assert_eq!(error, "qmd crashed");
+        // The failed search is no longer running
+        assert!(!search.cancel_search(1).unwrap());
+
+        let message = "Search timed out".to_string();
+        let timeout = QmdSearchResponse::failed(SearchErrorKind::Timeout, message);
+        backend.script(Ok(timeout));
+        let response = search_notes(&search, &backend, 2).unwrap();
+        assert_eq!(response.error_kind, Some(SearchErrorKind::Timeout));
+        assert_eq!(response.error.as_deref(), Some("Search timed out"));
+        assert_eq!(backend.requests().len(), 2);
+    }
+
+    #[test]
+    fn results_are_resolved_against_the_scanned_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let hq = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir_all(hq.join("companies/acme")).unwrap();
+        fs::write(hq.join("companies/acme/plan.md"), "# Plan").unwrap();
+
+        let state = SearchState::default();
+        let tree = scanned(&hq, &["companies/*"], ScanOptions::default());
+        state.trees.lock().unwrap().insert("main".to_string(), tree);
+        let collection = Collection {
+            name: "hq".to_string(),
+            root: Some(hq.clone()),
+        };
+        let hit = QmdSearchResult {
+            file_path: "qmd://hq/companies/acme/plan.md".to_string(),
+            ..QmdSearchResult::default()
+        };
+        let backend = FakeBackend::new()
+            .with_collections(vec![collection])
+            .with_results(vec![hit]);
+
+        let search = state.context();
+        let response = search_notes(&search, &backend, 1).unwrap();
+        let result = &response.results[0];
+        assert_eq!(result.collection.as_deref(), Some("hq"));
+        let absolute = hq.join("companies/acme/plan.md");
+        let absolute = absolute.to_string_lossy().to_string();
+        assert_eq!(result.absolute_path, Some(absolute));
+        let relative = result.relative_path.as_deref();
+        assert_eq!(relative, Some("companies/acme/plan.md"));
+        assert_eq!(result.scope.as_deref(), Some("companies/*"));
+        assert_eq!(result.company.as_deref(), Some("acme"));
+        assert!(result.openable);
+
+        // The collection roots are listed once and cached
+        let roots = state.collection_roots.lock().unwrap().clone().unwrap();
+        assert_eq!(roots.get("hq"), Some(&hq));
+        let names = search.list_collections(&backend).unwrap();
+        assert_eq!(names, vec!["hq".to_string()]);
+    }
+}
@@ -0,0 +1,308 @@
+//! Keeping qmd's index up to date from inside the app.
+//!
+//! Adding the HQ as a qmd collection, `qmd update` (re-index changed files)
+//! and `qmd embed` (compute embeddings for semantic search) run as child
+//! processes, one at a time, with their output passed on line by line. With
+//! auto-indexing on, the file watcher schedules an update once documents
+//! have stopped changing for a while.
+
+use crate::search_backend::{run_streaming, LineSink, RunError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long documents must stay unchanged before an automatic reindex.
+pub const AUTO_INDEX_DELAY: Duration = Duration::from_secs(30);
+
+/// How long an index task may run. Embedding a large HQ on the CPU is slow.
+const INDEX_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// A qmd index command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexTask {
+    /// `qmd collection add`: index a new collection
+    Collection,
+    /// `qmd update`: re-index changed files of every collection
+    Update,
+    /// `qmd embed`: compute embeddings of new and changed documents
+    Embed,
+}
+
+impl IndexTask {
+    /// The qmd command line, for messages.
+    fn command(self) -> &'static str {
+        match self {
+            IndexTask::Collection => "qmd collection add",
+            IndexTask::Update => "qmd update",
+            IndexTask::Embed => "qmd embed",
+        }
+    }
+}
+
+/// Payload of `"qmd-progress"` events, emitted while an index task runs:
+/// one per line of qmd output, then one with `done` set.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QmdProgressEvent {
+    pub task: IndexTask,
+    /// A line of qmd's output (None in the final event)
+    pub line: Option<String>,
+    /// Whether the task has ended
+    pub done: bool,
+    /// Why the task failed (final event only)
+    pub error: Option<String>,
+    /// Whether the file watcher started the task rather than the user
+    pub automatic: bool,
+}
+
+/// Why `QmdIndexer::run` didn't complete a task.
+#[derive(Debug)]
+pub enum IndexError {
+    /// Another task is running
+    Busy(IndexTask),
+    Failed(String),
+}
+
+impl IndexError {
+    pub fn message(&self) -> String {
+        match self {
+            IndexError::Busy(task) => format!("{} is already running", task.command()),
+            IndexError::Failed(message) => message.clone(),
+        }
+    }
+}
+
+/// Runs qmd index tasks one at a time and schedules automatic reindexes.
+#[derive(Default)]
+pub struct QmdIndexer {
+    /// The task running, if any
+    running: Mutex<Option<IndexTask>>,
+    /// When the automatic reindex scheduled for each HQ is due
+    scheduled: Mutex<HashMap<String, Instant>>,
+}
+
+impl QmdIndexer {
+    /// The task running, if any.
+    pub fn running(&self) -> Option<IndexTask> {
+        *lock(&self.running)
+    }
+
+    /// Run `bin` with `args` as `task`, passing each line of its output to
+    /// `on_line`. Fails with `Busy` if another task is running.
+    pub fn run(
+        &self,
+        task: IndexTask,
+        bin: &Path,
+        args: &[&str],
+        on_line: LineSink,
+    ) -> Result<(), IndexError> {
+        {
+            let mut running = lock(&self.running);
+            if let Some(other) = *running {
+                return Err(IndexError::Busy(other));
+            }
+            *running = Some(task);
+        }
+        let mut cmd = Command::new(bin);
+        cmd.args(args);
+        let result = run_streaming(cmd, INDEX_TIMEOUT, &AtomicBool::new(false), Some(on_line));
+        *lock(&self.running) = None;
+
+        match result {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(IndexError::Failed(format!(
+                "{} failed ({})",
+                task.command(),
+                output.status
+            ))),
+            Err(RunError::Failed(e)) => Err(IndexError::Failed(format!(
+                "Failed to execute {}: {}",
+                task.command(),
+                e
+            ))),
+            Err(e) => Err(IndexError::Failed(e.message(task.command(), INDEX_TIMEOUT))),
+        }
+    }
+
+    /// Schedule an automatic reindex of `hq` `delay` from now, replacing the
+    /// one already scheduled. Returns true if none was, in which case the
+    /// caller must start a thread that waits for it with `wait_until_due`.
+    pub fn schedule(&self, hq: &str, delay: Duration) -> bool {
+        let due = Instant::now() + delay;
+        lock(&self.scheduled).insert(hq.to_string(), due).is_none()
+    }
+
+    /// Block until the reindex scheduled for `hq` is due, then unschedule it.
+    /// Returns false if it was unscheduled meanwhile.
+    pub fn wait_until_due(&self, hq: &str) -> bool {
+        loop {
+            let wait = {
+                let mut scheduled = lock(&self.scheduled);
+                let due = match scheduled.get(hq) {
+                    Some(due) => *due,
+                    None => return false,
+                };
+                let now = Instant::now();
+                if now >= due {
+                    scheduled.remove(hq);
+                    return true;
+                }
+                due - now
+            };
+            thread::sleep(wait);
+        }
+    }
+
+    /// Run `reindex` once the reindex scheduled for `hq` is due. A reindex
+    /// that finds another task running is put off by `retry_delay` and run
+    /// again; one that completes or fails otherwise is done.
+    pub fn run_scheduled(
+        &self,
+        hq: &str,
+        retry_delay: Duration,
+        mut reindex: impl FnMut() -> Result<(), IndexError>,
+    ) {
+        while self.wait_until_due(hq) {
+            match reindex() {
+                Err(IndexError::Busy(_)) => {
+                    self.schedule(hq, retry_delay);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Drop the reindex scheduled for `hq`, if any.
+    pub fn unschedule(&self, hq: &str) {
+        lock(&self.scheduled).remove(hq);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Collection name for an HQ folder: its directory name in lowercase, with
+/// anything but letters, digits, '-' and '_' replaced by '-'.
+pub fn collection_name_for(hq: &Path) -> String {
+    let name: String = hq
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = name.trim_matches('-');
+    if name.is_empty() {
+        "hq".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    const DELAY: Duration = Duration::from_millis(60);
+
+    #[test]
+    fn rescheduling_puts_the_reindex_off() {
+        let indexer = QmdIndexer::default();
+        assert!(indexer.schedule("/hq", DELAY));
+        thread::sleep(DELAY / 2);
+        let rescheduled = Instant::now();
+        assert!(!indexer.schedule("/hq", DELAY));
+
+        assert!(indexer.wait_until_due("/hq"));
+        assert!(rescheduled.elapsed() >= DELAY);
+        // Once due, the reindex is no longer scheduled
+        assert!(!indexer.wait_until_due("/hq"));
+        assert!(indexer.schedule("/hq", DELAY));
+    }
+
+    #[test]
+    fn unscheduled_reindexes_stop_waiting() {
+        let indexer = Arc::new(QmdIndexer::default());
+        indexer.schedule("/hq", DELAY * 3);
+        indexer.schedule("/other", Duration::ZERO);
+        let waiter = {
+            let indexer = indexer.clone();
+            thread::spawn(move || indexer.wait_until_due("/hq"))
+        };
+        thread::sleep(DELAY);
+        indexer.unschedule("/hq");
+        assert!(!waiter.join().unwrap());
+        assert!(indexer.wait_until_due("/other"));
+    }
+
+    #[test]
+    fn busy_reindexes_are_retried() {
+        let indexer = QmdIndexer::default();
+        indexer.schedule("/hq", Duration::ZERO);
+        let start = Instant::now();
+        let mut outcomes = vec![Ok(()), Err(IndexError::Busy(IndexTask::Embed))];
+        let mut runs = 0;
+        indexer.run_scheduled("/hq", DELAY, || {
+            runs += 1;
+            outcomes.pop().unwrap()
+        });
+        assert_eq!(runs, 2);
+        assert!(start.elapsed() >= DELAY);
+
+        // Failures aren't retried
+        indexer.schedule("/hq", Duration::ZERO);
+        let mut runs = 0;
+        indexer.run_scheduled("/hq", DELAY, || {
+            runs += 1;
+            Err(IndexError::Failed("qmd update failed".to_string()))
+        });
+        assert_eq!(runs, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn one_task_runs_at_a_time() {
+        let indexer = Arc::new(QmdIndexer::default());
+        let sink: LineSink = Arc::new(|_| {});
+        let running = {
+            let (indexer, sink) = (indexer.clone(), sink.clone());
+            thread::spawn(move || {
+                let args = ["-c", "sleep 0.3"];
+                indexer.run(IndexTask::Update, Path::new("/bin/sh"), &args, sink)
+            })
+        };
+        while indexer.running().is_none() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let args = ["-c", "true"];
+        let result = indexer.run(IndexTask::Embed, Path::new("/bin/sh"), &args, sink);
+        assert!(matches!(result, Err(IndexError::Busy(IndexTask::Update))));
+        assert!(running.join().unwrap().is_ok());
+        assert_eq!(indexer.running(), None);
+    }
+
+    #[test]
+    fn collection_names_come_from_the_folder_name() {
+        let name = |path: &str| collection_name_for(Path::new(path));
+        assert_eq!(name("/Users/me/My HQ"), "my-hq");
+        assert_eq!(name("/Users/me/team_docs-2"), "team_docs-2");
+        assert_eq!(name("/Users/me/(Notes)"), "notes");
+        assert_eq!(name("/Users/me/Über"), "über");
+        assert_eq!(name("/Users/me/!!!"), "hq");
+        assert_eq!(name("/"), "hq");
+    }
+}
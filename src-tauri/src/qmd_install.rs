@@ -0,0 +1,392 @@
+//! Finding the qmd binary and working out what it can do.
+//!
+//! qmd is looked up once: at the path set in the app's config, else on PATH
+//! and in the usual install locations (Tauri .app bundles don't inherit the
+//! user's shell PATH). The result is cached until the configured path
+//! changes. Older qmd releases lack `vsearch`, `query` or `--json`, so the
+//! features of the binary found are read from its help output.
+
+use crate::search_backend::{run_limited, RunError};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How long `qmd --version` and `qmd --help` may take.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the qmd binary supports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QmdFeatures {
+    /// `qmd vsearch` (semantic search)
+    pub vsearch: bool,
+    /// `qmd query` (hybrid search)
+    pub query: bool,
+    /// `--json` output, which searches need
+    pub json: bool,
+    /// `qmd collection list`
+    pub collections: bool,
+}
+
+/// The qmd binary in use, as returned by `qmd_info`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QmdInfo {
+    /// The binary, if one was found
+    pub path: Option<PathBuf>,
+    /// Whether `path` is the one set in the config
+    pub configured: bool,
+    /// Version as printed by `qmd --version` (e.g. "1.0.4")
+    pub version: Option<String>,
+    pub features: QmdFeatures,
+    /// Why qmd can't be used, or what it can't do, when it is too old or
+    /// fails to run
+    pub warning: Option<String>,
+}
+
+impl QmdInfo {
+    /// Whether searches can go to qmd.
+    pub fn usable(&self) -> bool {
+        self.path.is_some() && self.features.json
+    }
+}
+
+/// Finds qmd and caches what was found.
+#[derive(Default)]
+pub struct QmdLocator {
+    state: Mutex<Located>,
+    /// Signalled when a lookup finishes
+    looked_up: Condvar,
+}
+
+#[derive(Default)]
+struct Located {
+    /// The path set in the config
+    configured: Option<PathBuf>,
+    info: Option<Arc<QmdInfo>>,
+    /// Whether a lookup is running
+    probing: bool,
+    /// Bumped by `set_path`, so a lookup of the old path isn't cached
+    generation: u64,
+}
+
+impl QmdLocator {
+    /// The qmd in use, looked up on first call. Callers that arrive during
+    /// the lookup wait for it rather than probing again. qmd is run without
+    /// holding the lock, so `cached` and `set_path` never wait on it.
+    pub fn info(&self) -> Arc<QmdInfo> {
+        let mut located = self.lock();
+        while located.info.is_none() && located.probing {
+            located = match self.looked_up.wait(located) {
+                Ok(located) => located,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+        }
+        if let Some(info) = &located.info {
+            return info.clone();
+        }
+        located.probing = true;
+        let configured = located.configured.clone();
+        let generation = located.generation;
+        drop(located);
+
+        let info = Arc::new(locate(configured.as_deref()));
+
+        let mut located = self.lock();
+        located.probing = false;
+        if located.generation == generation {
+            located.info = Some(info.clone());
+        }
+        self.looked_up.notify_all();
+        info
+    }
+
+    /// The qmd in use if it has been looked up already. For callers that
+    /// mustn't wait on running qmd, such as scans and the file watcher.
+    pub fn cached(&self) -> Option<Arc<QmdInfo>> {
+        self.lock().info.clone()
+    }
+
+    /// Use the binary at `path` from now on, or look for one if None. The
+    /// next `info` looks qmd up again.
+    pub fn set_path(&self, path: Option<PathBuf>) {
+        let mut located = self.lock();
+        located.configured = path;
+        located.info = None;
+        located.generation += 1;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Located> {
+        match self.state.lock() {
+            Ok(located) => located,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Look up the configured binary, else search for one.
+fn locate(configured: Option<&Path>) -> QmdInfo {
+    match configured {
+        Some(path) if !path.is_file() => QmdInfo {
+            path: Some(path.to_path_buf()),
+            configured: true,
+            warning: Some(format!("qmd not found at {}", path.display())),
+            ..QmdInfo::default()
+        },
+        Some(path) => inspect(path, true),
+        None => match find_installed() {
+            Some(path) => inspect(&path, false),
+            None => QmdInfo::default(),
+        },
+    }
+}
+
+/// First qmd on PATH or in a well-known install location.
+fn find_installed() -> Option<PathBuf> {
+    let names: &[&str] = if cfg!(windows) {
+        &["qmd.exe", "qmd.cmd"]
+    } else {
+        &["qmd"]
+    };
+    let on_path = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let home = std::env::var("HOME").unwrap_or_default();
+    let well_known = [
+        format!("{}/.bun/bin", home),
+        format!("{}/bin", home),
+        format!("{}/.local/bin", home),
+        "/usr/local/bin".to_string(),
+        "/opt/homebrew/bin".to_string(),
+    ];
+    on_path
+        .into_iter()
+        .chain(well_known.iter().map(PathBuf::from))
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+/// Run the binary at `path` to read its version and features.
+fn inspect(path: &Path, configured: bool) -> QmdInfo {
+    let mut info = QmdInfo {
+        path: Some(path.to_path_buf()),
+        configured,
+        ..QmdInfo::default()
+    };
+    let (version, help) = match probe(path, &["--version"])
+        .and_then(|version| probe(path, &["--help"]).map(|help| (version, help)))
+    {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            info.warning = Some(format!("Couldn't run qmd at {}: {}", path.display(), e));
+            return info;
+        }
+    };
+    info.version = parse_version(&version);
+    info.features = QmdFeatures {
+        vsearch: has_command(&help, "vsearch"),
+        query: has_command(&help, "query"),
+        json: help.contains("--json")
+            || probe(path, &["search", "--help"]).is_ok_and(|help| help.contains("--json")),
+        collections: has_command(&help, "collection"),
+    };
+    info.warning = compatibility_warning(&info);
+    info
+}
+
+/// Combined stdout and stderr of the binary at `path` run with `args`. Help
+/// goes to stderr, and may exit non-zero, with some CLI parsers.
+fn probe(path: &Path, args: &[&str]) -> Result<String, String> {
+    let mut cmd = Command::new(path);
+    cmd.args(args);
+    let output = run_limited(cmd, PROBE_TIMEOUT, &AtomicBool::new(false)).map_err(|e| match e {
+        RunError::Failed(e) => e,
+        RunError::TimedOut => format!("no response within {}s", PROBE_TIMEOUT.as_secs()),
+        RunError::Cancelled => "cancelled".to_string(),
+    })?;
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push('\n');
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(text)
+}
+
+/// The first dotted version number in `qmd --version` output ("qmd 1.0.4",
+/// "v0.9.0").
+fn parse_version(output: &str) -> Option<String> {
+    output
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .map(|word| word.trim_start_matches('v'))
+        .find(|word| {
+            word.contains('.')
+                && word.starts_with(|c: char| c.is_ascii_digit())
+                && word
+                    .split('.')
+                    .all(|part| part.starts_with(|c: char| c.is_ascii_digit()))
+        })
+        .map(str::to_string)
+}
+
+/// Whether `help` lists subcommand `name`: a line that starts with it,
+/// optionally after "qmd ".
+fn has_command(help: &str, name: &str) -> bool {
+    help.lines().any(|line| {
+        let line = line.trim_start();
+        let line = line.strip_prefix("qmd ").unwrap_or(line);
+        line.split_whitespace().next() == Some(name)
+    })
+}
+
+/// What the UI should warn about for a qmd that runs.
+fn compatibility_warning(info: &QmdInfo) -> Option<String> {
+    let qmd = match &info.version {
+        Some(version) => format!("qmd {}", version),
+        None => "This qmd".to_string(),
+    };
+    if !info.features.json {
+        return Some(format!(
+            "{} is too old: it can't return results as JSON (--json). Update qmd to search with it; until then the built-in keyword search is used.",
+            qmd
+        ));
+    }
+    let missing: Vec<&str> = [
+        (!info.features.vsearch).then_some("semantic search (vsearch)"),
+        (!info.features.query).then_some("hybrid search (query)"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if missing.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} doesn't support {}. Update qmd to use them.",
+        qmd,
+        missing.join(" or ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `qmd --help` of a release with every search mode.
+    const HELP: &str = "\
+qmd - Quick Markdown Search
+
+Usage:
+  qmd collection add [path] --name <name> --mask <pattern>  - Create/index collection
+  qmd collection list           - List all collections with details
+  qmd ls [collection[/path]]    - List collections or files in a collection
+  qmd get <file>[:line] [-l N]  - Get document (optionally from line, max N lines)
+  qmd status                    - Show index status and collections
+  qmd update [--pull]           - Re-index all collections (--pull: git pull first)
+  qmd embed [-f]                - Create vector embeddings (800 tokens/chunk, 15% overlap)
+  qmd search <query>            - Full-text search (BM25)
+  qmd vsearch <query>           - Vector similarity search
+  qmd query <query>             - Combined search with query expansion + reranking
+  qmd mcp                       - Start MCP server (for AI agent integration)
+
+Search options:
+  -n <num>                   - Number of results (default: 5, or 20 for --files/--json)
+  --min-score <num>          - Minimum similarity score
+  --full                     - Output full document instead of snippet
+  --json                     - JSON output with snippets (default: 20 results)
+  -c, --collection <name>    - Filter results to a specific collection
+";
+
+    /// `qmd --help` of an early release: keyword search only, no JSON output.
+    const OLD_HELP: &str = "\
+Usage:
+  qmd add [glob]        - Index markdown files
+  qmd search <query>    - Full-text search (BM25)
+  qmd status            - Show index status
+
+Options:
+  -n <num>              - Number of results
+  --full                - Output full document instead of snippet
+";
+
+    #[test]
+    fn versions_are_read_from_version_output() {
+        assert_eq!(parse_version("qmd 1.0.4\n").as_deref(), Some("1.0.4"));
+        assert_eq!(parse_version("v0.9.0\n").as_deref(), Some("0.9.0"));
+        let version = parse_version("qmd version 1.2.0 (bun 1.1.38, sqlite-vec v0.1.6)");
+        assert_eq!(version.as_deref(), Some("1.2.0"));
+        // A CLI without --version prints its usage instead
+        assert_eq!(parse_version(OLD_HELP), None);
+        assert_eq!(parse_version("error: unknown option '--version'"), None);
+    }
+
+    #[test]
+    fn commands_are_read_from_help_output() {
+        for command in ["collection", "search", "vsearch", "query", "embed"] {
+            assert!(has_command(HELP, command), "{}", command);
+        }
+        // Subcommands of commands and words in descriptions aren't commands
+        for word in ["add", "list", "Vector", "similarity"] {
+            assert!(!has_command(HELP, word), "{}", word);
+        }
+
+        assert!(has_command(OLD_HELP, "search"));
+        assert!(!has_command(OLD_HELP, "vsearch"));
+        assert!(!has_command(OLD_HELP, "query"));
+        assert!(!has_command(OLD_HELP, "collection"));
+        assert!(!OLD_HELP.contains("--json"));
+    }
+
+    /// A fake qmd printing `version` for --version and `help` for --help
+    /// (and `search --help`).
+    #[cfg(unix)]
+    fn fake_qmd(dir: &Path, version: &str, help: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::write(dir.join("version.txt"), version).unwrap();
+        std::fs::write(dir.join("help.txt"), help).unwrap();
+        let script = format!(
+            "#!/bin/sh\nif [ \"$1\" = --version ]; then cat '{0}/version.txt'; else cat '{0}/help.txt' >&2; exit 1; fi\n",
+            dir.display()
+        );
+        let path = dir.join("qmd");
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn features_of_a_current_qmd() {
+        let dir = tempfile::tempdir().unwrap();
+        let info = inspect(&fake_qmd(dir.path(), "qmd 1.0.4\n", HELP), true);
+        assert_eq!(info.version.as_deref(), Some("1.0.4"));
+        let all = QmdFeatures {
+            vsearch: true,
+            query: true,
+            json: true,
+            collections: true,
+        };
+        assert_eq!(info.features, all);
+        assert!(info.usable());
+        assert_eq!(info.warning, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn features_of_an_old_qmd() {
+        let dir = tempfile::tempdir().unwrap();
+        let info = inspect(&fake_qmd(dir.path(), "v0.9.0\n", OLD_HELP), false);
+        assert_eq!(info.features, QmdFeatures::default());
+        assert!(!info.usable());
+        let warning = info.warning.unwrap();
+        assert!(warning.starts_with("qmd 0.9.0 is too old"), "{}", warning);
+
+        // JSON output but no semantic or hybrid search
+        let help = format!("{}  --json                - JSON output\n", OLD_HELP);
+        let info = inspect(&fake_qmd(dir.path(), "qmd 0.9.5\n", &help), false);
+        assert!(info.usable());
+        assert!(!info.features.vsearch && !info.features.query);
+        let warning = info.warning.unwrap();
+        assert!(warning.contains("semantic search (vsearch) or hybrid search (query)"));
+    }
+}
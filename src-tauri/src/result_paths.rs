@@ -0,0 +1,214 @@
+//! Mapping search results back to documents in the HQ.
+//!
+//! qmd reports files as `qmd://<collection>/<path in collection>`, the
+//! built-in index as absolute paths. Each result is resolved against the
+//! roots of qmd's collections (falling back to the HQ root) and matched to
+//! the window's scanned scope directories, so the frontend gets the path of
+//! the file's tree node, its scope and company, and whether it can be opened.
+
+use crate::formats;
+use crate::scopes::relative_str;
+use crate::QmdSearchResult;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A scanned scope directory.
+pub struct ScopeDir {
+    /// Path of the scope's root node
+    pub path: PathBuf,
+    /// The directory it resolves to (through symlinks)
+    pub canonical: PathBuf,
+    /// The scope pattern that matched it
+    pub pattern: String,
+}
+
+impl ScopeDir {
+    /// Tree path of `canonical` if it is inside this scope. Entries below a
+    /// root are listed under their resolved path, as the scanner does.
+    fn node_path_for(&self, canonical: &Path) -> Option<PathBuf> {
+        if canonical == self.canonical {
+            Some(self.path.clone())
+        } else if canonical.starts_with(&self.canonical) {
+            Some(canonical.to_path_buf())
+        } else {
+            None
+        }
+    }
+}
+
+/// Resolves the results of one search.
+pub struct ResultPaths {
+    /// The window's HQ root, once it has been scanned
+    hq: Option<PathBuf>,
+    scopes: Vec<ScopeDir>,
+    /// Root directory of each qmd collection that reports one
+    collections: HashMap<String, PathBuf>,
+}
+
+impl ResultPaths {
+    pub fn new(
+        hq: Option<PathBuf>,
+        scopes: Vec<ScopeDir>,
+        collections: HashMap<String, PathBuf>,
+    ) -> Self {
+        ResultPaths {
+            hq,
+            scopes,
+            collections,
+        }
+    }
+
+    /// Fill in where `result` is: its collection, absolute path (that of its
+    /// tree node when it is in a scanned scope), HQ-relative path, scope,
+    /// company and whether it can be opened.
+    pub fn resolve(&self, result: &mut QmdSearchResult) {
+        let file = match result.file_path.strip_prefix("qmd://") {
+            Some(rest) => {
+                let (collection, rel) = rest.split_once('/').unwrap_or((rest, ""));
+                result.collection = Some(collection.to_string());
+                let root = self.collections.get(collection).or(self.hq.as_ref());
+                root.map(|root| root.join(rel))
+            }
+            None if Path::new(&result.file_path).is_absolute() => {
+                Some(PathBuf::from(&result.file_path))
+            }
+            None => self.hq.as_ref().map(|hq| hq.join(&result.file_path)),
+        };
+        let file = match file.filter(|file| file.is_file()) {
+            Some(file) => file,
+            None => return,
+        };
+
+        let canonical = fs::canonicalize(&file).unwrap_or_else(|_| file.clone());
+        let in_scope = self.scopes.iter().find_map(|scope| {
+            let node_path = scope.node_path_for(&canonical)?;
+            Some((node_path, scope))
+        });
+        // A scope that links outside the HQ lists its entries under their
+        // resolved path, so the HQ-relative path goes through the scope's own
+        let (absolute, alias) = match in_scope {
+            Some((node_path, scope)) => {
+                result.scope = Some(scope.pattern.clone());
+                let rest = canonical.strip_prefix(&scope.canonical).ok();
+                (node_path, rest.map(|rest| scope.path.join(rest)))
+            }
+            None => (file.clone(), None),
+        };
+
+        if let Some(hq) = &self.hq {
+            let candidates = [
+                Some(&absolute),
+                alias.as_ref(),
+                Some(&canonical),
+                Some(&file),
+            ];
+            let relative = candidates
+                .into_iter()
+                .flatten()
+                .map(|path| relative_str(hq, path))
+                .find(|rel| !rel.is_empty());
+            result.company = relative.as_deref().and_then(company_of);
+            result.relative_path = relative;
+        }
+        result.openable = absolute
+            .file_name()
+            .and_then(|name| formats::for_file_name(&name.to_string_lossy()))
+            .is_some();
+        result.absolute_path = Some(absolute.to_string_lossy().to_string());
+    }
+}
+
+/// The company an HQ-relative path belongs to: `<id>` in `companies/<id>/...`.
+fn company_of(relative: &str) -> Option<String> {
+    let mut parts = relative.split('/');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("companies"), Some(id), Some(_)) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(paths: &ResultPaths, file_path: &str) -> QmdSearchResult {
+        let mut result = QmdSearchResult {
+            file_path: file_path.to_string(),
+            ..QmdSearchResult::default()
+        };
+        paths.resolve(&mut result);
+        result
+    }
+
+    #[test]
+    fn results_in_the_hq_get_their_company_and_scope() {
+        let dir = tempfile::tempdir().unwrap();
+        let hq = fs::canonicalize(dir.path()).unwrap();
+        let scope = hq.join("companies/acme/knowledge");
+        fs::create_dir_all(&scope).unwrap();
+        fs::write(scope.join("guide.md"), "# Guide").unwrap();
+        fs::write(hq.join("notes.bin"), "x").unwrap();
+
+        let scopes = vec![ScopeDir {
+            path: scope.clone(),
+            canonical: scope.clone(),
+            pattern: "companies/*/knowledge".to_string(),
+        }];
+        let paths = ResultPaths::new(Some(hq.clone()), scopes, HashMap::new());
+
+        let result = resolved(&paths, "qmd://hq/companies/acme/knowledge/guide.md");
+        assert_eq!(result.collection.as_deref(), Some("hq"));
+        let absolute = scope.join("guide.md").to_string_lossy().to_string();
+        assert_eq!(result.absolute_path, Some(absolute));
+        let relative = "companies/acme/knowledge/guide.md";
+        assert_eq!(result.relative_path.as_deref(), Some(relative));
+        assert_eq!(result.scope.as_deref(), Some("companies/*/knowledge"));
+        assert_eq!(result.company.as_deref(), Some("acme"));
+        assert!(result.openable);
+
+        let result = resolved(&paths, "notes.bin");
+        assert_eq!(result.relative_path.as_deref(), Some("notes.bin"));
+        assert_eq!(result.scope, None);
+        assert_eq!(result.company, None);
+        assert!(!result.openable);
+
+        let result = resolved(&paths, "qmd://hq/missing.md");
+        assert_eq!(result.absolute_path, None);
+        assert_eq!(result.relative_path, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn results_in_a_symlinked_scope_are_relative_to_the_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let hq = root.join("hq");
+        let target = root.join("acme-knowledge");
+        fs::create_dir_all(hq.join("companies/acme")).unwrap();
+        fs::create_dir_all(target.join("sub")).unwrap();
+        fs::write(target.join("sub/guide.md"), "# Guide").unwrap();
+        let link = hq.join("companies/acme/knowledge");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let scopes = vec![ScopeDir {
+            path: link.clone(),
+            canonical: target.clone(),
+            pattern: "companies/*/knowledge".to_string(),
+        }];
+        let paths = ResultPaths::new(Some(hq.clone()), scopes, HashMap::new());
+        let node_path = target.join("sub/guide.md").to_string_lossy().to_string();
+
+        // qmd reports the path through the link, the built-in index the
+        // resolved one; both map to the node under its resolved path
+        let through_link = "qmd://hq/companies/acme/knowledge/sub/guide.md".to_string();
+        for file_path in [through_link, node_path.clone()] {
+            let result = resolved(&paths, &file_path);
+            assert_eq!(result.absolute_path.as_deref(), Some(node_path.as_str()));
+            let relative = "companies/acme/knowledge/sub/guide.md";
+            assert_eq!(result.relative_path.as_deref(), Some(relative));
+            assert_eq!(result.scope.as_deref(), Some("companies/*/knowledge"));
+            assert_eq!(result.company.as_deref(), Some("acme"));
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Persistent scan cache for `scan_hq_directory`.
+//!
+//! One JSON file per HQ root lives under `<app data dir>/scan-cache/`. Files are
+//! keyed by path and validated by mtime + size, so unchanged docs reuse their
+//! cached title and front matter without being re-opened. Directory listings are keyed by path
+//! and mtime, so only directories whose entries changed are re-read.
+
+use crate::formats::DocInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Bump when the on-disk layout changes; older caches are discarded.
+const SCAN_CACHE_VERSION: u32 = 3;
+
+/// Cached data for a single document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedFile {
+    /// Modified time in nanoseconds since epoch
+    pub mtime_ns: u64,
+    /// File size in bytes
+    pub size: u64,
+    /// Title and front matter read when the file was last opened
+    pub info: DocInfo,
+}
+
+/// Cached listing for a single directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedDir {
+    /// Modified time in nanoseconds since epoch
+    pub mtime_ns: u64,
+    /// Entry names, already in scanner order (directories first, then alphabetical)
+    pub entries: Vec<String>,
+    /// Names that are not valid UTF-8 (lossily converted), left out of `entries`
+    #[serde(default)]
+    pub non_utf8: Vec<String>,
+}
+
+/// A directory's entries as returned by `ScanContext::list_dir`.
+pub struct DirListing {
+    /// Entry names in scanner order
+    pub entries: Vec<String>,
+    /// Names that are not valid UTF-8 (lossily converted); these can't be
+    /// addressed by the scanner and are only reported
+    pub non_utf8: Vec<String>,
+}
+
+/// The persisted cache for one HQ root.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanCache {
+    pub version: u32,
+    /// HQ root this cache belongs to
+    pub hq_root: String,
+    pub files: HashMap<String, CachedFile>,
+    pub dirs: HashMap<String, CachedDir>,
+}
+
+impl ScanCache {
+    /// Create an empty cache for `hq_root`.
+    pub fn new(hq_root: &str) -> Self {
+        ScanCache {
+            version: SCAN_CACHE_VERSION,
+            hq_root: hq_root.to_string(),
+            files: HashMap::new(),
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// Load the cache from disk. Returns None if it is missing, unreadable,
+    /// from an older version, or belongs to a different HQ root.
+    pub fn load(path: &Path, hq_root: &str) -> Option<Self> {
+        let raw = fs::read_to_string(path).ok()?;
+        let cache: ScanCache = serde_json::from_str(&raw).ok()?;
+        if cache.version != SCAN_CACHE_VERSION || cache.hq_root != hq_root {
+            return None;
+        }
+        Some(cache)
+    }
+
+    /// Write the cache to disk, creating the cache directory if needed.
+    /// Writes to a temp file first so a crash never leaves a torn cache behind.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create cache dir: {}", e))?;
+        }
+        let json = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize scan cache: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| format!("Failed to write scan cache: {}", e))?;
+        fs::rename(&tmp, path).map_err(|e| format!("Failed to write scan cache: {}", e))
+    }
+}
+
+/// Location of the cache file for `hq_root` inside the app data dir.
+pub fn cache_file_path(app_data_dir: &Path, hq_root: &str) -> PathBuf {
+    app_data_dir
+        .join("scan-cache")
+        .join(format!("{:016x}.json", fnv1a(hq_root.as_bytes())))
+}
+
+/// Modified time of `metadata` in nanoseconds since epoch (0 if unavailable).
+pub fn mtime_ns(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// FNV-1a hash. Used for cache file names because, unlike `DefaultHasher`,
+/// its output is stable across Rust releases.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Per-scan cache bookkeeping threaded through `scan_dir_recursive`.
+///
+/// Reads come from the cache loaded at the start of the scan; everything the
+/// scan visits is recorded into a fresh cache, so deleted entries fall out.
+/// Shared by reference across scanner worker threads.
+pub struct ScanContext {
+    previous: ScanCache,
+    next: Mutex<ScanCache>,
+    /// Whether a valid cache was loaded for this HQ root
+    pub cache_used: bool,
+    /// Number of files re-read and directories re-listed
+    refreshed: AtomicU32,
+}
+
+impl ScanContext {
+    /// Start a scan with the cache loaded from disk (if any).
+    pub fn new(hq_root: &str, previous: Option<ScanCache>) -> Self {
+        let cache_used = previous.is_some();
+        ScanContext {
+            previous: previous.unwrap_or_else(|| ScanCache::new(hq_root)),
+            next: Mutex::new(ScanCache::new(hq_root)),
+            cache_used,
+            refreshed: AtomicU32::new(0),
+        }
+    }
+
+    /// Number of files re-read and directories re-listed so far.
+    pub fn refreshed(&self) -> u32 {
+        self.refreshed.load(Ordering::Relaxed)
+    }
+
+    /// List a directory's entry names in scanner order, reusing the cached
+    /// listing if the directory's mtime has not changed.
+    pub fn list_dir(&self, dir: &Path) -> io::Result<DirListing> {
+        let key = dir.to_string_lossy().to_string();
+        let mtime = mtime_ns(&fs::metadata(dir)?);
+
+        let listing = match self.previous.dirs.get(&key) {
+            Some(cached) if cached.mtime_ns == mtime => DirListing {
+                entries: cached.entries.clone(),
+                non_utf8: cached.non_utf8.clone(),
+            },
+            _ => {
+                let mut entries_vec: Vec<_> = fs::read_dir(dir)?.filter_map(|e| e.ok()).collect();
+
+                // Sort entries: directories first, then alphabetically
+                entries_vec.sort_by(|a, b| {
+                    let a_is_dir = a.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                    let b_is_dir = b.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                    match (a_is_dir, b_is_dir) {
+                        (true, false) => std::cmp::Ordering::Less,
+                        (false, true) => std::cmp::Ordering::Greater,
+                        _ => a.file_name().cmp(&b.file_name()),
+                    }
+                });
+
+                self.refreshed.fetch_add(1, Ordering::Relaxed);
+                let mut listing = DirListing {
+                    entries: Vec::new(),
+                    non_utf8: Vec::new(),
+                };
+                for entry in &entries_vec {
+                    match entry.file_name().into_string() {
+                        Ok(name) => listing.entries.push(name),
+                        Err(name) => listing.non_utf8.push(name.to_string_lossy().to_string()),
+                    }
+                }
+                listing
+            }
+        };
+
+        if let Ok(mut next) = self.next.lock() {
+            next.dirs.insert(
+                key,
+                CachedDir {
+                    mtime_ns: mtime,
+                    entries: listing.entries.clone(),
+                    non_utf8: listing.non_utf8.clone(),
+                },
+            );
+        }
+        Ok(listing)
+    }
+
+    /// Return the title and front matter for a document, calling `read_info`
+    /// only if the file's mtime or size differ from the cached entry.
+    pub fn file_info(
+        &self,
+        path: &Path,
+        metadata: &fs::Metadata,
+        read_info: impl FnOnce(&Path) -> DocInfo,
+    ) -> DocInfo {
+        let key = path.to_string_lossy().to_string();
+        let mtime = mtime_ns(metadata);
+        let size = metadata.len();
+
+        let info = match self.previous.files.get(&key) {
+            Some(cached) if cached.mtime_ns == mtime && cached.size == size => cached.info.clone(),
+            _ => {
+                self.refreshed.fetch_add(1, Ordering::Relaxed);
+                read_info(path)
+            }
+        };
+
+        if let Ok(mut next) = self.next.lock() {
+            next.files.insert(
+                key,
+                CachedFile {
+                    mtime_ns: mtime,
+                    size,
+                    info: info.clone(),
+                },
+            );
+        }
+        info
+    }
+
+    /// Consume the context, returning the cache to persist.
+    pub fn into_cache(self) -> ScanCache {
+        self.next.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn titled(title: &str) -> DocInfo {
+        DocInfo {
+            title: Some(title.to_string()),
+            ..DocInfo::default()
+        }
+    }
+
+    fn info_of(ctx: &ScanContext, path: &Path, title: &str) -> Option<String> {
+        let metadata = fs::metadata(path).unwrap();
+        ctx.file_info(path, &metadata, |_| titled(title)).title
+    }
+
+    fn set_mtime(path: &Path, mtime: SystemTime) {
+        fs::File::open(path).unwrap().set_modified(mtime).unwrap();
+    }
+
+    /// Scan `dir` and `doc` once and return the cache that scan leaves.
+    fn first_scan(hq: &str, dir: &Path, doc: &Path) -> ScanCache {
+        let ctx = ScanContext::new(hq, None);
+        ctx.list_dir(dir).unwrap();
+        info_of(&ctx, doc, "One");
+        ctx.into_cache()
+    }
+
+    #[test]
+    fn cache_round_trips_through_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let hq = dir.path().to_string_lossy().to_string();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let doc = dir.path().join("a.md");
+        fs::write(&doc, "# One").unwrap();
+        let cache = first_scan(&hq, dir.path(), &doc);
+
+        let data = tempfile::tempdir().unwrap();
+        let path = cache_file_path(data.path(), &hq);
+        cache.save(&path).unwrap();
+        let loaded = ScanCache::load(&path, &hq).unwrap();
+        assert_eq!(loaded.files.len(), 1);
+        let listing = &loaded.dirs[&hq];
+        assert_eq!(listing.entries, vec!["sub".to_string(), "a.md".to_string()]);
+
+        // Nothing changed, so nothing is re-read
+        let ctx = ScanContext::new(&hq, Some(loaded));
+        assert!(ctx.cache_used);
+        let listing = ctx.list_dir(dir.path()).unwrap();
+        assert_eq!(listing.entries, vec!["sub".to_string(), "a.md".to_string()]);
+        assert_eq!(info_of(&ctx, &doc, "Reread").as_deref(), Some("One"));
+        assert_eq!(ctx.refreshed(), 0);
+    }
+
+    #[test]
+    fn files_are_reread_when_mtime_or_size_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let hq = dir.path().to_string_lossy().to_string();
+        let doc = dir.path().join("a.md");
+        fs::write(&doc, "# One").unwrap();
+        let mtime = fs::metadata(&doc).unwrap().modified().unwrap();
+        let cache = first_scan(&hq, dir.path(), &doc);
+
+        // Same size, newer mtime
+        fs::write(&doc, "# Two").unwrap();
+        set_mtime(&doc, mtime + Duration::from_secs(1));
+        let ctx = ScanContext::new(&hq, Some(cache.clone()));
+        assert_eq!(info_of(&ctx, &doc, "Two").as_deref(), Some("Two"));
+        assert_eq!(ctx.refreshed(), 1);
+
+        // Same mtime, different size
+        fs::write(&doc, "# Three").unwrap();
+        set_mtime(&doc, mtime);
+        let ctx = ScanContext::new(&hq, Some(cache));
+        assert_eq!(info_of(&ctx, &doc, "Three").as_deref(), Some("Three"));
+        assert_eq!(ctx.refreshed(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dirs_are_relisted_when_mtime_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let hq = dir.path().to_string_lossy().to_string();
+        let doc = dir.path().join("a.md");
+        fs::write(&doc, "# One").unwrap();
+        let mtime = fs::metadata(dir.path()).unwrap().modified().unwrap();
+        let cache = first_scan(&hq, dir.path(), &doc);
+
+        // The listing is trusted as long as the directory's mtime matches
+        fs::write(dir.path().join("b.md"), "# B").unwrap();
+        set_mtime(dir.path(), mtime);
+        let ctx = ScanContext::new(&hq, Some(cache.clone()));
+        assert_eq!(ctx.list_dir(dir.path()).unwrap().entries, vec!["a.md"]);
+
+        set_mtime(dir.path(), mtime + Duration::from_secs(1));
+        let ctx = ScanContext::new(&hq, Some(cache));
+        let listing = ctx.list_dir(dir.path()).unwrap();
+        assert_eq!(listing.entries, vec!["a.md", "b.md"]);
+        assert_eq!(ctx.refreshed(), 1);
+    }
+
+    #[test]
+    fn unusable_cache_files_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let hq = dir.path().to_string_lossy().to_string();
+        let path = cache_file_path(dir.path(), &hq);
+        assert!(ScanCache::load(&path, &hq).is_none());
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{\"version\": 3, \"hqRoot\": ").unwrap();
+        assert!(ScanCache::load(&path, &hq).is_none());
+        // A corrupt cache is simply replaced by the next save
+        ScanCache::new(&hq).save(&path).unwrap();
+        assert!(ScanCache::load(&path, &hq).is_some());
+
+        assert!(ScanCache::load(&path, "/other").is_none());
+        let old = ScanCache {
+            version: SCAN_CACHE_VERSION - 1,
+            ..ScanCache::new(&hq)
+        };
+        old.save(&path).unwrap();
+        assert!(ScanCache::load(&path, &hq).is_none());
+    }
+}
@@ -0,0 +1,463 @@
+//! Scope pattern parsing and expansion.
+//!
+//! Scopes are HQ-relative glob patterns: `*` and `?` match within one path
+//! segment, `**` matches any number of segments, `{a,b}` matches either
+//! alternative and `[abc]` matches a character class. Patterns starting with
+//! `!` exclude matching directories (and everything below them) from the
+//! scopes matched by the other patterns.
+
+use crate::ignore_rules::IgnoreEngine;
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// How deep a `**` pattern may descend below its literal prefix.
+const MAX_SCOPE_DEPTH: usize = 15;
+
+/// A scope pattern that could not be parsed.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopePatternError {
+    /// The pattern as given by the caller
+    pub pattern: String,
+    /// Why it failed to parse
+    pub error: String,
+}
+
+/// A single positive scope pattern.
+struct IncludePattern {
+    /// The pattern as given by the caller
+    raw: String,
+    /// Leading literal segments, walked directly instead of listed
+    prefix: PathBuf,
+    /// Matcher for the full HQ-relative path; None if the pattern is fully literal
+    matcher: Option<GlobMatcher>,
+    /// How many levels below `prefix` a match can be
+    max_depth: usize,
+}
+
+/// The concrete directories a `ScopeSet` expands to.
+pub struct ScopeExpansion {
+    /// Matched directories, in pattern order and without duplicates
+    pub dirs: Vec<PathBuf>,
+    /// The pattern (as given) that matched each of `dirs`, index for index
+    pub patterns: Vec<String>,
+    /// Include patterns (as given) that matched no directory
+    pub empty_patterns: Vec<String>,
+}
+
+/// A directory in which new subdirectories may match a wildcard scope pattern.
+pub struct WatchParent {
+    pub dir: PathBuf,
+    /// Whether matches can appear at any depth below `dir` (patterns with
+    /// `**` or braces), rather than only in its direct subdirectories
+    pub recursive: bool,
+}
+
+/// A parsed set of include and exclude scope patterns.
+pub struct ScopeSet {
+    includes: Vec<IncludePattern>,
+    excludes: GlobSet,
+}
+
+impl ScopeSet {
+    /// Parse scope patterns. Patterns that fail to parse are skipped and
+    /// reported alongside the resulting set.
+    pub fn parse(patterns: &[String]) -> (ScopeSet, Vec<ScopePatternError>) {
+        let mut includes = Vec::new();
+        let mut excludes = GlobSetBuilder::new();
+        let mut errors = Vec::new();
+
+        for raw in patterns {
+            let (negated, pattern) = match raw.trim().strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, raw.trim()),
+            };
+            let pattern = pattern.trim_matches('/');
+
+            if pattern.is_empty() {
+                errors.push(ScopePatternError {
+                    pattern: raw.clone(),
+                    error: "empty pattern".to_string(),
+                });
+                continue;
+            }
+
+            let glob = match build_glob(pattern) {
+                Ok(glob) => glob,
+                Err(e) => {
+                    errors.push(ScopePatternError {
+                        pattern: raw.clone(),
+                        error: e.kind().to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            if negated {
+                excludes.add(glob);
+            } else {
+                includes.push(IncludePattern::new(raw, pattern, glob));
+            }
+        }
+
+        let excludes = excludes.build().unwrap_or_else(|_| GlobSet::empty());
+        (ScopeSet { includes, excludes }, errors)
+    }
+
+    /// Expand all include patterns into concrete directories under `hq`,
+    /// in pattern order, without duplicates and without excluded directories.
+    /// Wildcard levels skip directories hidden by the ignore engine.
+    pub fn expand(&self, hq: &Path, ignore: &IgnoreEngine) -> ScopeExpansion {
+        let mut expansion = ScopeExpansion {
+            dirs: Vec::new(),
+            patterns: Vec::new(),
+            empty_patterns: Vec::new(),
+        };
+        for include in &self.includes {
+            let paths = include.expand(hq, self, ignore);
+            if paths.is_empty() {
+                expansion.empty_patterns.push(include.raw.clone());
+            }
+            for path in paths {
+                if !expansion.dirs.contains(&path) {
+                    expansion.dirs.push(path);
+                    expansion.patterns.push(include.raw.clone());
+                }
+            }
+        }
+        expansion
+    }
+
+    /// Directories to watch so that new directories matching a wildcard
+    /// include pattern are noticed: each pattern's literal prefix, plus the
+    /// directories between it and the matching level. Fully literal patterns
+    /// contribute nothing.
+    pub fn watch_parents(&self, hq: &Path, ignore: &IgnoreEngine) -> Vec<WatchParent> {
+        let mut parents: Vec<WatchParent> = Vec::new();
+        for include in &self.includes {
+            let matcher = match &include.matcher {
+                Some(matcher) => matcher,
+                None => continue,
+            };
+            let root = hq.join(&include.prefix);
+            if include.max_depth == MAX_SCOPE_DEPTH {
+                if root.is_dir() {
+                    parents.push(WatchParent {
+                        dir: root,
+                        recursive: true,
+                    });
+                }
+                continue;
+            }
+            let walk = MatchWalk {
+                hq,
+                matcher,
+                set: self,
+                ignore,
+            };
+            walk.collect_parents(&root, include.max_depth, &mut parents);
+        }
+
+        // A recursive parent already covers everything below it
+        let mut unique: Vec<WatchParent> = Vec::new();
+        for parent in parents {
+            let covered = unique
+                .iter()
+                .any(|p| p.dir == parent.dir || (p.recursive && parent.dir.starts_with(&p.dir)));
+            if !covered {
+                unique.retain(|p| !(parent.recursive && p.dir.starts_with(&parent.dir)));
+                unique.push(parent);
+            }
+        }
+        unique
+    }
+
+    /// Whether the HQ-relative path `rel` (`/`-separated) is excluded by a
+    /// `!` pattern, either directly or because one of its ancestors is.
+    pub fn is_excluded(&self, rel: &str) -> bool {
+        if self.excludes.is_empty() {
+            return false;
+        }
+        let mut end = 0;
+        for segment in rel.split('/') {
+            end += segment.len();
+            if self.excludes.is_match(&rel[..end]) {
+                return true;
+            }
+            end += 1;
+        }
+        false
+    }
+}
+
+impl IncludePattern {
+    fn new(raw: &str, pattern: &str, glob: Glob) -> Self {
+        let segments: Vec<&str> = pattern.split('/').collect();
+        let literal_len = segments.iter().take_while(|s| !has_glob_meta(s)).count();
+        let prefix: PathBuf = segments[..literal_len].iter().collect();
+
+        if literal_len == segments.len() {
+            return IncludePattern {
+                raw: raw.to_string(),
+                prefix,
+                matcher: None,
+                max_depth: 0,
+            };
+        }
+
+        // Brace alternatives may contain separators, so only a pattern
+        // without `**` or `{` has a fixed depth.
+        let rest = &segments[literal_len..];
+        let max_depth = if rest.iter().any(|s| s.contains("**") || s.contains('{')) {
+            MAX_SCOPE_DEPTH
+        } else {
+            rest.len()
+        };
+
+        IncludePattern {
+            raw: raw.to_string(),
+            prefix,
+            matcher: Some(glob.compile_matcher()),
+            max_depth,
+        }
+    }
+
+    fn expand(&self, hq: &Path, set: &ScopeSet, ignore: &IgnoreEngine) -> Vec<PathBuf> {
+        let root = hq.join(&self.prefix);
+        let matcher = match &self.matcher {
+            Some(matcher) => matcher,
+            None => {
+                // No wildcard - just join directly
+                return if set.is_excluded(&relative_str(hq, &root)) {
+                    Vec::new()
+                } else {
+                    vec![root]
+                };
+            }
+        };
+
+        let mut results = Vec::new();
+        let walk = MatchWalk {
+            hq,
+            matcher,
+            set,
+            ignore,
+        };
+        walk.collect(&root, self.max_depth, &mut results);
+        results
+    }
+}
+
+/// Directory walk that expands one wildcard include pattern.
+struct MatchWalk<'a> {
+    hq: &'a Path,
+    matcher: &'a GlobMatcher,
+    set: &'a ScopeSet,
+    ignore: &'a IgnoreEngine,
+}
+
+impl MatchWalk<'_> {
+    /// Collect directories below `dir` (up to `depth` levels) whose HQ-relative
+    /// path matches the pattern. Matched directories are not descended into.
+    fn collect(&self, dir: &Path, depth: usize, results: &mut Vec<PathBuf>) {
+        if depth == 0 {
+            return;
+        }
+
+        for (path, rel) in self.subdirs(dir) {
+            if self.matcher.is_match(&rel) {
+                results.push(path);
+            } else {
+                self.collect(&path, depth - 1, results);
+            }
+        }
+    }
+
+    /// Collect `dir` and the non-matching directories below it (up to `depth`
+    /// levels, exclusive), in which a new match could appear.
+    fn collect_parents(&self, dir: &Path, depth: usize, parents: &mut Vec<WatchParent>) {
+        if depth == 0 || !dir.is_dir() {
+            return;
+        }
+        parents.push(WatchParent {
+            dir: dir.to_path_buf(),
+            recursive: false,
+        });
+        for (path, rel) in self.subdirs(dir) {
+            if !self.matcher.is_match(&rel) {
+                self.collect_parents(&path, depth - 1, parents);
+            }
+        }
+    }
+
+    /// Subdirectories of `dir` (following symlinks) with their HQ-relative
+    /// paths, sorted, leaving out excluded and ignored ones.
+    fn subdirs(&self, dir: &Path) -> Vec<(PathBuf, String)> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut dirs: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_type()
+                    .map(|t| t.is_dir() || t.is_symlink())
+                    .unwrap_or(false)
+            })
+            .map(|e| e.path())
+            // Check if the path is a directory (following symlinks)
+            .filter(|p| p.is_dir())
+            .collect();
+        dirs.sort();
+
+        dirs.into_iter()
+            .map(|path| {
+                let rel = relative_str(self.hq, &path);
+                (path, rel)
+            })
+            .filter(|(_, rel)| !self.set.is_excluded(rel) && self.ignore.check(rel, true).is_none())
+            .collect()
+    }
+}
+
+/// Compile a scope glob. `*` and `?` never cross a `/`; `**` does.
+fn build_glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .backslash_escape(true)
+        .build()
+}
+
+fn has_glob_meta(segment: &str) -> bool {
+    segment.contains(['*', '?', '[', '{', '\\'])
+}
+
+/// `path` relative to `hq`, joined with `/` regardless of platform.
+/// Returns an empty string for paths outside `hq`.
+pub fn relative_str(hq: &Path, path: &Path) -> String {
+    path.strip_prefix(hq)
+        .map(|rel| {
+            rel.components()
+                .filter_map(|c| match c {
+                    Component::Normal(name) => Some(name.to_string_lossy()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_reports_bad_patterns_and_keeps_the_rest() {
+        let (set, errors) = ScopeSet::parse(&patterns(&[
+            " /knowledge/public/ ",
+            "",
+            "!",
+            "knowledge/{a,b",
+            "!companies/personal",
+        ]));
+        let bad: Vec<&str> = errors.iter().map(|e| e.pattern.as_str()).collect();
+        assert_eq!(bad, ["", "!", "knowledge/{a,b"]);
+        assert_eq!(set.includes.len(), 1);
+        assert_eq!(set.includes[0].prefix, Path::new("knowledge/public"));
+        assert!(set.includes[0].matcher.is_none());
+    }
+
+    #[test]
+    fn only_fixed_wildcard_patterns_have_a_depth() {
+        let (set, _) = ScopeSet::parse(&patterns(&[
+            "companies/*/knowledge",
+            "companies/{acme/docs,beta}",
+            "**/runbooks",
+        ]));
+        let depths: Vec<usize> = set.includes.iter().map(|i| i.max_depth).collect();
+        assert_eq!(depths, [2, MAX_SCOPE_DEPTH, MAX_SCOPE_DEPTH]);
+        assert_eq!(set.includes[0].prefix, Path::new("companies"));
+        assert_eq!(set.includes[2].prefix, Path::new(""));
+    }
+
+    #[test]
+    fn excluding_a_directory_excludes_everything_below_it() {
+        let (set, _) = ScopeSet::parse(&patterns(&["!companies/personal", "!**/drafts"]));
+        assert!(set.is_excluded("companies/personal"));
+        assert!(set.is_excluded("companies/personal/knowledge/notes.md"));
+        assert!(set.is_excluded("knowledge/drafts/q3/plan.md"));
+        assert!(!set.is_excluded("companies/personal-brand"));
+        assert!(!set.is_excluded("companies"));
+
+        let (none, _) = ScopeSet::parse(&patterns(&["knowledge"]));
+        assert!(!none.is_excluded("knowledge/drafts"));
+    }
+
+    #[test]
+    fn expand_matches_directories_in_pattern_order() {
+        let hq = tempfile::tempdir().unwrap();
+        for dir in [
+            "knowledge/public",
+            "knowledge/shared",
+            "knowledge/private",
+            "companies/acme/knowledge",
+            "companies/acme/docs",
+            "companies/beta/knowledge",
+            "companies/personal/knowledge",
+            "companies/acme/node_modules/pkg/runbooks",
+            "ops/team/runbooks/old/runbooks",
+        ] {
+            fs::create_dir_all(hq.path().join(dir)).unwrap();
+        }
+        let ignore = IgnoreEngine::new(hq.path());
+        let (set, errors) = ScopeSet::parse(&patterns(&[
+            "knowledge/{public,shared}",
+            "companies/*/knowledge",
+            "knowledge/public",
+            "companies/{acme/docs,beta}",
+            "**/runbooks",
+            "missing/*",
+            "!companies/personal",
+        ]));
+        assert!(errors.is_empty());
+
+        let expansion = set.expand(hq.path(), &ignore);
+        let dirs: Vec<String> = expansion
+            .dirs
+            .iter()
+            .map(|dir| relative_str(hq.path(), dir))
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                "knowledge/public",
+                "knowledge/shared",
+                "companies/acme/knowledge",
+                "companies/beta/knowledge",
+                // An alternative may span segments
+                "companies/acme/docs",
+                "companies/beta",
+                // Matched directories aren't descended into; node_modules is ignored
+                "ops/team/runbooks",
+            ]
+        );
+        assert_eq!(expansion.patterns[4], "companies/{acme/docs,beta}");
+        assert_eq!(expansion.empty_patterns, ["missing/*"]);
+    }
+
+    #[test]
+    fn relative_str_is_empty_outside_the_hq() {
+        let hq = Path::new("/hq");
+        assert_eq!(relative_str(hq, Path::new("/hq/a/b")), "a/b");
+        assert_eq!(relative_str(hq, Path::new("/hq")), "");
+        assert_eq!(relative_str(hq, Path::new("/elsewhere/a")), "");
+    }
+}
@@ -0,0 +1,863 @@
+//! Search engines behind one interface.
+//!
+//! `qmd_search` and its sibling commands pick a `SearchBackend` per call
+//! (the qmd CLI when it is installed, else the built-in index of the
+//! window's HQ) and hand it to the command bodies, which never know which
+//! one they got. In tests, `FakeBackend` stands in for a real engine so the
+//! command layer can be tested without qmd.
+//!
+//! qmd runs as a child process with a time limit per search mode, and is
+//! killed when its search is cancelled (e.g. replaced by a newer one).
+
+use crate::qmd_install::QmdFeatures;
+use crate::search_index::IndexedDoc;
+use crate::{QmdSearchResponse, QmdSearchResult, SearchIndexes};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+use std::collections::VecDeque;
+#[cfg(test)]
+use std::sync::atomic::AtomicU32;
+#[cfg(test)]
+use std::sync::Mutex;
+
+/// Results returned when a search doesn't ask for a number.
+const DEFAULT_LIMIT: u32 = 10;
+
+/// How long other qmd commands (e.g. listing collections) may run.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a qmd reindex may run.
+const REINDEX_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// How often a running qmd process is checked for exit, timeout and cancellation.
+const WAIT_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How a query is matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Exact words (qmd `search`)
+    Keyword,
+    /// Conceptual similarity (qmd `vsearch`)
+    Semantic,
+    /// Both, reranked (qmd `query`)
+    Hybrid,
+}
+
+impl SearchMode {
+    pub const ALL: [SearchMode; 3] = [
+        SearchMode::Keyword,
+        SearchMode::Semantic,
+        SearchMode::Hybrid,
+    ];
+
+    /// How long a search in this mode may run. Semantic and hybrid searches
+    /// first load embedding (and reranking) models, which can take a while.
+    pub fn timeout(self) -> Duration {
+        match self {
+            SearchMode::Keyword => Duration::from_secs(10),
+            SearchMode::Semantic => Duration::from_secs(60),
+            SearchMode::Hybrid => Duration::from_secs(90),
+        }
+    }
+
+    /// Parse a mode name as sent by the frontend. Anything unrecognized is
+    /// hybrid.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "keyword" => SearchMode::Keyword,
+            "semantic" => SearchMode::Semantic,
+            _ => SearchMode::Hybrid,
+        }
+    }
+}
+
+/// A search as passed to a backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchRequest {
+    /// The query, never blank
+    pub query: String,
+    pub mode: SearchMode,
+    /// Collection to scope the search to; None searches everything
+    pub collection: Option<String>,
+    /// Max number of results
+    pub limit: u32,
+    /// How long the search may run before it is stopped
+    pub timeout: Duration,
+}
+
+/// Why a search failed. Sent as `errorKind` alongside `error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchErrorKind {
+    /// The engine reported an error or isn't ready
+    Failed,
+    /// The search ran past its time limit and was stopped
+    Timeout,
+    /// A newer search (or `cancel_search`) replaced it
+    Cancelled,
+}
+
+/// What a backend can do right now. Returned by `search_status`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchStatus {
+    /// "qmd" or "builtin"
+    pub backend: String,
+    /// Whether searches can run now
+    pub ready: bool,
+    /// Modes the backend supports; others fall back to the first
+    pub modes: Vec<SearchMode>,
+    /// Whether searches can be scoped to a collection
+    pub collections: bool,
+    /// Why the backend isn't ready, or which qmd binary is used
+    pub detail: Option<String>,
+}
+
+/// A collection searches can be scoped to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collection {
+    pub name: String,
+    /// Directory the collection indexes, when the backend reports it
+    pub root: Option<PathBuf>,
+}
+
+/// A search engine the search commands can dispatch to.
+pub trait SearchBackend: Send + Sync {
+    fn status(&self) -> SearchStatus;
+
+    /// Run a search. Engine failures the user should see (e.g. a bad query
+    /// or a timeout) come back as a response with `error` set; `Err` means
+    /// the engine couldn't be run at all. Slow backends should give up with
+    /// a "cancelled" response once `cancel` is set.
+    fn search(
+        &self,
+        request: &SearchRequest,
+        cancel: &AtomicBool,
+    ) -> Result<QmdSearchResponse, String>;
+
+    /// The collections searches can be scoped to.
+    fn list_collections(&self) -> Result<Vec<Collection>, String>;
+
+    /// Rebuild the backend's index from the documents on disk.
+    fn reindex(&self) -> Result<(), String>;
+}
+
+/// Run a search the way `qmd_search` does: blank queries return nothing
+/// without reaching the backend, an empty or "all" collection searches
+/// everything, and the time limit follows the mode.
+pub fn run_search(
+    backend: &dyn SearchBackend,
+    query: &str,
+    mode: &str,
+    collection: Option<String>,
+    limit: Option<u32>,
+    cancel: &AtomicBool,
+) -> Result<QmdSearchResponse, String> {
+    if query.trim().is_empty() {
+        return Ok(QmdSearchResponse::empty());
+    }
+    let mode = SearchMode::from_name(mode);
+    let request = SearchRequest {
+        query: query.to_string(),
+        mode,
+        collection: collection.filter(|c| !c.is_empty() && c != "all"),
+        limit: limit.unwrap_or(DEFAULT_LIMIT),
+        timeout: mode.timeout(),
+    };
+    backend.search(&request, cancel)
+}
+
+/// Rebuild the backend's index the way `reindex_search` does, returning the
+/// backend's status afterwards.
+pub fn run_reindex(backend: &dyn SearchBackend) -> Result<SearchStatus, String> {
+    backend.reindex()?;
+    Ok(backend.status())
+}
+
+// ---------------------------------------------------------------------------
+// qmd CLI
+// ---------------------------------------------------------------------------
+
+/// The qmd command-line tool. Modes and collections are offered as far as
+/// the installed version supports them.
+pub struct QmdCli {
+    bin: PathBuf,
+    features: QmdFeatures,
+}
+
+impl QmdCli {
+    pub fn new(bin: PathBuf, features: QmdFeatures) -> Self {
+        QmdCli { bin, features }
+    }
+
+    fn command(&self) -> Command {
+        Command::new(&self.bin)
+    }
+
+    fn supports(&self, mode: SearchMode) -> bool {
+        match mode {
+            SearchMode::Keyword => true,
+            SearchMode::Semantic => self.features.vsearch,
+            SearchMode::Hybrid => self.features.query,
+        }
+    }
+}
+
+impl SearchBackend for QmdCli {
+    fn status(&self) -> SearchStatus {
+        SearchStatus {
+            backend: "qmd".to_string(),
+            ready: true,
+            modes: SearchMode::ALL
+                .into_iter()
+                .filter(|mode| self.supports(*mode))
+                .collect(),
+            collections: self.features.collections,
+            detail: Some(self.bin.to_string_lossy().to_string()),
+        }
+    }
+
+    fn search(
+        &self,
+        request: &SearchRequest,
+        cancel: &AtomicBool,
+    ) -> Result<QmdSearchResponse, String> {
+        // Map mode to qmd subcommand
+        let subcmd = match request.mode {
+            SearchMode::Keyword => "search",
+            SearchMode::Semantic => "vsearch",
+            SearchMode::Hybrid => "query",
+        };
+        if !self.supports(request.mode) {
+            let message = format!("This version of qmd has no `{}` command", subcmd);
+            return Ok(QmdSearchResponse::failed(SearchErrorKind::Failed, message));
+        }
+
+        let mut cmd = self.command();
+        cmd.arg(subcmd)
+            .arg(&request.query)
+            .arg("--json")
+            .arg("-n")
+            .arg(request.limit.to_string());
+        if let Some(collection) = &request.collection {
+            cmd.arg("-c").arg(collection);
+        }
+
+        let output = match run_limited(cmd, request.timeout, cancel) {
+            Ok(output) => output,
+            Err(RunError::Failed(e)) => return Err(format!("Failed to execute qmd: {}", e)),
+            Err(RunError::TimedOut) => {
+                let message = format!(
+                    "qmd took longer than {}s and was stopped",
+                    request.timeout.as_secs()
+                );
+                return Ok(QmdSearchResponse::failed(SearchErrorKind::Timeout, message));
+            }
+            Err(RunError::Cancelled) => {
+                let message = "Search cancelled".to_string();
+                return Ok(QmdSearchResponse::failed(
+                    SearchErrorKind::Cancelled,
+                    message,
+                ));
+            }
+        };
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = format!("qmd error: {}", stderr.trim());
+            return Ok(QmdSearchResponse::failed(SearchErrorKind::Failed, message));
+        }
+
+        let results = parse_qmd_results(&String::from_utf8_lossy(&output.stdout));
+        Ok(QmdSearchResponse {
+            total: results.len(),
+            results,
+            ..QmdSearchResponse::empty()
+        })
+    }
+
+    fn list_collections(&self) -> Result<Vec<Collection>, String> {
+        if !self.features.collections {
+            return Ok(Vec::new());
+        }
+        let mut cmd = self.command();
+        cmd.arg("collection").arg("list");
+        let output = run_limited(cmd, COMMAND_TIMEOUT, &AtomicBool::new(false))
+            .map_err(|e| e.message("qmd collection list", COMMAND_TIMEOUT))?;
+
+        if !output.status.success() {
+            return Ok(Vec::new());
+        }
+
+        Ok(parse_collection_list(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+
+    fn reindex(&self) -> Result<(), String> {
+        let mut cmd = self.command();
+        cmd.arg("update");
+        let output = run_limited(cmd, REINDEX_TIMEOUT, &AtomicBool::new(false))
+            .map_err(|e| e.message("qmd update", REINDEX_TIMEOUT))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("qmd error: {}", stderr.trim()));
+        }
+        Ok(())
+    }
+}
+
+/// Receives the lines a process prints, as `run_streaming` reads them.
+pub(crate) type LineSink = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Why `run_limited` returned no output.
+pub(crate) enum RunError {
+    /// The process couldn't be started or waited for
+    Failed(String),
+    TimedOut,
+    Cancelled,
+}
+
+impl RunError {
+    /// Error message for a failed run of `what`, limited to `timeout`.
+    pub(crate) fn message(self, what: &str, timeout: Duration) -> String {
+        match self {
+            RunError::Failed(e) => format!("Failed to execute qmd: {}", e),
+            RunError::TimedOut => {
+                format!(
+                    "{} took longer than {}s and was stopped",
+                    what,
+                    timeout.as_secs()
+                )
+            }
+            RunError::Cancelled => format!("{} was cancelled", what),
+        }
+    }
+}
+
+/// Run `cmd` to completion and collect its output, killing it if it runs
+/// longer than `timeout` or `cancel` is set.
+pub(crate) fn run_limited(
+    cmd: Command,
+    timeout: Duration,
+    cancel: &AtomicBool,
+) -> Result<Output, RunError> {
+    run_streaming(cmd, timeout, cancel, None)
+}
+
+/// `run_limited`, also passing each line of output (stdout and stderr alike)
+/// to `on_line` as soon as it is printed.
+pub(crate) fn run_streaming(
+    mut cmd: Command,
+    timeout: Duration,
+    cancel: &AtomicBool,
+    on_line: Option<LineSink>,
+) -> Result<Output, RunError> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| RunError::Failed(e.to_string()))?;
+
+    // Drain the pipes on their own threads so a chatty process can't stall
+    // on a full pipe while we wait for it
+    let stdout = child.stdout.take().map(|pipe| drain(pipe, on_line.clone()));
+    let stderr = child.stderr.take().map(|pipe| drain(pipe, on_line));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        let stop = match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if cancel.load(Ordering::Relaxed) => RunError::Cancelled,
+            Ok(None) if Instant::now() >= deadline => RunError::TimedOut,
+            Ok(None) => {
+                thread::sleep(WAIT_INTERVAL);
+                continue;
+            }
+            Err(e) => RunError::Failed(e.to_string()),
+        };
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(stop);
+    };
+
+    let collect = |pipe: Option<JoinHandle<Vec<u8>>>| {
+        pipe.and_then(|handle| handle.join().ok())
+            .unwrap_or_default()
+    };
+    Ok(Output {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    })
+}
+
+/// Read `pipe` to the end on a new thread, passing each line to `on_line`
+/// as it arrives. Progress bars redraw their line after a '\r', so that ends
+/// a line too.
+fn drain(mut pipe: impl Read + Send + 'static, on_line: Option<LineSink>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let mut line_start = 0;
+        loop {
+            let read = match pipe.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            buf.extend_from_slice(&chunk[..read]);
+            if let Some(on_line) = &on_line {
+                while let Some(len) = buf[line_start..]
+                    .iter()
+                    .position(|b| *b == b'\n' || *b == b'\r')
+                {
+                    emit_line(on_line, &buf[line_start..line_start + len]);
+                    line_start += len + 1;
+                }
+            }
+        }
+        if let Some(on_line) = &on_line {
+            emit_line(on_line, &buf[line_start..]);
+        }
+        buf
+    })
+}
+
+/// Pass a line of output to `on_line`, without terminal color and cursor
+/// codes. Blank lines are dropped.
+fn emit_line(on_line: &LineSink, bytes: &[u8]) {
+    let raw = String::from_utf8_lossy(bytes);
+    let mut line = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Skip "ESC [ params letter"
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            line.push(c);
+        }
+    }
+    let line = line.trim_end();
+    if !line.trim().is_empty() {
+        on_line(line);
+    }
+}
+
+/// Parse the output of `qmd collection list`: one collection per line,
+/// optionally followed by indented "Key: value" detail lines. The
+/// collection's root is the first absolute path on its line or its details
+/// (e.g. "hq  ~/hq" or "  Path: /Users/me/hq"). Headers ending in ':' are
+/// skipped.
+pub fn parse_collection_list(output: &str) -> Vec<Collection> {
+    let mut collections: Vec<Collection> = Vec::new();
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let indented = line.starts_with(char::is_whitespace);
+        if let Some(current) = collections.last_mut().filter(|_| indented) {
+            if trimmed.contains(": ") {
+                if current.root.is_none() {
+                    current.root = find_path(trimmed);
+                }
+                continue;
+            }
+        }
+        if trimmed.ends_with(':') {
+            continue;
+        }
+        let mut words = trimmed.splitn(2, char::is_whitespace);
+        let name = words.next().unwrap_or_default();
+        let name = name
+            .trim_start_matches("qmd://")
+            .trim_end_matches(['/', ':']);
+        if name.is_empty() {
+            continue;
+        }
+        collections.push(Collection {
+            name: name.to_string(),
+            root: words.next().and_then(find_path),
+        });
+    }
+    collections
+}
+
+/// First absolute (or `~/`) path among the words of `text`.
+fn find_path(text: &str) -> Option<PathBuf> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c| matches!(c, '(' | ')' | '[' | ']' | '"' | '\'' | ',')))
+        .find_map(|word| {
+            if let Some(rest) = word.strip_prefix("~/") {
+                let home = std::env::var_os("HOME")?;
+                return Some(PathBuf::from(home).join(rest));
+            }
+            let path = PathBuf::from(word);
+            path.is_absolute().then_some(path)
+        })
+}
+
+/// Parse qmd's `--json` output: a JSON array of results, or one result per
+/// line (NDJSON). Lines that don't parse are skipped.
+pub fn parse_qmd_results(stdout: &str) -> Vec<QmdSearchResult> {
+    serde_json::from_str(stdout).unwrap_or_else(|_| {
+        stdout
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| serde_json::from_str::<QmdSearchResult>(line).ok())
+            .collect()
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Built-in index
+// ---------------------------------------------------------------------------
+
+/// The built-in index of one HQ (see `search_index`). Every mode runs as a
+/// keyword search, and collections don't apply: the index covers the whole
+/// scanned HQ. Results carry absolute paths.
+pub(crate) struct BuiltinSearch {
+    indexes: SearchIndexes,
+    /// HQ of the calling window; None before its first scan
+    hq_path: Option<String>,
+    /// Where the index is persisted
+    index_path: Option<PathBuf>,
+}
+
+impl BuiltinSearch {
+    pub(crate) fn new(
+        indexes: SearchIndexes,
+        hq_path: Option<String>,
+        index_path: Option<PathBuf>,
+    ) -> Self {
+        BuiltinSearch {
+            indexes,
+            hq_path,
+            index_path,
+        }
+    }
+
+    /// Why searches can't run yet, if they can't.
+    fn not_ready(&self) -> Option<String> {
+        let hq_path = match &self.hq_path {
+            Some(hq_path) => hq_path,
+            None => return Some("Search is available once the HQ folder has been scanned".into()),
+        };
+        let ready = self
+            .indexes
+            .lock()
+            .is_ok_and(|guard| guard.get(hq_path).is_some_and(|index| index.is_ready()));
+        (!ready).then(|| "The search index is still being built".to_string())
+    }
+}
+
+impl SearchBackend for BuiltinSearch {
+    fn status(&self) -> SearchStatus {
+        let detail = self.not_ready();
+        SearchStatus {
+            backend: "builtin".to_string(),
+            ready: detail.is_none(),
+            modes: vec![SearchMode::Keyword],
+            collections: false,
+            detail,
+        }
+    }
+
+    /// Searches take milliseconds, so they aren't worth cancelling.
+    fn search(
+        &self,
+        request: &SearchRequest,
+        _cancel: &AtomicBool,
+    ) -> Result<QmdSearchResponse, String> {
+        let guard = self
+            .indexes
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let index = self
+            .hq_path
+            .as_ref()
+            .and_then(|hq_path| guard.get(hq_path))
+            .filter(|index| index.is_ready());
+        let results = match index {
+            Some(index) => index.search(&request.query, request.limit as usize),
+            None => {
+                let message = self.not_ready().unwrap_or_default();
+                return Ok(QmdSearchResponse::failed(SearchErrorKind::Failed, message));
+            }
+        };
+        Ok(QmdSearchResponse {
+            total: results.len(),
+            results,
+            ..QmdSearchResponse::empty()
+        })
+    }
+
+    fn list_collections(&self) -> Result<Vec<Collection>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Re-read every indexed document. Documents the index doesn't know
+    /// yet are picked up by the next scan.
+    fn reindex(&self) -> Result<(), String> {
+        let hq_path = match &self.hq_path {
+            Some(hq_path) => hq_path,
+            None => return Err("The HQ folder hasn't been scanned yet".to_string()),
+        };
+        let (generation, documents) = self
+            .indexes
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?
+            .get_mut(hq_path)
+            .ok_or_else(|| "The search index is still being built".to_string())?
+            .begin_rebuild();
+
+        let read: Vec<IndexedDoc> = documents
+            .iter()
+            .filter_map(|p| IndexedDoc::read(p))
+            .collect();
+        let mut guard = self
+            .indexes
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let index = match guard.get_mut(hq_path) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        if index.finish_sync(generation, &documents, read) {
+            if let Some(path) = &self.index_path {
+                index.save(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Fake backend for tests
+// ---------------------------------------------------------------------------
+
+/// A scriptable backend for testing the command layer. Searches are answered
+/// from a queue of scripted responses, then from a fixed result list, and
+/// every request is recorded. A search whose cancel flag is already set
+/// returns a "cancelled" response.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeBackend {
+    status: Option<SearchStatus>,
+    results: Vec<QmdSearchResult>,
+    collections: Vec<Collection>,
+    scripted: Mutex<VecDeque<Result<QmdSearchResponse, String>>>,
+    requests: Mutex<Vec<SearchRequest>>,
+    reindexed: AtomicU32,
+}
+
+#[cfg(test)]
+impl FakeBackend {
+    pub fn new() -> Self {
+        FakeBackend::default()
+    }
+
+    /// Results every unscripted search returns (up to its limit).
+    pub fn with_results(mut self, results: Vec<QmdSearchResult>) -> Self {
+        self.results = results;
+        self
+    }
+
+    pub fn with_collections(mut self, collections: Vec<Collection>) -> Self {
+        self.collections = collections;
+        self
+    }
+
+    /// Status to report instead of a ready backend supporting everything.
+    pub fn with_status(mut self, status: SearchStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Queue the outcome of the next search.
+    pub fn script(&self, outcome: Result<QmdSearchResponse, String>) {
+        if let Ok(mut scripted) = self.scripted.lock() {
+            scripted.push_back(outcome);
+        }
+    }
+
+    /// Every search request received, oldest first.
+    pub fn requests(&self) -> Vec<SearchRequest> {
+        self.requests
+            .lock()
+            .map(|requests| requests.clone())
+            .unwrap_or_default()
+    }
+
+    /// Number of times `reindex` was called.
+    pub fn reindex_count(&self) -> u32 {
+        self.reindexed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+impl SearchBackend for FakeBackend {
+    fn status(&self) -> SearchStatus {
+        self.status.clone().unwrap_or_else(|| SearchStatus {
+            backend: "fake".to_string(),
+            ready: true,
+            modes: SearchMode::ALL.to_vec(),
+            collections: true,
+            detail: None,
+        })
+    }
+
+    fn search(
+        &self,
+        request: &SearchRequest,
+        cancel: &AtomicBool,
+    ) -> Result<QmdSearchResponse, String> {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(request.clone());
+        }
+        if cancel.load(Ordering::Relaxed) {
+            let message = "Search cancelled".to_string();
+            return Ok(QmdSearchResponse::failed(
+                SearchErrorKind::Cancelled,
+                message,
+            ));
+        }
+        if let Some(outcome) = self.scripted.lock().ok().and_then(|mut s| s.pop_front()) {
+            return outcome;
+        }
+        let results: Vec<QmdSearchResult> = self
+            .results
+            .iter()
+            .take(request.limit as usize)
+            .cloned()
+            .collect();
+        Ok(QmdSearchResponse {
+            total: results.len(),
+            results,
+            ..QmdSearchResponse::empty()
+        })
+    }
+
+    fn list_collections(&self) -> Result<Vec<Collection>, String> {
+        Ok(self.collections.clone())
+    }
+
+    fn reindex(&self) -> Result<(), String> {
+        self.reindexed.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(title: &str) -> QmdSearchResult {
+        QmdSearchResult {
+            title: title.to_string(),
+            file_path: format!("/hq/{}.md", title),
+            ..QmdSearchResult::default()
+        }
+    }
+
+    fn titles(response: &QmdSearchResponse) -> Vec<&str> {
+        response.results.iter().map(|r| r.title.as_str()).collect()
+    }
+
+    #[test]
+    fn blank_queries_do_not_reach_the_backend() {
+        let backend = FakeBackend::new().with_results(vec![result("a")]);
+        let cancel = AtomicBool::new(false);
+        let response = run_search(&backend, "  \t", "keyword", None, None, &cancel).unwrap();
+        assert!(response.results.is_empty());
+        assert_eq!(response.error, None);
+        assert!(backend.requests().is_empty());
+    }
+
+    #[test]
+    fn search_requests_follow_mode_collection_and_limit() {
+        let results = vec![result("a"), result("b"), result("c")];
+        let backend = FakeBackend::new().with_results(results);
+        let cancel = AtomicBool::new(false);
+
+        let response = run_search(&backend, "deploy", "semantic", None, Some(2), &cancel).unwrap();
+        assert_eq!(titles(&response), ["a", "b"]);
+        assert_eq!(response.total, 2);
+
+        let all = Some("all".to_string());
+        run_search(&backend, "deploy", "keyword", all, None, &cancel).unwrap();
+        let named = Some("notes".to_string());
+        run_search(&backend, "deploy", "bogus", named, None, &cancel).unwrap();
+
+        let requests = backend.requests();
+        let expected = [
+            (SearchMode::Semantic, None, 2),
+            (SearchMode::Keyword, None, DEFAULT_LIMIT),
+            (SearchMode::Hybrid, Some("notes"), DEFAULT_LIMIT),
+        ];
+        assert_eq!(requests.len(), expected.len());
+        for (request, (mode, collection, limit)) in requests.iter().zip(expected) {
+            assert_eq!(request.query, "deploy");
+            assert_eq!(request.mode, mode);
+            assert_eq!(request.collection.as_deref(), collection);
+            assert_eq!(request.limit, limit);
+            assert_eq!(request.timeout, mode.timeout());
+        }
+    }
+
+    #[test]
+    fn search_failures_are_passed_through() {
+        let backend = FakeBackend::new().with_results(vec![result("a")]);
+        let cancel = AtomicBool::new(false);
+        let timeout = QmdSearchResponse::failed(SearchErrorKind::Timeout, "too slow".to_string());
+        backend.script(Ok(timeout));
+        backend.script(Err("qmd not found".to_string()));
+
+        let response = run_search(&backend, "deploy", "hybrid", None, None, &cancel).unwrap();
+        assert_eq!(response.error_kind, Some(SearchErrorKind::Timeout));
+        assert_eq!(response.error.as_deref(), Some("too slow"));
+        let error = run_search(&backend, "deploy", "hybrid", None, None, &cancel).unwrap_err();
+        assert_eq!(error, "qmd not found");
+
+        // Scripted outcomes are used up; the fixed results answer again
+        let response = run_search(&backend, "deploy", "hybrid", None, None, &cancel).unwrap();
+        assert_eq!(titles(&response), ["a"]);
+
+        cancel.store(true, Ordering::Relaxed);
+        let response = run_search(&backend, "deploy", "hybrid", None, None, &cancel).unwrap();
+        assert_eq!(response.error_kind, Some(SearchErrorKind::Cancelled));
+        assert!(response.results.is_empty());
+    }
+
+    #[test]
+    fn reindex_returns_the_status_afterwards() {
+        let backend = FakeBackend::new().with_status(SearchStatus {
+            backend: "builtin".to_string(),
+            ready: true,
+            modes: vec![SearchMode::Keyword],
+            collections: false,
+            detail: None,
+        });
+        let status = run_reindex(&backend).unwrap();
+        assert_eq!(backend.reindex_count(), 1);
+        assert_eq!(status.backend, "builtin");
+        assert_eq!(status.modes, [SearchMode::Keyword]);
+        assert!(!status.collections);
+    }
+}
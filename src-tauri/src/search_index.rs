@@ -0,0 +1,941 @@
+//! Built-in full-text search, for when qmd isn't installed.
+//!
+//! Indexes the title, headings and body text of each scanned markdown
+//! document and answers keyword, `"phrase"` and `prefix*` queries ranked with
+//! BM25. Only each document's word counts are kept: phrase queries and
+//! snippets read the matching documents back from disk. The index is
+//! persisted under `<app data dir>/search-index/` (one JSON file per HQ root)
+//! and revalidated by mtime + size on the next scan, so only changed
+//! documents are re-read. Between scans the file watcher keeps the index
+//! current.
+
+use crate::formats::{self, DocFormat};
+use crate::front_matter::Fence;
+use crate::scan_cache::{fnv1a, mtime_ns};
+use crate::{FsChangeEvent, QmdSearchResult};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Bump when the on-disk layout changes; older indexes are discarded.
+const SEARCH_INDEX_VERSION: u32 = 1;
+
+/// Documents larger than this are indexed by title only.
+const MAX_INDEXED_BYTES: u64 = 2 * 1024 * 1024;
+
+/// BM25 term-frequency saturation.
+const K1: f64 = 1.2;
+
+/// BM25 document-length normalization.
+const B: f64 = 0.75;
+
+/// Indexed fields, as indices into per-field counts.
+const TITLE: usize = 0;
+const HEADINGS: usize = 1;
+const BODY: usize = 2;
+const FIELDS: usize = 3;
+
+/// A count for each field.
+type FieldCounts = [u32; FIELDS];
+
+/// How much a match in each field counts, relative to the body.
+const FIELD_BOOST: [f64; FIELDS] = [3.0, 2.0, 1.0];
+
+/// Characters of context kept on each side of a snippet's match.
+const SNIPPET_CONTEXT: usize = 80;
+
+/// The text of one document, as read for indexing.
+#[derive(Debug, Clone)]
+pub struct IndexedDoc {
+    /// Absolute path, as it appears in the scanned tree
+    pub path: String,
+    /// Modified time in nanoseconds since epoch
+    pub mtime_ns: u64,
+    /// File size in bytes
+    pub size: u64,
+    /// Front matter `title`, else the first `# ` heading
+    pub title: Option<String>,
+    /// Text of every heading, in document order
+    pub headings: Vec<String>,
+    /// Everything else after the front matter, markdown included
+    pub body: String,
+}
+
+impl IndexedDoc {
+    /// Read a document for indexing. Returns None for anything that isn't a
+    /// readable markdown file.
+    pub fn read(path: &Path) -> Option<Self> {
+        if !is_indexed(path) {
+            return None;
+        }
+        let metadata = fs::metadata(path).ok().filter(|m| m.is_file())?;
+        let mut doc = IndexedDoc {
+            path: path.to_string_lossy().to_string(),
+            mtime_ns: mtime_ns(&metadata),
+            size: metadata.len(),
+            title: formats::extract_md_info(path).title,
+            headings: Vec::new(),
+            body: String::new(),
+        };
+        if metadata.len() > MAX_INDEXED_BYTES {
+            return Some(doc);
+        }
+
+        let raw = fs::read(path).ok()?;
+        let text = String::from_utf8_lossy(&raw);
+        let lines: Vec<&str> = text.lines().collect();
+
+        // Skip a front matter block, unless it is never closed
+        let mut start = 0;
+        if let Some(fence) = lines.first().and_then(|line| Fence::detect(line)) {
+            if let Some(end) = lines.iter().skip(1).position(|line| fence.closes(line)) {
+                start = end + 2;
+            }
+        }
+
+        let mut in_code = false;
+        for line in &lines[start..] {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_code = !in_code;
+            }
+            match heading_text(line).filter(|_| !in_code) {
+                Some(heading) => doc.headings.push(heading.to_string()),
+                None => {
+                    doc.body.push_str(line);
+                    doc.body.push('\n');
+                }
+            }
+        }
+        Some(doc)
+    }
+
+    fn field(&self, field: usize) -> Cow<'_, str> {
+        match field {
+            TITLE => Cow::Borrowed(self.title.as_deref().unwrap_or("")),
+            HEADINGS => Cow::Owned(self.headings.join("\n")),
+            _ => Cow::Borrowed(&self.body),
+        }
+    }
+}
+
+/// Whether documents at `path` are indexed: markdown and MDX files.
+pub fn is_indexed(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(formats::for_file_name)
+        .is_some_and(|spec| matches!(spec.format, DocFormat::Markdown | DocFormat::Mdx))
+}
+
+/// Text of an ATX heading line (`## Text`), without its markers.
+fn heading_text(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    let rest = trimmed.trim_start_matches('#');
+    let level = trimmed.len() - rest.len();
+    let is_heading = (1..=6).contains(&level) && rest.starts_with([' ', '\t']);
+    is_heading.then(|| rest.trim().trim_end_matches('#').trim_end())
+}
+
+/// The persisted form of an index.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SavedIndex<D> {
+    version: u32,
+    /// HQ root this index belongs to
+    hq_root: String,
+    docs: Vec<D>,
+}
+
+/// An indexed document, as kept in memory and persisted.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    /// Absolute path, as it appears in the scanned tree
+    path: String,
+    /// Modified time in nanoseconds since epoch
+    mtime_ns: u64,
+    /// File size in bytes
+    size: u64,
+    title: Option<String>,
+    /// How often each of the document's words occurs in each field
+    terms: Vec<(String, FieldCounts)>,
+    /// Number of words in each field
+    #[serde(skip)]
+    lens: FieldCounts,
+}
+
+impl Entry {
+    fn new(doc: IndexedDoc) -> Self {
+        let mut counts: HashMap<String, FieldCounts> = HashMap::new();
+        for field in 0..FIELDS {
+            for (_, term) in tokenize(&doc.field(field)) {
+                counts.entry(term).or_default()[field] += 1;
+            }
+        }
+        let mut entry = Entry {
+            path: doc.path,
+            mtime_ns: doc.mtime_ns,
+            size: doc.size,
+            title: doc.title,
+            terms: counts.into_iter().collect(),
+            lens: [0; FIELDS],
+        };
+        entry.count_lens();
+        entry
+    }
+
+    fn count_lens(&mut self) {
+        self.lens = [0; FIELDS];
+        for (_, counts) in &self.terms {
+            for (len, count) in self.lens.iter_mut().zip(counts) {
+                *len += count;
+            }
+        }
+    }
+}
+
+/// One part of a query. A document must match every clause.
+enum Clause {
+    Term(String),
+    /// `word*`: any term starting with the word
+    Prefix(String),
+    /// `"some words"`: the words in order, or a word that splits into several
+    Phrase(Vec<String>),
+}
+
+/// The search index for one HQ root.
+pub struct SearchIndex {
+    hq_root: String,
+    docs: HashMap<u32, Entry>,
+    /// Document ID of each indexed path
+    ids: HashMap<String, u32>,
+    next_id: u32,
+    /// Per-field occurrence counts of each term, by document ID. Ordered so
+    /// prefix queries can walk a range.
+    terms: BTreeMap<String, HashMap<u32, FieldCounts>>,
+    /// Sum of each field's length over all documents
+    total_lens: [u64; FIELDS],
+    /// Whether the index holds anything yet: it was loaded from disk or a
+    /// sync has completed
+    ready: bool,
+    /// Changed since it was last saved
+    dirty: bool,
+    /// A save has been scheduled and not yet done (see `schedule_save`)
+    save_scheduled: bool,
+    /// Bumped by each `begin_sync`, so an outdated sync doesn't overwrite a newer one
+    sync_generation: u64,
+}
+
+impl SearchIndex {
+    /// Create an empty index for `hq_root`.
+    pub fn new(hq_root: &str) -> Self {
+        SearchIndex {
+            hq_root: hq_root.to_string(),
+            docs: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 0,
+            terms: BTreeMap::new(),
+            total_lens: [0; FIELDS],
+            ready: false,
+            dirty: false,
+            save_scheduled: false,
+            sync_generation: 0,
+        }
+    }
+
+    /// Load the index from disk. Returns None if it is missing, unreadable,
+    /// from an older version, or belongs to a different HQ root.
+    pub fn load(path: &Path, hq_root: &str) -> Option<Self> {
+        let raw = fs::read_to_string(path).ok()?;
+        let saved: SavedIndex<Entry> = serde_json::from_str(&raw).ok()?;
+        if saved.version != SEARCH_INDEX_VERSION || saved.hq_root != hq_root {
+            return None;
+        }
+        let mut index = SearchIndex::new(hq_root);
+        for mut entry in saved.docs {
+            entry.count_lens();
+            index.insert_entry(entry);
+        }
+        index.ready = true;
+        index.dirty = false;
+        Some(index)
+    }
+
+    /// Write the index to disk, creating its directory if needed. Writes to a
+    /// temp file first so a crash never leaves a torn index behind.
+    pub fn save(&mut self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create search index dir: {}", e))?;
+        }
+        let saved = SavedIndex {
+            version: SEARCH_INDEX_VERSION,
+            hq_root: self.hq_root.clone(),
+            docs: self.docs.values().collect(),
+        };
+        let json = serde_json::to_string(&saved)
+            .map_err(|e| format!("Failed to serialize search index: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| format!("Failed to write search index: {}", e))?;
+        fs::rename(&tmp, path).map_err(|e| format!("Failed to write search index: {}", e))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Note that a save has been scheduled, to be done with `save_if_dirty`.
+    /// Returns false if one already was, in which case it will save the
+    /// latest changes too.
+    pub fn schedule_save(&mut self) -> bool {
+        !std::mem::replace(&mut self.save_scheduled, true)
+    }
+
+    /// Save the index if it changed since it was last saved, completing the
+    /// scheduled save.
+    pub fn save_if_dirty(&mut self, path: &Path) -> Result<(), String> {
+        self.save_scheduled = false;
+        if !self.dirty {
+            return Ok(());
+        }
+        self.save(path)
+    }
+
+    /// Start syncing with a scan: returns the sync's generation and the
+    /// documents among `documents` that are new or changed since they were
+    /// indexed. Read those without holding the index, then pass them to
+    /// `finish_sync`.
+    pub fn begin_sync(&mut self, documents: &[PathBuf]) -> (u64, Vec<PathBuf>) {
+        self.sync_generation += 1;
+        let stale = documents
+            .iter()
+            .filter(|path| is_indexed(path))
+            .filter(|path| {
+                let indexed = self
+                    .ids
+                    .get(&*path.to_string_lossy())
+                    .and_then(|id| self.docs.get(id));
+                match (indexed, fs::metadata(path)) {
+                    (Some(entry), Ok(metadata)) => {
+                        entry.mtime_ns != mtime_ns(&metadata) || entry.size != metadata.len()
+                    }
+                    _ => true,
+                }
+            })
+            .cloned()
+            .collect();
+        (self.sync_generation, stale)
+    }
+
+    /// Start re-reading every indexed document: returns the sync's
+    /// generation and the documents' paths, to pass to `finish_sync` once read.
+    pub fn begin_rebuild(&mut self) -> (u64, Vec<PathBuf>) {
+        self.sync_generation += 1;
+        let documents = self.ids.keys().map(PathBuf::from).collect();
+        (self.sync_generation, documents)
+    }
+
+    /// Finish a sync started with `begin_sync` or `begin_rebuild`: drop documents that are no
+    /// longer in `documents` and index the ones that were re-read. Returns
+    /// false, changing nothing, if a newer sync has started since.
+    pub fn finish_sync(
+        &mut self,
+        generation: u64,
+        documents: &[PathBuf],
+        read: Vec<IndexedDoc>,
+    ) -> bool {
+        if generation != self.sync_generation {
+            return false;
+        }
+        let keep: HashSet<String> = documents
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        let gone: Vec<u32> = self
+            .docs
+            .iter()
+            .filter(|(_, entry)| !keep.contains(&entry.path))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in gone {
+            self.remove_id(id);
+        }
+        for doc in read {
+            self.insert(doc);
+        }
+        self.ready = true;
+        true
+    }
+
+    /// Apply a change reported by the file watcher. Documents are indexed
+    /// under their path on disk, so a change inside a symlinked scope uses
+    /// its `real_path`/`real_from`. `documents_below` lists the documents now
+    /// inside a directory that was created or moved in.
+    pub fn apply_change(
+        &mut self,
+        change: &FsChangeEvent,
+        documents_below: impl FnOnce(&Path) -> Vec<PathBuf>,
+    ) {
+        let path = change.real_path.as_ref().unwrap_or(&change.path);
+        if change.kind == "remove" {
+            self.remove(path);
+            return;
+        }
+        if let Some(from) = change.real_from.as_ref().or(change.from.as_ref()) {
+            self.rename(from, path);
+        }
+
+        let path = Path::new(path);
+        if path.is_dir() {
+            if matches!(change.kind.as_str(), "create" | "rename") {
+                for document in documents_below(path) {
+                    self.update(&document);
+                }
+            }
+        } else if is_indexed(path) {
+            self.update(path);
+        }
+    }
+
+    /// Re-read the document at `path`, dropping it if it can't be read.
+    fn update(&mut self, path: &Path) {
+        match IndexedDoc::read(path) {
+            Some(doc) => self.insert(doc),
+            None => self.remove(&path.to_string_lossy()),
+        }
+    }
+
+    /// Index `doc`, replacing the entry for its path.
+    fn insert(&mut self, doc: IndexedDoc) {
+        self.insert_entry(Entry::new(doc));
+    }
+
+    fn insert_entry(&mut self, entry: Entry) {
+        if let Some(id) = self.ids.get(&entry.path).copied() {
+            self.remove_id(id);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+
+        for (term, counts) in &entry.terms {
+            self.terms
+                .entry(term.clone())
+                .or_default()
+                .insert(id, *counts);
+        }
+        for (total, len) in self.total_lens.iter_mut().zip(entry.lens) {
+            *total += len as u64;
+        }
+        self.ids.insert(entry.path.clone(), id);
+        self.docs.insert(id, entry);
+        self.dirty = true;
+    }
+
+    fn remove_id(&mut self, id: u32) {
+        let entry = match self.docs.remove(&id) {
+            Some(entry) => entry,
+            None => return,
+        };
+        self.ids.remove(&entry.path);
+        for (term, _) in &entry.terms {
+            if let Some(postings) = self.terms.get_mut(term) {
+                postings.remove(&id);
+                if postings.is_empty() {
+                    self.terms.remove(term);
+                }
+            }
+        }
+        for (total, len) in self.total_lens.iter_mut().zip(entry.lens) {
+            *total -= len as u64;
+        }
+        self.dirty = true;
+    }
+
+    /// Drop `path` and everything below it.
+    fn remove(&mut self, path: &str) {
+        let prefix = format!("{}/", path);
+        let gone: Vec<u32> = self
+            .ids
+            .iter()
+            .filter(|(p, _)| *p == path || p.starts_with(&prefix))
+            .map(|(_, id)| *id)
+            .collect();
+        for id in gone {
+            self.remove_id(id);
+        }
+    }
+
+    /// Move `from` and everything below it to `to`. Content is unchanged,
+    /// so only the paths are updated.
+    fn rename(&mut self, from: &str, to: &str) {
+        let prefix = format!("{}/", from);
+        let moved: Vec<String> = self
+            .ids
+            .keys()
+            .filter(|p| *p == from || p.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for old in moved {
+            let new = format!("{}{}", to, &old[from.len()..]);
+            // Anything already at the new path is replaced
+            if let Some(id) = self.ids.get(&new).copied() {
+                self.remove_id(id);
+            }
+            if let Some(id) = self.ids.remove(&old) {
+                if let Some(entry) = self.docs.get_mut(&id) {
+                    entry.path = new.clone();
+                }
+                self.ids.insert(new, id);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Run `query` and return up to `limit` results, best first.
+    ///
+    /// Words are matched case-insensitively and all of them must occur.
+    /// `"quoted words"` must occur in that order and `word*` matches any word
+    /// starting with `word`. Ranking is BM25 over title, headings and body,
+    /// with title and heading matches weighted higher.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<QmdSearchResult> {
+        let clauses = parse_query(query);
+        if clauses.is_empty() || self.docs.is_empty() {
+            return Vec::new();
+        }
+
+        let doc_count = self.docs.len() as f64;
+        let avg_lens = self
+            .total_lens
+            .map(|total| (total as f64 / doc_count).max(1.0));
+        let mut scores: Option<HashMap<u32, f64>> = None;
+        for clause in &clauses {
+            let matched = self.matches(clause);
+            let n = matched.len() as f64;
+            let idf = ((doc_count - n + 0.5) / (n + 0.5)).ln_1p();
+            let clause_scores: HashMap<u32, f64> = matched
+                .into_iter()
+                .filter_map(|(id, counts)| {
+                    let entry = self.docs.get(&id)?;
+                    let score: f64 = (0..FIELDS)
+                        .filter(|&field| counts[field] > 0)
+                        .map(|field| {
+                            let tf = counts[field] as f64;
+                            let norm = 1.0 - B + B * entry.lens[field] as f64 / avg_lens[field];
+                            FIELD_BOOST[field] * tf * (K1 + 1.0) / (tf + K1 * norm)
+                        })
+                        .sum();
+                    Some((id, idf * score))
+                })
+                .collect();
+            scores = Some(match scores {
+                None => clause_scores,
+                Some(previous) => previous
+                    .into_iter()
+                    .filter_map(|(id, score)| Some((id, score + clause_scores.get(&id)?)))
+                    .collect(),
+            });
+        }
+
+        let mut ranked: Vec<(&Entry, f64)> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(id, score)| Some((self.docs.get(&id)?, score)))
+            .collect();
+        ranked.sort_by(|(a, a_score), (b, b_score)| {
+            b_score.total_cmp(a_score).then_with(|| a.path.cmp(&b.path))
+        });
+        ranked.truncate(limit);
+        ranked
+            .into_iter()
+            .map(|(entry, score)| {
+                let path = Path::new(&entry.path);
+                let title = entry.title.clone().unwrap_or_else(|| {
+                    let name = path.file_stem().unwrap_or_default();
+                    name.to_string_lossy().to_string()
+                });
+                // Read back from disk; a document that can't be read any more
+                // is still listed, without a snippet
+                let body = IndexedDoc::read(path).map(|doc| doc.body);
+                QmdSearchResult {
+                    doc_id: format!("#{:06x}", fnv1a(entry.path.as_bytes()) & 0xff_ffff),
+                    // Squash the unbounded BM25 score into 0..1, as qmd reports
+                    score: score / (1.0 + score),
+                    title,
+                    file_path: entry.path.clone(),
+                    snippet: body
+                        .map(|body| snippet(&body, &clauses))
+                        .unwrap_or_default(),
+                    ..QmdSearchResult::default()
+                }
+            })
+            .collect()
+    }
+
+    /// Per-field match counts of `clause` in each document it matches.
+    fn matches(&self, clause: &Clause) -> HashMap<u32, FieldCounts> {
+        match clause {
+            Clause::Term(term) => self.terms.get(term).cloned().unwrap_or_default(),
+            Clause::Prefix(prefix) => {
+                let mut matched: HashMap<u32, FieldCounts> = HashMap::new();
+                let terms = self
+                    .terms
+                    .range(prefix.clone()..)
+                    .take_while(|(term, _)| term.starts_with(prefix.as_str()));
+                for (_, postings) in terms {
+                    for (id, counts) in postings {
+                        let total = matched.entry(*id).or_default();
+                        for (total, count) in total.iter_mut().zip(counts) {
+                            *total += count;
+                        }
+                    }
+                }
+                matched
+            }
+            Clause::Phrase(words) => {
+                // Only documents with every word can contain the phrase, and
+                // only those are read back to look for it
+                let mut candidates: Option<HashSet<u32>> = None;
+                for word in words {
+                    let ids: HashSet<u32> = match self.terms.get(word) {
+                        Some(postings) => postings.keys().copied().collect(),
+                        None => return HashMap::new(),
+                    };
+                    candidates = Some(match candidates {
+                        None => ids,
+                        Some(previous) => previous.intersection(&ids).copied().collect(),
+                    });
+                }
+                candidates
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|id| {
+                        let doc = IndexedDoc::read(Path::new(&self.docs.get(&id)?.path))?;
+                        let counts = [TITLE, HEADINGS, BODY]
+                            .map(|field| count_phrase(&doc.field(field), words));
+                        counts
+                            .iter()
+                            .any(|&count| count > 0)
+                            .then_some((id, counts))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Location of the index file for `hq_root` inside the app data dir.
+pub fn index_file_path(app_data_dir: &Path, hq_root: &str) -> PathBuf {
+    app_data_dir
+        .join("search-index")
+        .join(format!("{:016x}.json", fnv1a(hq_root.as_bytes())))
+}
+
+/// The lowercased words of `text` (runs of letters and digits), each with
+/// its byte range.
+fn tokenize(text: &str) -> Vec<(Range<usize>, String)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push((s..i, text[s..i].to_lowercase()));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s..text.len(), text[s..].to_lowercase()));
+    }
+    tokens
+}
+
+/// Split a query into clauses: `"quoted"` parts are phrases, words ending in
+/// `*` are prefixes, and everything else is a plain word.
+fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    // Odd-numbered parts are inside quotes
+    for (i, part) in query.split('"').enumerate() {
+        if i % 2 == 1 {
+            clauses.extend(clause_for(words(part), false));
+            continue;
+        }
+        for word in part.split_whitespace() {
+            clauses.extend(clause_for(words(word), word.ends_with('*')));
+        }
+    }
+    clauses
+}
+
+fn words(text: &str) -> Vec<String> {
+    tokenize(text).into_iter().map(|(_, word)| word).collect()
+}
+
+fn clause_for(mut words: Vec<String>, prefix: bool) -> Option<Clause> {
+    match words.len() {
+        0 => None,
+        1 if prefix => Some(Clause::Prefix(words.remove(0))),
+        1 => Some(Clause::Term(words.remove(0))),
+        _ => Some(Clause::Phrase(words)),
+    }
+}
+
+/// Number of times the words of `phrase` occur in order in `text`.
+fn count_phrase(text: &str, phrase: &[String]) -> u32 {
+    words(text)
+        .windows(phrase.len())
+        .filter(|window| *window == phrase)
+        .count() as u32
+}
+
+/// A one-line excerpt of `body` around the first place a clause matches, or
+/// its opening if none does (e.g. the match was in the title).
+fn snippet(body: &str, clauses: &[Clause]) -> String {
+    let tokens = tokenize(body);
+    let hit = tokens.iter().enumerate().find_map(|(i, (range, token))| {
+        let matched = clauses.iter().any(|clause| match clause {
+            Clause::Term(term) => token == term,
+            Clause::Prefix(prefix) => token.starts_with(prefix.as_str()),
+            Clause::Phrase(words) => tokens[i..]
+                .iter()
+                .map(|(_, token)| token)
+                .take(words.len())
+                .eq(words.iter()),
+        });
+        matched.then(|| range.clone())
+    });
+
+    let (start, end) = hit
+        .as_ref()
+        .map_or((0, 0), |range| (range.start, range.end));
+    let before = if hit.is_some() { SNIPPET_CONTEXT } else { 0 };
+    let after = 2 * SNIPPET_CONTEXT - before;
+    let mut from = body[..start]
+        .char_indices()
+        .rev()
+        .nth(before.saturating_sub(1))
+        .map_or(0, |(i, _)| i);
+    let mut to = body[end..]
+        .char_indices()
+        .nth(after)
+        .map_or(body.len(), |(i, _)| end + i);
+    // Don't cut words in half
+    if from > 0 {
+        from = body[from..start]
+            .find(char::is_whitespace)
+            .map_or(from, |i| from + i);
+    }
+    if to < body.len() {
+        to = body[end..to]
+            .rfind(char::is_whitespace)
+            .map_or(to, |i| end + i);
+    }
+
+    let mut text = body[from..to]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !body[..from].trim().is_empty() {
+        text.insert(0, '…');
+    }
+    if !body[to..].trim().is_empty() {
+        text.push('…');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(path: &str, title: Option<&str>, headings: &[&str], body: &str) -> IndexedDoc {
+        IndexedDoc {
+            path: path.to_string(),
+            mtime_ns: 0,
+            size: body.len() as u64,
+            title: title.map(str::to_string),
+            headings: headings.iter().map(|h| h.to_string()).collect(),
+            body: body.to_string(),
+        }
+    }
+
+    fn paths(results: &[QmdSearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.file_path.as_str()).collect()
+    }
+
+    /// File names of `results`, in order.
+    fn names(results: &[QmdSearchResult]) -> Vec<&str> {
+        results
+            .iter()
+            .map(|r| r.file_path.rsplit('/').next().unwrap_or_default())
+            .collect()
+    }
+
+    fn change(kind: &str, path: &Path, real_path: Option<&Path>) -> FsChangeEvent {
+        let string = |path: &Path| path.to_string_lossy().to_string();
+        FsChangeEvent {
+            path: string(path),
+            kind: kind.to_string(),
+            from: None,
+            real_path: real_path.map(string),
+            real_from: None,
+            hash: None,
+            title: None,
+            mtime: None,
+        }
+    }
+
+    /// An index of four documents in `dir`, which are written there too so
+    /// phrase queries and snippets can read them back.
+    fn sample_index(dir: &Path) -> SearchIndex {
+        let docs = [
+            ("guide.md", Some("Deploy Guide"), &[][..], "How we ship.\n"),
+            ("notes.md", None, &["Deploy"][..], "Notes.\n"),
+            ("log.md", None, &[][..], "Friday we had a deploy.\n"),
+            ("other.md", None, &[][..], "Unrelated text.\n"),
+        ];
+        let mut index = SearchIndex::new("/hq");
+        for (name, title, headings, body) in docs {
+            let path = dir.join(name);
+            let text: String = headings.iter().map(|h| format!("## {}\n", h)).collect();
+            fs::write(&path, text + body).unwrap();
+            index.insert(doc(&path.to_string_lossy(), title, headings, body));
+        }
+        index
+    }
+
+    #[test]
+    fn tokenize_lowercases_words_with_byte_ranges() {
+        let tokens = tokenize("Deploy the API-v2, naïve!");
+        let expected = [
+            (0..6, "deploy"),
+            (7..10, "the"),
+            (11..14, "api"),
+            (15..17, "v2"),
+            (19..25, "naïve"),
+        ];
+        assert_eq!(tokens.len(), expected.len());
+        for ((range, word), (expected_range, expected_word)) in tokens.iter().zip(expected) {
+            assert_eq!(range, &expected_range);
+            assert_eq!(word, expected_word);
+        }
+        assert!(tokenize(" -- ").is_empty());
+    }
+
+    #[test]
+    fn query_splits_into_terms_phrases_and_prefixes() {
+        let clauses = parse_query("Deploy \"roll back\" conf* \"single\"");
+        assert_eq!(clauses.len(), 4);
+        assert!(matches!(&clauses[0], Clause::Term(t) if t == "deploy"));
+        assert!(matches!(&clauses[1], Clause::Phrase(w) if w == &["roll", "back"]));
+        assert!(matches!(&clauses[2], Clause::Prefix(p) if p == "conf"));
+        assert!(matches!(&clauses[3], Clause::Term(t) if t == "single"));
+        assert!(parse_query(" * \"\" ").is_empty());
+    }
+
+    #[test]
+    fn title_and_heading_matches_rank_above_body_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = sample_index(dir.path());
+        let results = index.search("deploy", 10);
+        assert_eq!(names(&results), ["guide.md", "notes.md", "log.md"]);
+        for pair in results.windows(2) {
+            assert!(pair[0].score > pair[1].score);
+        }
+        assert!(results.iter().all(|r| r.score > 0.0 && r.score < 1.0));
+        assert_eq!(results[2].snippet, "Friday we had a deploy.");
+
+        assert_eq!(names(&index.search("deploy", 1)), ["guide.md"]);
+    }
+
+    #[test]
+    fn every_clause_must_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = sample_index(dir.path());
+        assert_eq!(names(&index.search("deploy friday", 10)), ["log.md"]);
+        assert_eq!(names(&index.search("\"had a deploy\"", 10)), ["log.md"]);
+        assert!(index.search("\"deploy had\"", 10).is_empty());
+        assert_eq!(names(&index.search("UNREL*", 10)), ["other.md"]);
+        assert!(index.search("deploy missing", 10).is_empty());
+    }
+
+    #[test]
+    fn saved_indexes_keep_word_counts_but_not_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = sample_index(dir.path());
+        let file = dir.path().join("index/hq.json");
+        assert!(index.schedule_save());
+        assert!(!index.schedule_save());
+        index.save_if_dirty(&file).unwrap();
+        assert!(!index.is_dirty());
+        // Saving completes the scheduled save
+        assert!(index.schedule_save());
+
+        let saved = fs::read_to_string(&file).unwrap();
+        assert!(saved.contains("\"friday\""));
+        assert!(!saved.contains("Friday we had"));
+
+        let loaded = SearchIndex::load(&file, "/hq").unwrap();
+        assert!(loaded.is_ready() && !loaded.is_dirty());
+        let results = loaded.search("deploy", 10);
+        assert_eq!(names(&results), ["guide.md", "notes.md", "log.md"]);
+        assert_eq!(results[2].snippet, "Friday we had a deploy.");
+        assert!(SearchIndex::load(&file, "/other").is_none());
+
+        // A document deleted since it was indexed is listed without a snippet,
+        // but phrases can no longer be found in it
+        fs::remove_file(dir.path().join("log.md")).unwrap();
+        let results = loaded.search("friday", 10);
+        assert_eq!(names(&results), ["log.md"]);
+        assert_eq!(results[0].snippet, "");
+        assert!(loaded.search("\"had a deploy\"", 10).is_empty());
+    }
+
+    #[test]
+    fn apply_change_follows_real_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        let alias = dir.path().join("alias");
+        fs::create_dir_all(real.join("docs")).unwrap();
+        fs::write(real.join("docs/a.md"), "# Alpha\nFirst document.\n").unwrap();
+
+        let mut index = SearchIndex::new("/hq");
+        let created = change("create", &alias.join("docs"), Some(&real.join("docs")));
+        index.apply_change(&created, |dir| vec![dir.join("a.md")]);
+        let indexed = real.join("docs/a.md").to_string_lossy().to_string();
+        assert_eq!(paths(&index.search("alpha", 10)), [indexed.as_str()]);
+
+        fs::rename(real.join("docs"), real.join("moved")).unwrap();
+        let mut renamed = change("rename", &alias.join("moved"), Some(&real.join("moved")));
+        renamed.from = Some(alias.join("docs").to_string_lossy().to_string());
+        renamed.real_from = Some(real.join("docs").to_string_lossy().to_string());
+        index.apply_change(&renamed, |_| Vec::new());
+        let moved = real.join("moved/a.md").to_string_lossy().to_string();
+        assert_eq!(paths(&index.search("alpha", 10)), [moved.as_str()]);
+
+        fs::write(real.join("moved/a.md"), "# Beta\n").unwrap();
+        let modified = change(
+            "modify",
+            &alias.join("moved/a.md"),
+            Some(&real.join("moved/a.md")),
+        );
+        index.apply_change(&modified, |_| Vec::new());
+        assert!(index.search("alpha", 10).is_empty());
+        assert_eq!(paths(&index.search("beta", 10)), [moved.as_str()]);
+
+        let removed = change("remove", &alias.join("moved"), Some(&real.join("moved")));
+        index.apply_change(&removed, |_| Vec::new());
+        assert!(index.search("beta", 10).is_empty());
+        assert!(index.is_dirty());
+    }
+}
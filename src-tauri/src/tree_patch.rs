@@ -0,0 +1,202 @@
+//! Incremental updates to a scanned tree.
+//!
+//! The watcher rescans only the entry a change touched and merges the result
+//! into the last scanned tree here. Every change to the tree is recorded in a
+//! `TreePatch`, which is sent to the frontend as a `"tree-patch"` event so it
+//! can update its copy without a full rescan.
+
+use crate::FileTreeNode;
+use serde::Serialize;
+use std::cmp::Ordering;
+
+/// A node added to the tree.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertedNode {
+    /// Path of the directory the node was added to
+    pub parent_path: String,
+    /// Position among the parent's children, once earlier inserts are applied
+    pub index: usize,
+    /// The new node with its full subtree
+    pub node: FileTreeNode,
+}
+
+/// Payload of `"tree-patch"` events. Apply `removed` first, then `inserted`
+/// in order, then `updated`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreePatch {
+    pub inserted: Vec<InsertedNode>,
+    /// Paths of nodes removed along with their subtrees
+    pub removed: Vec<String>,
+    /// Nodes whose own fields changed (e.g. a new title, or a `file_count`
+    /// rolled up from below). Directories are sent without `children`; keep
+    /// the children you have.
+    pub updated: Vec<FileTreeNode>,
+}
+
+impl TreePatch {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+
+    fn remove(&mut self, node: FileTreeNode) {
+        // Changes below a removed node no longer matter
+        let prefix = format!("{}/", node.path);
+        self.inserted
+            .retain(|i| i.parent_path != node.path && !i.parent_path.starts_with(&prefix));
+        self.removed.retain(|p| !p.starts_with(&prefix));
+        self.updated
+            .retain(|u| u.path != node.path && !u.path.starts_with(&prefix));
+        self.removed.push(node.path);
+    }
+
+    fn insert(&mut self, parent_path: &str, index: usize, node: FileTreeNode) {
+        self.inserted.push(InsertedNode {
+            parent_path: parent_path.to_string(),
+            index,
+            node,
+        });
+    }
+
+    fn update(&mut self, node: &FileTreeNode) {
+        self.updated.retain(|u| u.path != node.path);
+        self.updated.push(shallow(node));
+    }
+}
+
+/// Merge a rescanned entry into `root`.
+///
+/// `dirs` names the directories from `root` down to the entry's parent (all
+/// already in the tree), `name` is the entry itself, and `new` is its rescan
+/// result (None if it is gone or no longer holds documents). Counts are
+/// rolled up the ancestor chain, and directories left without documents are
+/// pruned, except `root` itself.
+pub fn apply_entry(
+    root: &mut FileTreeNode,
+    dirs: &[String],
+    name: &str,
+    new: Option<FileTreeNode>,
+    patch: &mut TreePatch,
+) {
+    match dirs.split_first() {
+        Some((first, rest)) => {
+            let index = match root.children.iter().position(|c| c.name == *first) {
+                Some(index) => index,
+                None => return,
+            };
+            apply_entry(&mut root.children[index], rest, name, new, patch);
+            let child = &root.children[index];
+            if child.file_count == 0 && !child.truncated && child.link_kind.is_none() {
+                let child = root.children.remove(index);
+                patch.remove(child);
+            }
+        }
+        None => {
+            let old = root.children.iter().position(|c| c.name == name);
+            match (old, new) {
+                (None, None) => {}
+                (Some(index), None) => {
+                    let old = root.children.remove(index);
+                    patch.remove(old);
+                }
+                (Some(index), Some(node)) if same_kind(&root.children[index], &node) => {
+                    diff_node(&mut root.children[index], node, patch);
+                }
+                (old, Some(node)) => {
+                    if let Some(index) = old {
+                        let old = root.children.remove(index);
+                        patch.remove(old);
+                    }
+                    let index = insert_index(&root.children, &node);
+                    patch.insert(&root.path, index, node.clone());
+                    root.children.insert(index, node);
+                }
+            }
+        }
+    }
+    roll_up(root, patch);
+}
+
+/// Replace `old` with `new` (same path and kind), recording the difference.
+fn diff_node(old: &mut FileTreeNode, mut new: FileTreeNode, patch: &mut TreePatch) {
+    if !old.is_directory || old.link_kind.is_some() {
+        if *old != new {
+            *old = new;
+            patch.update(old);
+        }
+        return;
+    }
+
+    let mut old_children = std::mem::take(&mut old.children);
+    old_children.retain(|child| {
+        let kept = new
+            .children
+            .iter()
+            .any(|n| n.name == child.name && same_kind(child, n));
+        if !kept {
+            patch.remove(child.clone());
+        }
+        kept
+    });
+
+    let mut children = Vec::with_capacity(new.children.len());
+    for (index, child) in std::mem::take(&mut new.children).into_iter().enumerate() {
+        match old_children.iter().position(|c| c.name == child.name) {
+            Some(i) => {
+                let mut existing = old_children.remove(i);
+                diff_node(&mut existing, child, patch);
+                children.push(existing);
+            }
+            None => {
+                patch.insert(&old.path, index, child.clone());
+                children.push(child);
+            }
+        }
+    }
+
+    let own_changed = shallow(old) != new;
+    new.children = children;
+    *old = new;
+    if own_changed {
+        patch.update(old);
+    }
+}
+
+/// Recompute a directory's counts from its children, recording a change.
+fn roll_up(dir: &mut FileTreeNode, patch: &mut TreePatch) {
+    let file_count = dir
+        .children
+        .iter()
+        .map(|c| if c.is_directory { c.file_count } else { 1 })
+        .sum();
+    let child_count = dir.children.len() as u32;
+    if dir.file_count != file_count || dir.child_count != child_count {
+        dir.file_count = file_count;
+        dir.child_count = child_count;
+        patch.update(dir);
+    }
+}
+
+/// Whether two nodes for the same name can be diffed in place.
+fn same_kind(a: &FileTreeNode, b: &FileTreeNode) -> bool {
+    a.is_directory == b.is_directory && a.link_kind == b.link_kind
+}
+
+/// Where `node` goes among `children`, in scanner order (directories
+/// first, then alphabetical).
+fn insert_index(children: &[FileTreeNode], node: &FileTreeNode) -> usize {
+    let key = |n: &FileTreeNode| (!n.is_directory, n.name.clone());
+    children
+        .iter()
+        .position(|c| key(node).cmp(&key(c)) == Ordering::Less)
+        .unwrap_or(children.len())
+}
+
+/// A copy of `node` without its children.
+fn shallow(node: &FileTreeNode) -> FileTreeNode {
+    FileTreeNode {
+        children: Vec::new(),
+        ..node.clone()
+    }
+}
@@ -0,0 +1,366 @@
+//! Native and polling file watchers behind one interface.
+//!
+//! inotify and FSEvents miss changes made on network shares (SMB, NFS) and in
+//! FUSE-based cloud-sync folders, since those changes never pass through the
+//! local kernel. Such directories are polled instead. Both kinds of watcher
+//! feed the same debounced event handler, so the `"fs-change"` events look the
+//! same either way.
+
+use crate::{WatchedDir, WatcherError, WatcherStatus};
+use notify::{PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use notify_debouncer_full::{new_debouncer_opt, DebounceEventResult, Debouncer, RecommendedCache};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Debounce window for file-system events.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Default for `WatchOptions::poll_interval_ms`.
+const DEFAULT_POLL_INTERVAL_MS: u64 = 2000;
+
+/// File systems whose changes may not produce native events: network
+/// shares and FUSE mounts, where most cloud-sync clients live.
+const UNRELIABLE_FS: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "afpfs", "webdav", "davfs", "ncpfs", "9p", "fuse",
+    "macfuse", "osxfuse",
+];
+
+/// How `start_watching` watches directories.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// OS file-system events only
+    Native,
+    /// Poll every directory for changes
+    Poll,
+    /// OS events, but poll directories on network or FUSE file systems and
+    /// directories a native watch fails for
+    #[default]
+    Auto,
+}
+
+/// Options for `start_watching`. All fields are optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchOptions {
+    /// How directories are watched (default: auto)
+    #[serde(default)]
+    pub mode: WatchMode,
+    /// How often polled directories are checked, in milliseconds (default: 2000)
+    pub poll_interval_ms: Option<u64>,
+    /// Schedule a qmd reindex when documents change (default: off)
+    #[serde(default)]
+    pub qmd_auto_index: bool,
+}
+
+impl WatchOptions {
+    /// How often polled directories are checked.
+    pub fn poll_interval(&self) -> Duration {
+        let interval = self.poll_interval_ms.unwrap_or(DEFAULT_POLL_INTERVAL_MS);
+        Duration::from_millis(interval.max(1))
+    }
+}
+
+/// The handler every debouncer of one `start_watching` reports to.
+pub type EventHandler = Arc<dyn Fn(DebounceEventResult) + Send + Sync>;
+
+/// The debouncers of one `start_watching`. Each is created the first time a
+/// directory needs it; dropping this stops them all.
+pub struct Watchers {
+    mode: WatchMode,
+    poll_interval: Duration,
+    handler: EventHandler,
+    native: Option<Debouncer<RecommendedWatcher, RecommendedCache>>,
+    poll: Option<Debouncer<PollWatcher, RecommendedCache>>,
+    /// The system's mounts, read the first time auto mode checks a directory
+    mounts: Option<MountTable>,
+}
+
+impl Watchers {
+    pub fn new(options: &WatchOptions, handler: EventHandler) -> Self {
+        Watchers {
+            mode: options.mode,
+            poll_interval: options.poll_interval(),
+            handler,
+            native: None,
+            poll: None,
+            mounts: None,
+        }
+    }
+
+    /// Watch `dir` (recursively or just its direct entries) and record the
+    /// outcome in `status`.
+    pub fn watch(
+        &mut self,
+        dir: &Path,
+        recursive: bool,
+        status: &mut WatcherStatus,
+    ) -> Result<(), WatcherError> {
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        let poll_reason = match self.mode {
+            WatchMode::Native => None,
+            WatchMode::Poll => Some("polling mode".to_string()),
+            WatchMode::Auto => self
+                .mounts
+                .get_or_insert_with(MountTable::read)
+                .unreliable_fs(dir),
+        };
+        let result = match poll_reason {
+            Some(reason) => self.watch_polling(dir, mode).map(|()| Some(reason)),
+            None => match self.watch_native(dir, mode) {
+                Ok(()) => Ok(None),
+                Err(e) if self.mode == WatchMode::Auto => self
+                    .watch_polling(dir, mode)
+                    .map(|()| Some(format!("native watch failed: {}", e))),
+                Err(e) => Err(e),
+            },
+        };
+
+        let path = dir.to_string_lossy().to_string();
+        match result {
+            Ok(poll_reason) => {
+                status.watched.push(WatchedDir {
+                    path,
+                    recursive,
+                    poll_reason,
+                });
+                Ok(())
+            }
+            Err(e) => {
+                let error = WatcherError {
+                    path: Some(path),
+                    message: format!("Failed to watch {}: {}", dir.display(), e),
+                };
+                status.failed.push(error.clone());
+                status.record_error(error.clone());
+                Err(error)
+            }
+        }
+    }
+
+    fn watch_native(&mut self, dir: &Path, mode: RecursiveMode) -> Result<(), String> {
+        let config = notify::Config::default();
+        debouncer(&mut self.native, &self.handler, config)?
+            .watch(dir, mode)
+            .map_err(|e| e.to_string())
+    }
+
+    fn watch_polling(&mut self, dir: &Path, mode: RecursiveMode) -> Result<(), String> {
+        let config = notify::Config::default().with_poll_interval(self.poll_interval);
+        debouncer(&mut self.poll, &self.handler, config)?
+            .watch(dir, mode)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// The debouncer in `slot`, created on first use.
+fn debouncer<'a, T: Watcher>(
+    slot: &'a mut Option<Debouncer<T, RecommendedCache>>,
+    handler: &EventHandler,
+    config: notify::Config,
+) -> Result<&'a mut Debouncer<T, RecommendedCache>, String> {
+    let debouncer = match slot.take() {
+        Some(debouncer) => debouncer,
+        None => {
+            let handler = handler.clone();
+            new_debouncer_opt(
+                DEBOUNCE,
+                None,
+                move |res: DebounceEventResult| handler(res),
+                RecommendedCache::new(),
+                config,
+            )
+            .map_err(|e| format!("Failed to create file watcher: {}", e))?
+        }
+    };
+    Ok(slot.insert(debouncer))
+}
+
+/// The mount points of the system and their file-system types.
+struct MountTable {
+    mounts: Vec<(PathBuf, String)>,
+}
+
+impl MountTable {
+    fn new(mounts: Vec<(PathBuf, String)>) -> Self {
+        MountTable { mounts }
+    }
+
+    /// Why native events can't be trusted for `dir`, if it is on a network or
+    /// FUSE file system.
+    ///
+    /// On Windows only UNC paths (`\\server\share`) are recognized; a share
+    /// mapped to a drive letter is watched natively unless polling mode is
+    /// chosen.
+    fn unreliable_fs(&self, dir: &Path) -> Option<String> {
+        #[cfg(windows)]
+        if dir.to_string_lossy().starts_with(r"\\?\UNC\") {
+            return Some("network share".to_string());
+        }
+
+        let fs_type = self.fs_type(dir)?;
+        is_unreliable(fs_type).then(|| format!("{} file system", fs_type))
+    }
+
+    /// File-system type of the mount `dir` is on (the longest mount point
+    /// containing it).
+    fn fs_type(&self, dir: &Path) -> Option<&str> {
+        self.mounts
+            .iter()
+            .filter(|(mount_point, _)| dir.starts_with(mount_point))
+            .max_by_key(|(mount_point, _)| mount_point.as_os_str().len())
+            .map(|(_, fs_type)| fs_type.as_str())
+    }
+}
+
+/// Whether changes on a file system of type `fs_type` may go unreported.
+fn is_unreliable(fs_type: &str) -> bool {
+    let name = fs_type.to_lowercase();
+    UNRELIABLE_FS.contains(&name.as_str()) || name.starts_with("fuse.")
+}
+
+#[cfg(target_os = "linux")]
+impl MountTable {
+    /// Read the mounts from /proc/self/mountinfo (none if it can't be read).
+    fn read() -> Self {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+        MountTable::new(parse_mountinfo(&mountinfo))
+    }
+}
+
+/// Mount points and types listed in /proc/self/mountinfo.
+#[cfg(target_os = "linux")]
+fn parse_mountinfo(mountinfo: &str) -> Vec<(PathBuf, String)> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // "<id> <parent> <dev> <root> <mount point> <options> [fields] - <type> ..."
+            let (left, right) = line.split_once(" - ")?;
+            let mount_point = unescape_mount_path(left.split(' ').nth(4)?);
+            let fs_type = right.split(' ').next()?;
+            Some((PathBuf::from(mount_point), fs_type.to_string()))
+        })
+        .collect()
+}
+
+/// Decode the octal escapes (`\040` for a space) in a mountinfo path.
+#[cfg(target_os = "linux")]
+fn unescape_mount_path(raw: &str) -> String {
+    let mut out = Vec::with_capacity(raw.len());
+    let bytes = raw.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match octal {
+            Some(byte) if bytes[i] == b'\\' => {
+                out.push(byte);
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(target_os = "macos")]
+impl MountTable {
+    /// Read the mounts from the output of `mount` (none if it fails).
+    fn read() -> Self {
+        let output = std::process::Command::new("/sbin/mount").output();
+        let mounts = output
+            .map(|output| parse_mount_output(&String::from_utf8_lossy(&output.stdout)))
+            .unwrap_or_default();
+        MountTable::new(mounts)
+    }
+}
+
+/// Mount points and types listed by `mount`
+/// ("<device> on <mount point> (<type>, <options>)").
+#[cfg(target_os = "macos")]
+fn parse_mount_output(mounts: &str) -> Vec<(PathBuf, String)> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let (_, rest) = line.split_once(" on ")?;
+            let (mount_point, details) = rest.rsplit_once(" (")?;
+            let fs_type = details.split([',', ')']).next()?;
+            Some((PathBuf::from(mount_point), fs_type.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+impl MountTable {
+    fn read() -> Self {
+        MountTable::new(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(mounts: &[(&str, &str)]) -> MountTable {
+        let mounts = mounts
+            .iter()
+            .map(|(path, fs_type)| (PathBuf::from(path), fs_type.to_string()))
+            .collect();
+        MountTable::new(mounts)
+    }
+
+    #[test]
+    fn network_and_fuse_file_systems_are_unreliable() {
+        for fs_type in ["nfs4", "CIFS", "smbfs", "fuse", "fuse.sshfs", "macfuse"] {
+            assert!(is_unreliable(fs_type), "{}", fs_type);
+        }
+        for fs_type in ["ext4", "apfs", "btrfs", "tmpfs", "fuseblk", "overlay"] {
+            assert!(!is_unreliable(fs_type), "{}", fs_type);
+        }
+    }
+
+    #[test]
+    fn the_longest_mount_point_decides() {
+        let mounts = table(&[
+            ("/", "ext4"),
+            ("/mnt/share", "cifs"),
+            ("/mnt/share/local", "ext4"),
+            ("/home/me/Drive", "fuse.rclone"),
+        ]);
+        assert_eq!(mounts.unreliable_fs(Path::new("/home/me/docs")), None);
+        let reason = mounts.unreliable_fs(Path::new("/mnt/share/hq"));
+        assert_eq!(reason.as_deref(), Some("cifs file system"));
+        assert_eq!(mounts.unreliable_fs(Path::new("/mnt/share/local/hq")), None);
+        let reason = mounts.unreliable_fs(Path::new("/home/me/Drive/hq"));
+        assert_eq!(reason.as_deref(), Some("fuse.rclone file system"));
+        // Mount points match whole path components
+        assert_eq!(mounts.fs_type(Path::new("/mnt/shared")), Some("ext4"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mountinfo_paths_are_unescaped() {
+        assert_eq!(unescape_mount_path(r"/mnt/My\040Share"), "/mnt/My Share");
+        assert_eq!(unescape_mount_path(r"/a\011b\134c"), "/a\tb\\c");
+        // Not an octal escape
+        assert_eq!(unescape_mount_path(r"/a\9x"), r"/a\9x");
+        assert_eq!(unescape_mount_path(r"/trailing\04"), r"/trailing\04");
+
+        let mountinfo = "22 1 0:21 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+            36 22 0:32 / /mnt/My\\040Share rw shared:2 - cifs //server/share rw\n";
+        let mounts = MountTable::new(parse_mountinfo(mountinfo));
+        let reason = mounts.unreliable_fs(Path::new("/mnt/My Share/hq"));
+        assert_eq!(reason.as_deref(), Some("cifs file system"));
+    }
+}
@@ -0,0 +1,192 @@
+//! What the file watcher watches for one HQ.
+//!
+//! notify does not follow symlinks, so a scope directory that is a symlink
+//! (e.g. `companies/x/knowledge` -> `~/repos/x-knowledge`) is watched at its
+//! canonical target, and events from there are mapped back to the path as
+//! seen through the HQ. Wildcard scope patterns also have their parent
+//! directories watched, so scope directories created later are picked up.
+
+use crate::ignore_rules::IgnoreEngine;
+use crate::scopes::{ScopeSet, WatchParent};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A watched scope directory.
+pub struct WatchRoot {
+    /// Scope directory as seen through the HQ
+    pub path: PathBuf,
+    /// Directory actually watched: `path` with symlinks resolved
+    pub target: PathBuf,
+}
+
+/// The scope roots and wildcard parents being watched.
+#[derive(Default)]
+pub struct WatchPlan {
+    pub roots: Vec<WatchRoot>,
+    pub parents: Vec<WatchParent>,
+}
+
+impl WatchPlan {
+    /// Expand the scopes under `hq` into the directories to watch.
+    pub fn new(hq: &Path, scope_set: &ScopeSet, ignore: &IgnoreEngine) -> Self {
+        let mut plan = WatchPlan::default();
+        plan.refresh(hq, scope_set, ignore);
+        plan
+    }
+
+    /// Re-expand the scopes, adding scope roots and parents that appeared
+    /// since the last expansion. Returns how many of each were added; the
+    /// new entries are at the end of `roots` and `parents`.
+    pub fn refresh(
+        &mut self,
+        hq: &Path,
+        scope_set: &ScopeSet,
+        ignore: &IgnoreEngine,
+    ) -> (usize, usize) {
+        let mut new_roots = 0;
+        for path in scope_set.expand(hq, ignore).dirs {
+            let target = match fs::canonicalize(&path) {
+                Ok(target) if target.is_dir() => target,
+                _ => continue,
+            };
+            if !self.roots.iter().any(|root| root.path == path) {
+                self.roots.push(WatchRoot { path, target });
+                new_roots += 1;
+            }
+        }
+
+        let mut new_parents = 0;
+        for parent in scope_set.watch_parents(hq, ignore) {
+            if !self.parents.iter().any(|p| p.dir == parent.dir) {
+                self.parents.push(parent);
+                new_parents += 1;
+            }
+        }
+        (new_roots, new_parents)
+    }
+
+    /// Map a path reported by notify to the path as seen through the HQ.
+    /// Paths outside every symlinked scope target are returned unchanged.
+    pub fn hq_path(&self, path: &Path) -> PathBuf {
+        self.roots
+            .iter()
+            .filter(|root| root.target != root.path)
+            .filter_map(|root| Some((root, path.strip_prefix(&root.target).ok()?)))
+            .max_by_key(|(root, _)| root.target.as_os_str().len())
+            .map(|(root, rest)| root.path.join(rest))
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// The on-disk path of an HQ path inside a symlinked scope root, or None
+    /// if no symlinked scope is involved.
+    pub fn real_path(&self, path: &Path) -> Option<PathBuf> {
+        let root = self.root_of(path)?;
+        if root.target == root.path {
+            return None;
+        }
+        Some(root.target.join(path.strip_prefix(&root.path).ok()?))
+    }
+
+    /// The scope root an HQ path lies in (the innermost one, if nested).
+    pub fn root_of(&self, path: &Path) -> Option<&WatchRoot> {
+        self.roots
+            .iter()
+            .filter(|root| path.starts_with(&root.path))
+            .max_by_key(|root| root.path.as_os_str().len())
+    }
+
+    /// Whether a new directory at `path` could be (or contain) a new scope root.
+    pub fn under_parent(&self, path: &Path) -> bool {
+        self.parents.iter().any(|parent| {
+            path.parent() == Some(parent.dir.as_path())
+                || (parent.recursive && path.starts_with(&parent.dir))
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// An HQ whose scope `a` links to `repos/x` outside it, next to a plain
+    /// scope `ab` and an unwatched `repos/xy`.
+    fn linked_hq() -> (tempfile::TempDir, PathBuf, WatchPlan) {
+        let dir = tempfile::tempdir().unwrap();
+        let base = fs::canonicalize(dir.path()).unwrap();
+        let hq = base.join("hq");
+        for sub in ["hq/ab", "hq/companies/acme", "repos/x", "repos/xy"] {
+            fs::create_dir_all(base.join(sub)).unwrap();
+        }
+        symlink(base.join("repos/x"), hq.join("a")).unwrap();
+
+        let scopes = ["a", "ab", "companies/*"].map(String::from);
+        let (scope_set, _) = ScopeSet::parse(&scopes);
+        let plan = WatchPlan::new(&hq, &scope_set, &IgnoreEngine::new(&hq));
+        (dir, base, plan)
+    }
+
+    #[test]
+    fn symlinked_roots_are_watched_at_their_target() {
+        let (_dir, base, plan) = linked_hq();
+        let hq = base.join("hq");
+        let targets: Vec<_> = plan.roots.iter().map(|r| (&r.path, &r.target)).collect();
+        assert!(targets.contains(&(&hq.join("a"), &base.join("repos/x"))));
+        assert!(targets.contains(&(&hq.join("ab"), &hq.join("ab"))));
+        assert_eq!(plan.roots.len(), 3);
+    }
+
+    #[test]
+    fn paths_map_between_target_and_hq() {
+        let (_dir, base, plan) = linked_hq();
+        let hq = base.join("hq");
+
+        let real = base.join("repos/x/notes/one.md");
+        assert_eq!(plan.hq_path(&real), hq.join("a/notes/one.md"));
+        assert_eq!(plan.real_path(&hq.join("a/notes/one.md")), Some(real));
+        assert_eq!(plan.hq_path(&base.join("repos/x")), hq.join("a"));
+
+        // `repos/xy` only shares a string prefix with the target
+        let beside = base.join("repos/xy/one.md");
+        assert_eq!(plan.hq_path(&beside), beside);
+        // Paths in unlinked scopes stay as they are
+        let plain = hq.join("ab/one.md");
+        assert_eq!(plan.hq_path(&plain), plain);
+        assert_eq!(plan.real_path(&plain), None);
+    }
+
+    #[test]
+    fn root_of_matches_whole_path_components() {
+        let (_dir, base, plan) = linked_hq();
+        let hq = base.join("hq");
+        let root = |path: &str| plan.root_of(&hq.join(path)).map(|r| r.path.clone());
+
+        assert_eq!(root("a/one.md"), Some(hq.join("a")));
+        assert_eq!(root("ab/one.md"), Some(hq.join("ab")));
+        assert_eq!(root("ab"), Some(hq.join("ab")));
+        assert_eq!(root("abc/one.md"), None);
+        assert_eq!(
+            root("companies/acme/one.md"),
+            Some(hq.join("companies/acme"))
+        );
+        assert_eq!(root("companies/one.md"), None);
+    }
+
+    #[test]
+    fn new_directories_under_wildcard_parents() {
+        let (_dir, base, mut plan) = linked_hq();
+        let hq = base.join("hq");
+        assert!(plan.under_parent(&hq.join("companies/new")));
+        // Only direct subdirectories can match `companies/*`
+        assert!(!plan.under_parent(&hq.join("companies/acme/sub")));
+        assert!(!plan.under_parent(&hq.join("companiesx/new")));
+        assert!(!plan.under_parent(&hq.join("ab/new")));
+
+        plan.parents.push(WatchParent {
+            dir: hq.join("notes"),
+            recursive: true,
+        });
+        assert!(plan.under_parent(&hq.join("notes/a/b/new")));
+        assert!(!plan.under_parent(&hq.join("notesx/new")));
+    }
+}
@@ -25,6 +25,19 @@ function App() {
     pathError,
   } = useAppConfig();
 
+  const scanOptions = useMemo(
+    () => ({ maxThreads: config.scanMaxThreads }),
+    [config.scanMaxThreads],
+  );
+  const watchOptions = useMemo(
+    () => ({
+      mode: config.watcherMode,
+      pollIntervalMs: config.watcherPollIntervalMs,
+      qmdAutoIndex: config.qmdAutoIndex,
+    }),
+    [config.watcherMode, config.watcherPollIntervalMs, config.qmdAutoIndex],
+  );
+
   const {
     tree,
     loading: treeLoading,
@@ -34,7 +47,7 @@ function App() {
     enabledScopes,
     setEnabledScopes,
     totalFiles,
-  } = useFileTree(isConnected ? config.hqFolderPath : null);
+  } = useFileTree(isConnected ? config.hqFolderPath : null, scanOptions);
 
   const {
     width: sidebarWidth,
@@ -58,22 +71,45 @@ function App() {
   selectedFileRef.current = selectedFile;
 
   // File watcher: auto-refresh content and tree on disk changes
-  const handleFileChange = useCallback((changedPath: string) => {
-    // If the changed file is the one currently being viewed, refresh it
-    if (selectedFileRef.current && selectedFileRef.current === changedPath) {
+  // Content hash of the viewed file as of its last refresh
+  const viewedHashRef = useRef<{ path: string; hash: string } | null>(null);
+  const handleFileChange = useCallback(
+    (changedPath: string, hash: string | null) => {
+      // If the changed file is the one currently being viewed, refresh it
+      if (!selectedFileRef.current || selectedFileRef.current !== changedPath) {
+        return;
+      }
+      const viewed = viewedHashRef.current;
+      if (hash && viewed?.path === changedPath && viewed.hash === hash) {
+        return;
+      }
+      viewedHashRef.current = hash ? { path: changedPath, hash } : null;
       setContentRefreshKey((k) => k + 1);
+    },
+    [],
+  );
+
+  // Follow the open document (or its directory) through renames
+  const [renamedFrom, setRenamedFrom] = useState<string | null>(null);
+  const handleFileRename = useCallback((from: string, to: string) => {
+    const current = selectedFileRef.current;
+    if (!current) return;
+    if (current === from) {
+      setRenamedFrom(current);
+      setSelectedFile(to);
+    } else if (current.startsWith(from + "/")) {
+      setRenamedFrom(current);
+      setSelectedFile(to + current.slice(from.length));
     }
   }, []);
 
-  const handleTreeChange = useCallback(() => {
-    rescan();
-  }, [rescan]);
-
-  const { watching } = useFileWatcher({
+  const { watching, watcherError } = useFileWatcher({
     hqPath: isConnected ? config.hqFolderPath : null,
     enabledScopes,
+    watchOptions,
     onFileChange: handleFileChange,
-    onTreeChange: handleTreeChange,
+    onFileRename: handleFileRename,
+    onRescanRecommended: rescan,
   });
 
   // Compute tier groups from tree roots
@@ -124,15 +160,22 @@ function App() {
 
     async function setupMenuListener() {
       try {
-        const { listen } = await import("@tauri-apps/api/event");
-        const unlistenFn = await listen<string>("menu-item-click", (event) => {
-          const menuId = event.payload;
-          if (menuId === "about") {
-            setAboutOpen(true);
-          } else if (menuId === "preferences") {
-            setSettingsOpen(true);
-          }
-        });
+        const { getCurrentWebviewWindow } = await import(
+          "@tauri-apps/api/webviewWindow"
+        );
+        // Menu clicks are sent to the focused window
+        const appWindow = getCurrentWebviewWindow();
+        const unlistenFn = await appWindow.listen<string>(
+          "menu-item-click",
+          (event) => {
+            const menuId = event.payload;
+            if (menuId === "about") {
+              setAboutOpen(true);
+            } else if (menuId === "preferences") {
+              setSettingsOpen(true);
+            }
+          },
+        );
         unlisten = unlistenFn;
       } catch {
         // Not in Tauri environment (e.g., web dev server)
@@ -294,6 +337,7 @@ function App() {
             onResizeMouseDown={handleSidebarResizeMouseDown}
             onResizeReset={resetSidebarWidth}
             watching={watching}
+            watcherError={watcherError}
             companyFilter={companyFilter}
             onCompanyFilterChange={handleCompanyFilterChange}
           />
@@ -304,6 +348,7 @@ function App() {
           onNavigate={handleNavigate}
           onNavigateToPath={handleNavigateToPath}
           refreshKey={contentRefreshKey}
+          renamedFrom={renamedFrom}
           tree={tree}
         />
       </div>
@@ -329,7 +374,7 @@ function App() {
         isOpen={searchOpen}
         onClose={handleCloseSearch}
         onSelectFile={handleSelectFile}
-        hqFolderPath={config.hqFolderPath}
+        qmdPath={config.qmdPath}
       />
     </>
   );
@@ -1,4 +1,10 @@
-import { useEffect, useMemo, useRef, useCallback } from "react";
+import {
+  useEffect,
+  useLayoutEffect,
+  useMemo,
+  useRef,
+  useCallback,
+} from "react";
 import { FileText, AlertCircle, Loader2 } from "lucide-react";
 import { GlassPanel } from "@/components/ui/glass-panel";
 import { IndigoLogo } from "@/components/ui/indigo-logo";
@@ -25,6 +31,8 @@ interface ContentAreaProps {
   onNavigateToPath?: (absolutePath: string) => void;
   /** Increment to force content refresh (used by file watcher) */
   refreshKey?: number;
+  /** Previous path of the selected file if it was just renamed (keeps the scroll position) */
+  renamedFrom?: string | null;
   /** File tree roots for directory lookup */
   tree?: FileTreeNode[];
   className?: string;
@@ -44,6 +52,7 @@ export function ContentArea({
   onNavigate,
   onNavigateToPath,
   refreshKey = 0,
+  renamedFrom = null,
   tree = [],
   className,
 }: ContentAreaProps) {
@@ -65,6 +74,18 @@ export function ContentArea({
     refresh();
   }, [refreshKey]); // eslint-disable-line react-hooks/exhaustive-deps
 
+  // A rename reloads the document under its new path; carry the scroll
+  // position over the loading state instead of jumping back to the top
+  const scrollRef = useRef<HTMLDivElement>(null);
+  const previousFile = useRef(selectedFile);
+  const carriedScroll = useRef<number | null>(null);
+  useLayoutEffect(() => {
+    if (renamedFrom !== null && renamedFrom === previousFile.current) {
+      carriedScroll.current = scrollRef.current?.scrollTop ?? null;
+    }
+    previousFile.current = selectedFile;
+  }, [selectedFile, renamedFrom]);
+
   // Detect if this is an INDEX.md file
   const isIndexMd = useMemo(() => {
     if (!selectedFile) return false;
@@ -113,6 +134,12 @@ export function ContentArea({
   const showMarkdown =
     !isIndexMd && content !== null && !loading && !error;
 
+  useLayoutEffect(() => {
+    if (loading || carriedScroll.current === null || !scrollRef.current) return;
+    scrollRef.current.scrollTop = carriedScroll.current;
+    carriedScroll.current = null;
+  }, [loading, content]);
+
   return (
     <GlassPanel
       variant="content"
@@ -131,7 +158,7 @@ export function ContentArea({
       </div>
 
       {/* Document content */}
-      <div className="flex-1 overflow-y-auto px-6 pb-8">
+      <div ref={scrollRef} className="flex-1 overflow-y-auto px-6 pb-8">
         <div className="mx-auto max-w-5xl pt-4">
           {/* Loading state */}
           {loading && (
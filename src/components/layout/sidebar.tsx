@@ -52,6 +52,8 @@ interface SidebarProps {
   onResizeReset: () => void;
   /** Whether the file watcher is actively running */
   watching?: boolean;
+  /** Last file watcher error, shown on the status indicator */
+  watcherError?: string | null;
   /** Active company filter (null = show all) */
   companyFilter?: string | null;
   /** Callback when company filter changes */
@@ -81,6 +83,7 @@ export function Sidebar({
   onResizeMouseDown,
   onResizeReset,
   watching = false,
+  watcherError = null,
   companyFilter = null,
   onCompanyFilterChange,
 }: SidebarProps) {
@@ -206,6 +209,13 @@ export function Sidebar({
             <div className="flex min-w-0 flex-1 items-center gap-1.5">
               {rescanning ? (
                 <RefreshCw className="h-3 w-3 shrink-0 animate-spin text-primary/50" />
+              ) : watching && watcherError ? (
+                <span
+                  className="relative flex h-2 w-2 shrink-0"
+                  title={`Watching with errors: ${watcherError}`}
+                >
+                  <span className="relative inline-flex h-2 w-2 rounded-full bg-amber-400" />
+                </span>
               ) : watching ? (
                 <span
                   className="relative flex h-2 w-2 shrink-0"
@@ -6,7 +6,8 @@
  * - Collection scoping dropdown
  * - Keyboard navigation (arrows, Enter, Escape)
  * - Debounced input with loading states
- * - qmd unavailable fallback message
+ * - Keyword-only built-in search when qmd isn't installed
+ * - Warning when the installed qmd is too old or can't be run
  */
 
 import { useEffect, useRef, useCallback, useState } from "react";
@@ -18,9 +19,11 @@ import {
   AlertCircle,
   ChevronDown,
   ExternalLink,
+  Clock,
 } from "lucide-react";
 import { GlassPanel } from "@/components/ui/glass-panel";
 import { cn } from "@/lib/utils";
+import { getCompanyDisplayName, getCompanyDotColor } from "@/lib/companies";
 import {
   useSearch,
   type SearchMode,
@@ -38,8 +41,8 @@ interface CommandPaletteProps {
   onClose: () => void;
   /** Callback when user selects a result (absolute file path) */
   onSelectFile: (filePath: string) => void;
-  /** HQ folder path for resolving relative paths from qmd results */
-  hqFolderPath: string | null;
+  /** qmd binary from the app config (unset = look for one) */
+  qmdPath?: string;
 }
 
 // ---------------------------------------------------------------------------
@@ -68,12 +71,13 @@ export function CommandPalette({
   isOpen,
   onClose,
   onSelectFile,
-  hqFolderPath,
+  qmdPath,
 }: CommandPaletteProps) {
   const {
     results,
     loading,
     error,
+    timedOut,
     total,
     query,
     setQuery,
@@ -83,8 +87,15 @@ export function CommandPalette({
     setCollection,
     collections,
     qmdStatus,
+    qmdInfo,
     clear,
-  } = useSearch();
+  } = useSearch(qmdPath);
+
+  // The built-in index (no qmd) only does keyword search
+  const modeOptions =
+    qmdStatus === "builtin"
+      ? MODE_OPTIONS.filter((opt) => opt.value === "keyword")
+      : MODE_OPTIONS;
 
   const [activeIndex, setActiveIndex] = useState(-1);
   const [showModeDropdown, setShowModeDropdown] = useState(false);
@@ -161,18 +172,13 @@ export function CommandPalette({
   // Handle selecting a result
   const handleSelect = useCallback(
     (result: SearchResult) => {
-      // Resolve the file path — qmd returns paths relative to the collection root
-      // Try to build an absolute path using hqFolderPath
-      let absolutePath = result.filePath;
-
-      if (hqFolderPath && !absolutePath.startsWith("/")) {
-        absolutePath = `${hqFolderPath}/${result.filePath}`;
-      }
-
-      onSelectFile(absolutePath);
+      // The backend resolves each result to its file; some can't be found
+      // (e.g. a collection outside this HQ that has since moved)
+      if (!result.openable || !result.absolutePath) return;
+      onSelectFile(result.absolutePath);
       handleClose();
     },
-    [hqFolderPath, onSelectFile, handleClose],
+    [onSelectFile, handleClose],
   );
 
   // Keyboard navigation
@@ -268,7 +274,7 @@ export function CommandPalette({
             onKeyDown={handleKeyDown}
             placeholder={
               qmdStatus === "unavailable"
-                ? "Search unavailable — qmd not installed"
+                ? "Search unavailable"
                 : "Search docs..."
             }
             disabled={qmdStatus === "unavailable"}
@@ -302,7 +308,7 @@ export function CommandPalette({
 
             {showModeDropdown && (
               <div className="absolute right-0 top-full z-20 mt-1 w-48 rounded-lg border border-white/10 bg-[rgba(25,25,25,0.95)] py-1 shadow-xl backdrop-blur-2xl">
-                {MODE_OPTIONS.map((opt) => (
+                {modeOptions.map((opt) => (
                   <button
                     key={opt.value}
                     onClick={() => {
@@ -419,17 +425,17 @@ export function CommandPalette({
           )}
         </div>
 
-        {/* qmd unavailable message */}
+        {/* Search backend unreachable (e.g. outside the desktop app) */}
         {qmdStatus === "unavailable" && (
           <div className="flex items-center gap-3 px-4 py-6">
             <AlertCircle className="h-5 w-5 shrink-0 text-amber-400/60" />
             <div>
               <p className="text-sm font-medium text-white/70">
-                Install qmd for search
+                Search unavailable
               </p>
               <p className="mt-1 text-xs text-white/40">
-                qmd provides keyword, semantic, and hybrid search across your
-                docs.
+                Search runs in the desktop app. With qmd installed it adds
+                semantic and hybrid search across your docs.
               </p>
               <a
                 href="https://github.com/tobi/qmd"
@@ -444,6 +450,14 @@ export function CommandPalette({
           </div>
         )}
 
+        {/* Installed qmd is too old, lacks modes, or fails to run */}
+        {qmdStatus !== "unavailable" && qmdInfo?.warning && (
+          <div className="flex items-start gap-2 border-b border-white/5 px-4 py-2">
+            <AlertCircle className="mt-0.5 h-3.5 w-3.5 shrink-0 text-amber-400/60" />
+            <p className="text-[11px] text-white/40">{qmdInfo.warning}</p>
+          </div>
+        )}
+
         {/* Results area */}
         {qmdStatus !== "unavailable" && (
           <div className="flex-1 overflow-y-auto" ref={listRef}>
@@ -494,13 +508,28 @@ export function CommandPalette({
             )}
 
             {/* Error state */}
-            {error && (
+            {error && !timedOut && (
               <div className="flex items-center gap-2 px-4 py-4">
                 <AlertCircle className="h-4 w-4 shrink-0 text-destructive/60" />
                 <p className="text-xs text-destructive/80">{error}</p>
               </div>
             )}
 
+            {/* Timed out: slower modes load models first */}
+            {error && timedOut && (
+              <div className="flex flex-col items-center py-8">
+                <Clock className="h-5 w-5 text-white/15" />
+                <p className="mt-2 text-xs text-white/40">
+                  Search took too long and was stopped
+                </p>
+                <p className="mt-1 text-[10px] text-white/25">
+                  {mode === "keyword"
+                    ? "Try a narrower collection"
+                    : "Try keyword mode for faster results"}
+                </p>
+              </div>
+            )}
+
             {/* Placeholder when no query */}
             {!query && !loading && (
               <div className="flex flex-col items-center py-8">
@@ -556,7 +585,9 @@ function SearchResultItem({
         "flex w-full items-start gap-3 px-4 py-2.5 text-left",
         "transition-colors duration-100",
         isActive ? "bg-white/[0.06]" : "hover:bg-white/[0.03]",
+        !result.openable && "cursor-default opacity-50",
       )}
+      title={result.openable ? undefined : "File not found in this HQ"}
     >
       <FileText className="mt-0.5 h-4 w-4 shrink-0 text-white/30" />
 
@@ -566,10 +597,23 @@ function SearchResultItem({
           {result.title}
         </p>
 
-        {/* File path */}
-        <p className="mt-0.5 truncate font-mono text-[10px] text-white/35">
-          {result.filePath}
-        </p>
+        {/* Company and file path */}
+        <div className="mt-0.5 flex min-w-0 items-center gap-1.5">
+          {result.company && (
+            <span className="flex shrink-0 items-center gap-1 text-[10px] text-white/45">
+              <span
+                className={cn(
+                  "h-1.5 w-1.5 rounded-full",
+                  getCompanyDotColor(result.company),
+                )}
+              />
+              {getCompanyDisplayName(result.company)}
+            </span>
+          )}
+          <p className="truncate font-mono text-[10px] text-white/35">
+            {result.filePath}
+          </p>
+        </div>
 
         {/* Snippet */}
         {result.snippet && (
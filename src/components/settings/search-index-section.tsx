@@ -0,0 +1,91 @@
+import { Database, FolderPlus, RefreshCw, Sparkles } from "lucide-react";
+import { cn } from "@/lib/utils";
+import { useQmdIndex, type IndexTask } from "@/hooks/use-qmd-index";
+
+const TASK_LABELS: Record<IndexTask, string> = {
+  collection: "Adding HQ to qmd",
+  update: "Updating index",
+  embed: "Computing embeddings",
+};
+
+/**
+ * Settings section for qmd's index of the HQ: add the folder as a collection,
+ * update the index and compute embeddings, with qmd's output as it runs.
+ */
+export function SearchIndexSection() {
+  const { running, automatic, lines, error, createCollection, update, embed } =
+    useQmdIndex();
+  const busy = running !== null;
+
+  return (
+    <div>
+      <div className="mb-3 flex items-center gap-2">
+        <Database className="h-3.5 w-3.5 text-white/30" />
+        <label className="text-xs font-medium text-white/50">
+          Search Index
+        </label>
+        {running && (
+          <span className="ml-auto flex items-center gap-1.5 text-[10px] text-primary/70">
+            <div className="h-2.5 w-2.5 animate-spin rounded-full border border-white/20 border-t-primary" />
+            {TASK_LABELS[running]}
+            {automatic && " (auto)"}
+          </span>
+        )}
+      </div>
+
+      <div className="flex gap-2">
+        <IndexButton onClick={createCollection} disabled={busy}>
+          <FolderPlus className="h-3.5 w-3.5" />
+          Add HQ to qmd
+        </IndexButton>
+        <IndexButton onClick={update} disabled={busy}>
+          <RefreshCw className="h-3.5 w-3.5" />
+          Update
+        </IndexButton>
+        <IndexButton onClick={embed} disabled={busy}>
+          <Sparkles className="h-3.5 w-3.5" />
+          Embed
+        </IndexButton>
+      </div>
+
+      {error && <p className="mt-2 text-xs text-destructive/80">{error}</p>}
+
+      {lines.length > 0 && (
+        <pre className="mt-2 max-h-32 overflow-y-auto rounded-lg bg-black/30 px-3 py-2 font-mono text-[10px] leading-relaxed text-white/40">
+          {lines.join("\n")}
+        </pre>
+      )}
+
+      <p className="mt-2 text-[10px] text-white/20">
+        Semantic and hybrid search need embeddings. Set qmdAutoIndex in the
+        config to update the index when documents change.
+      </p>
+    </div>
+  );
+}
+
+interface IndexButtonProps {
+  onClick: () => void;
+  disabled: boolean;
+  children: React.ReactNode;
+}
+
+function IndexButton({ onClick, disabled, children }: IndexButtonProps) {
+  return (
+    <button
+      onClick={onClick}
+      disabled={disabled}
+      className={cn(
+        "flex flex-1 items-center justify-center gap-2 rounded-lg px-3 py-2",
+        "text-xs font-medium",
+        "bg-white/5 text-white/60",
+        "transition-colors duration-150",
+        "hover:bg-white/10 hover:text-white/80",
+        "focus-visible:outline-none focus-visible:ring-1 focus-visible:ring-primary",
+        "disabled:opacity-50 disabled:cursor-not-allowed",
+      )}
+    >
+      {children}
+    </button>
+  );
+}
@@ -13,6 +13,7 @@ import { cn, truncatePath } from "@/lib/utils";
 import { APP_NAME, APP_VERSION } from "@/lib/constants";
 import type { AppConfig } from "@/lib/config";
 import { DEFAULT_SCOPES } from "@/lib/scanner";
+import { SearchIndexSection } from "@/components/settings/search-index-section";
 
 interface SettingsModalProps {
   /** Whether the modal is open */
@@ -40,8 +41,9 @@ interface SettingsModalProps {
 }
 
 /**
- * Settings modal for managing HQ folder connection and scan scopes.
- * Glass-styled overlay with folder picker, recent folders, and scope toggles.
+ * Settings modal for managing HQ folder connection, scan scopes and the qmd
+ * search index. Glass-styled overlay with folder picker, recent folders,
+ * scope toggles, and index actions.
  */
 export function SettingsModal({
   isOpen,
@@ -263,6 +265,9 @@ export function SettingsModal({
               </div>
             )}
 
+            {/* qmd search index */}
+            {isConnected && <SearchIndexSection />}
+
             {/* Recent folders */}
             {config.recentFolders.length > 0 && (
               <div>
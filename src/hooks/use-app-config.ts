@@ -2,6 +2,7 @@ import { useState, useEffect, useCallback } from "react";
 import { load } from "@tauri-apps/plugin-store";
 import { open } from "@tauri-apps/plugin-dialog";
 import { readDir } from "@tauri-apps/plugin-fs";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 import {
   type AppConfig,
   DEFAULT_CONFIG,
@@ -47,6 +48,15 @@ async function validateHqFolder(path: string): Promise<string | null> {
   }
 }
 
+/**
+ * Whether this is the app's first window. Windows opened with "New Window"
+ * start without a folder and keep their folder to themselves: only the main
+ * window's folder is persisted and restored on launch.
+ */
+function isMainWindow(): boolean {
+  return getCurrentWebviewWindow().label === "main";
+}
+
 /**
  * Hook for managing app configuration (HQ folder path, recent folders).
  * Persists config to Tauri's plugin-store (appConfigDir).
@@ -68,7 +78,9 @@ export function useAppConfig(): UseAppConfigReturn {
 
         if (cancelled) return;
 
-        if (saved) {
+        if (saved && !isMainWindow()) {
+          setConfig({ ...saved, hqFolderPath: null });
+        } else if (saved) {
           setConfig(saved);
 
           // Validate saved path still exists
@@ -104,7 +116,16 @@ export function useAppConfig(): UseAppConfigReturn {
   const saveConfig = useCallback(async (newConfig: AppConfig) => {
     try {
       const store = await load(CONFIG_STORE_KEY);
-      await store.set("config", newConfig);
+      if (isMainWindow()) {
+        await store.set("config", newConfig);
+      } else {
+        // Keep the main window's folder; share everything else
+        const saved = await store.get<AppConfig>("config");
+        await store.set("config", {
+          ...newConfig,
+          hqFolderPath: saved?.hqFolderPath ?? null,
+        });
+      }
       await store.save();
       setConfig(newConfig);
       setError(null);
@@ -143,6 +164,7 @@ export function useAppConfig(): UseAppConfigReturn {
 
       setPathError(null);
       const newConfig: AppConfig = {
+        ...config,
         hqFolderPath: selected,
         recentFolders: addToRecent(selected, config),
       };
@@ -167,6 +189,7 @@ export function useAppConfig(): UseAppConfigReturn {
 
       setPathError(null);
       const newConfig: AppConfig = {
+        ...config,
         hqFolderPath: path,
         recentFolders: addToRecent(path, config),
       };
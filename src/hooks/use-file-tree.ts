@@ -2,18 +2,26 @@
  * Hook for scanning and managing the HQ file tree.
  *
  * Features:
- * - Scans HQ directory using Rust-side Tauri command
+ * - Scans HQ directory using Rust-side Tauri command (background, cancellable)
+ * - Reports scan progress and shows scope roots as they complete
  * - Caches results in Tauri store for instant subsequent loads
  * - Background re-scan when window regains focus (debounced 2s)
+ * - Applies `tree-patch` events from the file watcher without a re-scan
  * - Configurable scopes
  */
 
 import { useState, useEffect, useCallback, useRef } from "react";
 import { load } from "@tauri-apps/plugin-store";
 import { getCurrentWindow } from "@tauri-apps/api/window";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 import {
   type FileTreeNode,
-  scanHqDirectory,
+  type ScanHandle,
+  type ScanOptions,
+  type ScanProgress,
+  type TreePatch,
+  applyTreePatch,
+  startScan,
   getDefaultEnabledScopes,
 } from "@/lib/scanner";
 
@@ -52,14 +60,20 @@ interface UseFileTreeReturn {
   setEnabledScopes: (scopeIds: string[]) => Promise<void>;
   /** Total .md file count across all scoped directories */
   totalFiles: number;
+  /** Running totals of the scan in progress (null when idle) */
+  scanProgress: ScanProgress | null;
 }
 
 /**
  * Hook for managing the HQ file tree.
  *
  * @param hqPath - Absolute path to the connected HQ folder (null if not connected)
+ * @param scanOptions - Optional scanner settings (should be memoized by the caller)
  */
-export function useFileTree(hqPath: string | null): UseFileTreeReturn {
+export function useFileTree(
+  hqPath: string | null,
+  scanOptions?: ScanOptions,
+): UseFileTreeReturn {
   const [tree, setTree] = useState<FileTreeNode[]>([]);
   const [loading, setLoading] = useState(false);
   const [rescanning, setRescanning] = useState(false);
@@ -68,6 +82,7 @@ export function useFileTree(hqPath: string | null): UseFileTreeReturn {
     getDefaultEnabledScopes(),
   );
   const [totalFiles, setTotalFiles] = useState(0);
+  const [scanProgress, setScanProgress] = useState<ScanProgress | null>(null);
 
   // Track if initial load from cache has completed
   const initialLoadDone = useRef(false);
@@ -75,6 +90,14 @@ export function useFileTree(hqPath: string | null): UseFileTreeReturn {
   const scanId = useRef(0);
   // Debounce timer for focus re-scan
   const focusTimer = useRef<ReturnType<typeof setTimeout> | null>(null);
+  // The Rust-side scan in flight, cancelled when superseded
+  const activeScan = useRef<ScanHandle | null>(null);
+  // Whether a tree is on screen (partial roots are only shown into an empty tree)
+  const hasTree = useRef(false);
+
+  useEffect(() => {
+    hasTree.current = tree.length > 0;
+  }, [tree]);
 
   // Calculate total file count
   const updateTotalFiles = useCallback((roots: FileTreeNode[]) => {
@@ -134,6 +157,10 @@ export function useFileTree(hqPath: string | null): UseFileTreeReturn {
 
       const currentScanId = ++scanId.current;
 
+      // A newer scan supersedes the one in flight
+      activeScan.current?.cancel().catch(() => {});
+      activeScan.current = null;
+
       if (isBackground) {
         setRescanning(true);
       } else {
@@ -141,11 +168,31 @@ export function useFileTree(hqPath: string | null): UseFileTreeReturn {
       }
       setError(null);
 
+      // Roots completed so far, by scope index
+      const partial: FileTreeNode[] = [];
+      const showPartial = !isBackground && !hasTree.current;
+
       try {
-        const results = await scanHqDirectory(hqPath, enabledScopes);
+        const handle = await startScan(hqPath, enabledScopes, scanOptions, {
+          onProgress: (progress) => {
+            if (currentScanId === scanId.current) setScanProgress(progress);
+          },
+          onRoot: (root, index) => {
+            if (!showPartial || currentScanId !== scanId.current) return;
+            partial[index] = root;
+            setTree(partial.filter(Boolean));
+          },
+        });
+        if (currentScanId !== scanId.current) {
+          handle.cancel().catch(() => {});
+          return;
+        }
+        activeScan.current = handle;
 
-        // Discard stale scan results
-        if (currentScanId !== scanId.current) return;
+        const results = await handle.result;
+
+        // Discard stale or cancelled scan results
+        if (currentScanId !== scanId.current || results === null) return;
 
         setTree(results);
         updateTotalFiles(results);
@@ -171,12 +218,14 @@ export function useFileTree(hqPath: string | null): UseFileTreeReturn {
         );
       } finally {
         if (currentScanId === scanId.current) {
+          activeScan.current = null;
           setLoading(false);
           setRescanning(false);
+          setScanProgress(null);
         }
       }
     },
-    [hqPath, enabledScopes, updateTotalFiles],
+    [hqPath, enabledScopes, scanOptions, updateTotalFiles],
   );
 
   // Initial scan when hqPath changes or on mount (after cache load)
@@ -191,6 +240,18 @@ export function useFileTree(hqPath: string | null): UseFileTreeReturn {
     return () => clearTimeout(timer);
   }, [hqPath, performScan]);
 
+  // Stop the scan in flight when the HQ folder changes or on unmount
+  useEffect(() => {
+    return () => {
+      scanId.current++;
+      activeScan.current?.cancel().catch(() => {});
+      activeScan.current = null;
+      setLoading(false);
+      setRescanning(false);
+      setScanProgress(null);
+    };
+  }, [hqPath]);
+
   // Background re-scan when window regains focus (debounced 2s)
   useEffect(() => {
     if (!hqPath) return;
@@ -219,6 +280,25 @@ export function useFileTree(hqPath: string | null): UseFileTreeReturn {
     };
   }, [hqPath, performScan]);
 
+  // Apply watcher patches to the tree instead of re-scanning
+  useEffect(() => {
+    if (!hqPath) return;
+
+    // Patches for this window's tree only
+    const appWindow = getCurrentWebviewWindow();
+    const unlisten = appWindow.listen<TreePatch>("tree-patch", ({ payload }) => {
+      setTree((roots) => {
+        const patched = applyTreePatch(roots, payload);
+        updateTotalFiles(patched);
+        return patched;
+      });
+    });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [hqPath, updateTotalFiles]);
+
   // Manual re-scan
   const rescan = useCallback(async () => {
     await performScan(false);
@@ -250,5 +330,6 @@ export function useFileTree(hqPath: string | null): UseFileTreeReturn {
     enabledScopes,
     setEnabledScopes,
     totalFiles,
+    scanProgress,
   };
 }
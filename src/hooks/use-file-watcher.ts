@@ -4,23 +4,80 @@
  * Features:
  * - Starts/stops watching when HQ path or scopes change
  * - Debounces file content changes (500ms per file, handled Rust-side)
- * - Debounces tree changes (1s frontend-side for add/delete batching)
  * - Calls onFileChange when a viewed file is modified
- * - Calls onTreeChange when files are added or removed
- * - Exposes watching status for UI indicator
+ * - Calls onFileRename when a file or directory is renamed
+ * - Calls onRescanRecommended for large batches (e.g. git pull, branch switch)
+ * - Tree structure changes arrive as `tree-patch` events (see useFileTree)
+ * - Exposes watching status and the last watcher error for UI indicator
  */
 
 import { useSyncExternalStore, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import { listen } from "@tauri-apps/api/event";
-import { DEFAULT_SCOPES, type ScanScope } from "@/lib/scanner";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
+import {
+  DEFAULT_SCOPES,
+  type ScanScope,
+  type ScopePatternError,
+} from "@/lib/scanner";
+import type { WatcherMode } from "@/lib/config";
 
-/** Payload emitted by the Rust file watcher. */
+/** A single change reported by the Rust file watcher. */
 interface FsChangeEvent {
   /** Absolute path that changed */
   path: string;
-  /** "modify" | "create" | "remove" */
+  /** "modify" | "create" | "remove" | "rename" */
   kind: string;
+  /** For "rename", the path before the rename (`path` is the new one) */
+  from: string | null;
+  /** Where `path` is on disk, if inside a symlinked scope (tree nodes use this path) */
+  realPath: string | null;
+  /** Where `from` is on disk, if inside a symlinked scope */
+  realFrom: string | null;
+  /** For documents: content hash after the change (null on "remove") */
+  hash: string | null;
+  /** For documents: title after the change */
+  title: string | null;
+  /** For documents: modified time in ms since epoch */
+  mtime: number | null;
+}
+
+/**
+ * Payload of `fs-change`: one debounce window's changes, one per path.
+ * Documents touched without their content changing are left out.
+ */
+interface FsChangeBatch {
+  changes: FsChangeEvent[];
+  /** Set for batches too large to patch; the tree should be re-scanned */
+  rescanRecommended: boolean;
+}
+
+/** Options for `start_watching`. */
+export interface WatchOptions {
+  mode?: WatcherMode;
+  pollIntervalMs?: number;
+  /** Schedule a qmd reindex when documents change */
+  qmdAutoIndex?: boolean;
+}
+
+/** Payload of `watcher-error`: a directory that couldn't be watched, or a runtime error. */
+interface WatcherError {
+  path: string | null;
+  message: string;
+}
+
+/** Payload of `watcher-status` (also returned by the `watcher_status` command). */
+export interface WatcherStatus {
+  watching: boolean;
+  hqPath: string | null;
+  mode: WatcherMode;
+  /** `pollReason` is set for folders polled instead of watched with OS events */
+  watched: { path: string; recursive: boolean; pollReason: string | null }[];
+  /** Directories that could not be watched */
+  failed: WatcherError[];
+  eventCount: number;
+  batchCount: number;
+  errorCount: number;
+  lastError: WatcherError | null;
 }
 
 interface UseFileWatcherOptions {
@@ -28,22 +85,26 @@ interface UseFileWatcherOptions {
   hqPath: string | null;
   /** Currently enabled scope IDs */
   enabledScopes: string[];
-  /** Called when a file's content changes (path of changed file) */
-  onFileChange: (path: string) => void;
-  /** Called when the tree structure changes (files added/removed) */
-  onTreeChange: () => void;
+  /** Watcher mode and poll interval (should be memoized by the caller) */
+  watchOptions?: WatchOptions;
+  /** Called when a file's content changes (path and new content hash, if known) */
+  onFileChange: (path: string, hash: string | null) => void;
+  /** Called when a file or directory is renamed (old path, new path) */
+  onFileRename?: (from: string, to: string) => void;
+  /** Called when a batch of changes is too large to patch the tree with */
+  onRescanRecommended?: () => void;
 }
 
 interface UseFileWatcherReturn {
   /** Whether the watcher is actively running */
   watching: boolean;
+  /** Message of the last watcher error since it started (null if none) */
+  watcherError: string | null;
 }
 
-/** Tree change debounce interval (ms) */
-const TREE_DEBOUNCE_MS = 1000;
-
 // Module-level watching state store (avoids setState-in-effect lint issues)
 let watchingState = false;
+let watcherErrorState: string | null = null;
 const watchingListeners = new Set<() => void>();
 
 function setWatchingState(value: boolean) {
@@ -53,6 +114,13 @@ function setWatchingState(value: boolean) {
   }
 }
 
+function setWatcherErrorState(value: string | null) {
+  if (watcherErrorState !== value) {
+    watcherErrorState = value;
+    watchingListeners.forEach((l) => l());
+  }
+}
+
 function subscribeWatching(listener: () => void) {
   watchingListeners.add(listener);
   return () => {
@@ -64,32 +132,46 @@ function getWatchingSnapshot() {
   return watchingState;
 }
 
+function getWatcherErrorSnapshot() {
+  return watcherErrorState;
+}
+
 /**
  * Hook that manages the Tauri file watcher lifecycle.
  *
  * Starts watching when hqPath is set, stops on unmount or path change.
- * Emits callbacks for file content changes and tree structure changes.
+ * Emits callbacks for file content changes, renames and large batches.
  */
 export function useFileWatcher({
   hqPath,
   enabledScopes,
+  watchOptions,
   onFileChange,
-  onTreeChange,
+  onFileRename,
+  onRescanRecommended,
 }: UseFileWatcherOptions): UseFileWatcherReturn {
   const watching = useSyncExternalStore(subscribeWatching, getWatchingSnapshot);
-  const treeDebounceTimer = useRef<ReturnType<typeof setTimeout> | null>(null);
+  const watcherError = useSyncExternalStore(
+    subscribeWatching,
+    getWatcherErrorSnapshot,
+  );
 
   // Use refs for callbacks to avoid re-subscribing on every render
   const onFileChangeRef = useRef(onFileChange);
-  const onTreeChangeRef = useRef(onTreeChange);
+  const onFileRenameRef = useRef(onFileRename);
+  const onRescanRecommendedRef = useRef(onRescanRecommended);
 
   useEffect(() => {
     onFileChangeRef.current = onFileChange;
   }, [onFileChange]);
 
   useEffect(() => {
-    onTreeChangeRef.current = onTreeChange;
-  }, [onTreeChange]);
+    onFileRenameRef.current = onFileRename;
+  }, [onFileRename]);
+
+  useEffect(() => {
+    onRescanRecommendedRef.current = onRescanRecommended;
+  }, [onRescanRecommended]);
 
   // Start/stop watcher when path or scopes change
   useEffect(() => {
@@ -113,10 +195,20 @@ export function useFileWatcher({
 
     async function startWatcher() {
       try {
-        await invoke("start_watching", {
+        const result = await invoke<{
+          patternErrors: ScopePatternError[];
+          watchErrors: WatcherError[];
+        }>("start_watching", {
           hqPath,
           scopes: patterns,
+          options: watchOptions ?? null,
         });
+        for (const { pattern, error } of result.patternErrors) {
+          console.warn(`Invalid scope pattern "${pattern}": ${error}`);
+        }
+        for (const { message } of result.watchErrors) {
+          console.warn(message);
+        }
         if (!cancelled) {
           setWatchingState(true);
         }
@@ -124,6 +216,7 @@ export function useFileWatcher({
         console.error("Failed to start file watcher:", err);
         if (!cancelled) {
           setWatchingState(false);
+          setWatcherErrorState(String(err));
         }
       }
     }
@@ -138,7 +231,7 @@ export function useFileWatcher({
       });
       setWatchingState(false);
     };
-  }, [hqPath, enabledScopes]);
+  }, [hqPath, enabledScopes, watchOptions]);
 
   // Listen for fs-change events from the Rust backend
   useEffect(() => {
@@ -147,22 +240,29 @@ export function useFileWatcher({
     let unlisten: (() => void) | undefined;
 
     async function subscribe() {
-      unlisten = await listen<FsChangeEvent>("fs-change", (event) => {
-        const { path, kind } = event.payload;
+      // Events from this window's watcher only
+      const appWindow = getCurrentWebviewWindow();
+      unlisten = await appWindow.listen<FsChangeBatch>("fs-change", (event) => {
+        const { changes, rescanRecommended } = event.payload;
+
+        for (const change of changes) {
+          const { kind } = change;
+          // Tree nodes inside symlinked scopes carry the on-disk path
+          const path = change.realPath ?? change.path;
+          const from = change.realFrom ?? change.from;
+
+          if (kind === "rename" && from) {
+            onFileRenameRef.current?.(from, path);
+          }
 
-        if (kind === "create" || kind === "remove") {
-          // Tree structure change — debounce to batch rapid add/remove
-          if (treeDebounceTimer.current) {
-            clearTimeout(treeDebounceTimer.current);
+          if (kind === "modify" && path.endsWith(".md")) {
+            // Content change on an .md file
+            onFileChangeRef.current(path, change.hash);
           }
-          treeDebounceTimer.current = setTimeout(() => {
-            onTreeChangeRef.current();
-          }, TREE_DEBOUNCE_MS);
         }
 
-        if (kind === "modify" && path.endsWith(".md")) {
-          // Content change on an .md file
-          onFileChangeRef.current(path);
+        if (rescanRecommended) {
+          onRescanRecommendedRef.current?.();
         }
       });
     }
@@ -171,11 +271,39 @@ export function useFileWatcher({
 
     return () => {
       unlisten?.();
-      if (treeDebounceTimer.current) {
-        clearTimeout(treeDebounceTimer.current);
-      }
     };
   }, [hqPath]);
 
-  return { watching };
+  // Listen for watcher health events
+  useEffect(() => {
+    if (!hqPath) return;
+
+    let unlistenError: (() => void) | undefined;
+    let unlistenStatus: (() => void) | undefined;
+
+    async function subscribe() {
+      const appWindow = getCurrentWebviewWindow();
+      unlistenError = await appWindow.listen<WatcherError>(
+        "watcher-error",
+        (event) => {
+          console.error("File watcher error:", event.payload.message);
+        },
+      );
+      unlistenStatus = await appWindow.listen<WatcherStatus>(
+        "watcher-status",
+        (event) => {
+          setWatcherErrorState(event.payload.lastError?.message ?? null);
+        },
+      );
+    }
+
+    subscribe();
+
+    return () => {
+      unlistenError?.();
+      unlistenStatus?.();
+    };
+  }, [hqPath]);
+
+  return { watching, watcherError };
 }
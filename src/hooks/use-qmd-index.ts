@@ -0,0 +1,154 @@
+/**
+ * Hook for managing the qmd index of the connected HQ.
+ *
+ * Adds the HQ folder to qmd as a collection and runs `qmd update` and
+ * `qmd embed` through the backend, which runs one index task at a time and
+ * streams qmd's output as `qmd-progress` events. Automatic reindexes started
+ * by the file watcher (config `qmdAutoIndex`) show up through the same events.
+ */
+
+import { useState, useEffect, useCallback } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
+
+// ---------------------------------------------------------------------------
+// Types
+// ---------------------------------------------------------------------------
+
+/**
+ * "collection": adding the HQ to qmd; "update": re-indexing changed files;
+ * "embed": computing embeddings for semantic search
+ */
+export type IndexTask = "collection" | "update" | "embed";
+
+/** Payload of `qmd-progress`: a line of qmd output, or the end of a task */
+interface QmdProgressEvent {
+  task: IndexTask;
+  line: string | null;
+  done: boolean;
+  error: string | null;
+  /** Whether the file watcher started the task */
+  automatic: boolean;
+}
+
+/** Lines of qmd output kept for display */
+const MAX_LINES = 50;
+
+interface UseQmdIndexReturn {
+  /** The index task running, if any */
+  running: IndexTask | null;
+  /** Whether the running (or last) task was started by the file watcher */
+  automatic: boolean;
+  /** The last lines of qmd output, oldest first */
+  lines: string[];
+  /** Why the last task failed */
+  error: string | null;
+  /** Add the HQ folder to qmd; resolves to the collection's name */
+  createCollection: () => Promise<string | null>;
+  /** Run `qmd update` */
+  update: () => Promise<void>;
+  /** Run `qmd embed` */
+  embed: () => Promise<void>;
+}
+
+// ---------------------------------------------------------------------------
+// Hook
+// ---------------------------------------------------------------------------
+
+export function useQmdIndex(): UseQmdIndexReturn {
+  const [running, setRunning] = useState<IndexTask | null>(null);
+  const [automatic, setAutomatic] = useState(false);
+  const [lines, setLines] = useState<string[]>([]);
+  const [error, setError] = useState<string | null>(null);
+
+  // Pick up a task that was running before this hook mounted
+  useEffect(() => {
+    invoke<IndexTask | null>("qmd_index_running")
+      .then((task) => setRunning((current) => current ?? task))
+      .catch(() => {
+        // Treated as idle; progress events will correct it
+      });
+  }, []);
+
+  // Follow qmd's output
+  useEffect(() => {
+    const appWindow = getCurrentWebviewWindow();
+    const unlisten = appWindow.listen<QmdProgressEvent>(
+      "qmd-progress",
+      ({ payload }) => {
+        setAutomatic(payload.automatic);
+        if (payload.done) {
+          setRunning(null);
+          setError(payload.error);
+          return;
+        }
+        setRunning(payload.task);
+        if (payload.line !== null) {
+          const line = payload.line;
+          setLines((prev) => [...prev, line].slice(-MAX_LINES));
+        }
+      },
+    );
+
+    return () => {
+      // unlisten is a Promise<UnlistenFn>
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  const start = useCallback((task: IndexTask) => {
+    setRunning(task);
+    setAutomatic(false);
+    setLines([]);
+    setError(null);
+  }, []);
+
+  // A command that fails before qmd runs (no qmd, another task running)
+  // sends no final event, so ask what is running
+  const fail = useCallback((err: unknown) => {
+    setError(String(err));
+    invoke<IndexTask | null>("qmd_index_running")
+      .then(setRunning)
+      .catch(() => setRunning(null));
+  }, []);
+
+  const createCollection = useCallback(async () => {
+    start("collection");
+    try {
+      const name = await invoke<string>("create_hq_collection", {
+        name: null,
+      });
+      // Nothing is run when the HQ already has a collection
+      setRunning(null);
+      return name;
+    } catch (err) {
+      fail(err);
+      return null;
+    }
+  }, [start, fail]);
+
+  const runTask = useCallback(
+    async (task: "update" | "embed") => {
+      start(task);
+      try {
+        await invoke("run_qmd_index", { task });
+      } catch (err) {
+        fail(err);
+      }
+    },
+    [start, fail],
+  );
+
+  const update = useCallback(() => runTask("update"), [runTask]);
+  const embed = useCallback(() => runTask("embed"), [runTask]);
+
+  return {
+    running,
+    automatic,
+    lines,
+    error,
+    createCollection,
+    update,
+    embed,
+  };
+}
@@ -3,6 +3,11 @@
  *
  * Shells out to the Rust backend which invokes the qmd CLI.
  * Supports three search modes: keyword (fast), semantic, and hybrid (best quality).
+ * Without qmd, the backend searches its built-in index (keyword only).
+ *
+ * Each search carries a request ID, so the backend stops the qmd process of a
+ * search that a newer one replaces. Searches that run past the backend's time
+ * limit for their mode come back with a "timeout" error.
  */
 
 import { useState, useEffect, useCallback, useRef } from "react";
@@ -21,10 +26,18 @@ export interface SearchResult {
   score: number;
   /** Document title */
   title: string;
-  /** Display file path (qmd prefix stripped) */
+  /** Display file path: HQ-relative when in the HQ, else qmd prefix stripped */
   filePath: string;
   /** Matched text snippet */
   snippet: string;
+  /** Absolute path of the file (its tree node's path when in a scanned scope) */
+  absolutePath: string | null;
+  /** Scope pattern of the scanned scope the file is in */
+  scope: string | null;
+  /** Company ID for files under companies/<id>/ */
+  company: string | null;
+  /** Whether the file was found and can be shown */
+  openable: boolean;
 }
 
 interface QmdSearchResponse {
@@ -34,12 +47,48 @@ interface QmdSearchResponse {
     title: string;
     filePath: string;
     snippet: string;
+    collection: string | null;
+    absolutePath: string | null;
+    relativePath: string | null;
+    scope: string | null;
+    company: string | null;
+    openable: boolean;
   }>;
   total: number;
   error: string | null;
+  errorKind: SearchErrorKind | null;
 }
 
-export type QmdStatus = "ready" | "unavailable" | "checking";
+/** Why a search failed: "cancelled" searches were replaced by a newer one */
+type SearchErrorKind = "failed" | "timeout" | "cancelled";
+
+/** "builtin": qmd isn't installed, so the backend's own index is searched */
+export type QmdStatus = "ready" | "builtin" | "unavailable" | "checking";
+
+/** The qmd binary the backend found (`qmd_info`) */
+export interface QmdInfo {
+  path: string | null;
+  /** Whether `path` is the one set in the config */
+  configured: boolean;
+  version: string | null;
+  features: {
+    vsearch: boolean;
+    query: boolean;
+    json: boolean;
+    collections: boolean;
+  };
+  /** Set when qmd is too old, lacks search modes or fails to run */
+  warning: string | null;
+}
+
+/** What the backend's search engine can do (`search_status`) */
+interface SearchStatus {
+  backend: "qmd" | "builtin";
+  ready: boolean;
+  modes: SearchMode[];
+  collections: boolean;
+  detail: string | null;
+}
 
 // ---------------------------------------------------------------------------
 // Debounce intervals per search mode
@@ -62,6 +111,8 @@ interface UseSearchReturn {
   loading: boolean;
   /** Error message from qmd or the backend */
   error: string | null;
+  /** Whether the last search was stopped for running too long */
+  timedOut: boolean;
   /** Total result count from last search */
   total: number;
   /** Current search query */
@@ -78,39 +129,66 @@ interface UseSearchReturn {
   setCollection: (c: string) => void;
   /** Available qmd collections */
   collections: string[];
-  /** Whether qmd CLI is available */
+  /** Whether qmd CLI is available, or the built-in index is used instead */
   qmdStatus: QmdStatus;
+  /** The qmd binary found, if any, and what it supports */
+  qmdInfo: QmdInfo | null;
   /** Clear results and query */
   clear: () => void;
 }
 
-export function useSearch(): UseSearchReturn {
+/**
+ * @param qmdPath qmd binary from the app config (unset = let the backend
+ *   look for one)
+ */
+export function useSearch(qmdPath?: string): UseSearchReturn {
   const [query, setQueryState] = useState("");
   const [mode, setMode] = useState<SearchMode>("hybrid");
   const [collection, setCollection] = useState("all");
   const [results, setResults] = useState<SearchResult[]>([]);
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [timedOut, setTimedOut] = useState(false);
   const [total, setTotal] = useState(0);
   const [collections, setCollections] = useState<string[]>([]);
   const [qmdStatus, setQmdStatus] = useState<QmdStatus>("checking");
+  const [qmdInfo, setQmdInfo] = useState<QmdInfo | null>(null);
 
   // Refs to track the latest search and debounce timer
   const searchIdRef = useRef(0);
   const timerRef = useRef<ReturnType<typeof setTimeout> | null>(null);
 
-  // Check qmd availability on mount
+  // Stop the search in flight, if any, and ignore its results
+  const abandonSearch = useCallback(() => {
+    const requestId = searchIdRef.current++;
+    invoke("cancel_search", { requestId }).catch(() => {
+      // Nothing to cancel
+    });
+  }, []);
+
+  // Point the backend at the configured qmd, then check which search
+  // backend is in use
   useEffect(() => {
     let cancelled = false;
 
     async function check() {
       try {
-        const available = await invoke<boolean>("check_qmd_available");
+        const info = await invoke<QmdInfo>("set_qmd_path", {
+          path: qmdPath ?? null,
+        });
         if (!cancelled) {
-          setQmdStatus(available ? "ready" : "unavailable");
+          setQmdInfo(info);
         }
 
-        if (available) {
+        const status = await invoke<SearchStatus>("search_status");
+        if (!cancelled) {
+          setQmdStatus(status.backend === "qmd" ? "ready" : "builtin");
+          // Fall back to a mode the backend supports (the built-in index
+          // only does keyword search)
+          setMode((m) => (status.modes.includes(m) ? m : status.modes[0]));
+        }
+
+        if (status.collections) {
           try {
             const colls = await invoke<string[]>("list_qmd_collections");
             if (!cancelled) {
@@ -131,7 +209,7 @@ export function useSearch(): UseSearchReturn {
     return () => {
       cancelled = true;
     };
-  }, []);
+  }, [qmdPath]);
 
   // Execute search
   const executeSearch = useCallback(
@@ -147,6 +225,7 @@ export function useSearch(): UseSearchReturn {
       const currentId = ++searchIdRef.current;
       setLoading(true);
       setError(null);
+      setTimedOut(false);
 
       try {
         const response = await invoke<QmdSearchResponse>("qmd_search", {
@@ -154,13 +233,16 @@ export function useSearch(): UseSearchReturn {
           mode: m,
           collection: c === "all" ? null : c,
           limit: 20,
+          requestId: currentId,
         });
 
         // Discard stale results
         if (currentId !== searchIdRef.current) return;
+        if (response.errorKind === "cancelled") return;
 
         if (response.error) {
           setError(response.error);
+          setTimedOut(response.errorKind === "timeout");
           setResults([]);
           setTotal(0);
         } else {
@@ -168,8 +250,13 @@ export function useSearch(): UseSearchReturn {
             docId: r.docId || "",
             score: r.score || 0,
             title: r.title || r.filePath.split("/").pop() || "Untitled",
-            filePath: r.filePath.replace(/^qmd:\/\/[^/]+\//, ""),
+            filePath:
+              r.relativePath ?? r.filePath.replace(/^qmd:\/\/[^/]+\//, ""),
             snippet: r.snippet || "",
+            absolutePath: r.absolutePath,
+            scope: r.scope,
+            company: r.company,
+            openable: r.openable,
           }));
           setResults(mapped);
           setTotal(response.total);
@@ -199,9 +286,11 @@ export function useSearch(): UseSearchReturn {
       }
 
       if (!q.trim()) {
+        abandonSearch();
         setResults([]);
         setTotal(0);
         setError(null);
+        setTimedOut(false);
         setLoading(false);
         return;
       }
@@ -211,7 +300,7 @@ export function useSearch(): UseSearchReturn {
         executeSearch(q, mode, collection);
       }, DEBOUNCE_MS[mode]);
     },
-    [mode, collection, executeSearch],
+    [mode, collection, executeSearch, abandonSearch],
   );
 
   // Re-search when mode or collection changes (if there's an active query)
@@ -237,23 +326,26 @@ export function useSearch(): UseSearchReturn {
     setResults([]);
     setTotal(0);
     setError(null);
+    setTimedOut(false);
     setLoading(false);
-    searchIdRef.current++;
-  }, []);
+    abandonSearch();
+  }, [abandonSearch]);
 
-  // Cleanup debounce timer on unmount
+  // Cleanup debounce timer and search in flight on unmount
   useEffect(() => {
     return () => {
       if (timerRef.current) {
         clearTimeout(timerRef.current);
       }
+      abandonSearch();
     };
-  }, []);
+  }, [abandonSearch]);
 
   return {
     results,
     loading,
     error,
+    timedOut,
     total,
     query,
     setQuery,
@@ -263,6 +355,7 @@ export function useSearch(): UseSearchReturn {
     setCollection,
     collections,
     qmdStatus,
+    qmdInfo,
     clear,
   };
 }
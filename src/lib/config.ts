@@ -8,8 +8,29 @@ export interface AppConfig {
   hqFolderPath: string | null;
   /** Last 3 connected folder paths for quick switching */
   recentFolders: string[];
+  /** Cap on scanner worker threads (unset = one per CPU core) */
+  scanMaxThreads?: number;
+  /**
+   * How the file watcher watches: OS events ("native"), polling ("poll"), or
+   * OS events with polling for network/cloud folders ("auto", the default)
+   */
+  watcherMode?: WatcherMode;
+  /** How often polled folders are checked, in milliseconds (default 2000) */
+  watcherPollIntervalMs?: number;
+  /**
+   * Path to the qmd binary (unset = look on PATH and in the usual install
+   * locations)
+   */
+  qmdPath?: string;
+  /**
+   * Run `qmd update` (and `qmd embed`) automatically once changed documents
+   * have settled (default off)
+   */
+  qmdAutoIndex?: boolean;
 }
 
+export type WatcherMode = "native" | "poll" | "auto";
+
 export const DEFAULT_CONFIG: AppConfig = {
   hqFolderPath: null,
   recentFolders: [],
@@ -9,6 +9,8 @@
  */
 
 import { invoke } from "@tauri-apps/api/core";
+import type { UnlistenFn } from "@tauri-apps/api/event";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 import { getCompanyDisplayName, getCompanyDotColor } from "@/lib/companies";
 
 // ---------------------------------------------------------------------------
@@ -23,16 +25,251 @@ export interface FileTreeNode {
   path: string;
   /** Whether this node is a directory */
   isDirectory: boolean;
-  /** Title extracted from the first `# ` heading in .md files */
+  /** Title from front matter, else the format's own heading */
   title: string | null;
   /** Child nodes (populated for directories) */
   children: FileTreeNode[];
   /** Depth in the tree (0 = root scope directory) */
   depth: number;
-  /** Number of .md files in this subtree (for directories) */
+  /** Number of documents in this subtree (for directories) */
   fileCount: number;
   /** Last modified timestamp (seconds since epoch) */
   modified: number | null;
+  /** Full YAML/TOML front matter block (files only) */
+  frontMatter: Record<string, unknown> | null;
+  /** Front matter `tags` */
+  tags: string[];
+  /** Front matter `description` */
+  description: string | null;
+  /** Front matter `order`, for manual sidebar ordering */
+  order: number | null;
+  /** Front matter `draft` */
+  draft: boolean;
+  /**
+   * Set on symlinked directories that are listed but not expanded:
+   * "cycle" (resolves to an ancestor) or "alias" (content shown elsewhere)
+   */
+  linkKind: "cycle" | "alias" | null;
+  /** For cycle/alias nodes, the path of the node that shows the content */
+  linkTarget: string | null;
+  /** Document format, for picking a renderer (null for directories) */
+  format: DocFormat | null;
+  /**
+   * Set on directories whose subtree was cut short by a depth, entry or file
+   * limit; `children` and `fileCount` are then incomplete
+   */
+  truncated: boolean;
+  /** Number of direct children (for directories), kept when `children` is left out */
+  childCount: number;
+  /**
+   * Set on directories from `listDirectory` below the requested depth:
+   * `children` is empty, and `fileCount` and `childCount` come from the last
+   * scan (or only count the directory's own entries)
+   */
+  collapsed: boolean;
+}
+
+/** Document formats the Rust scanner includes. */
+export type DocFormat =
+  | "markdown"
+  | "mdx"
+  | "text"
+  | "rst"
+  | "org"
+  | "notebook";
+
+/** A non-fatal problem with a single file found during a scan. */
+export interface ScanWarning {
+  /** Absolute path of the file */
+  path: string;
+  /** Human-readable description of the problem */
+  message: string;
+}
+
+/** Result of the `scan_hq_directory` command. */
+export interface ScanResult {
+  /** One FileTreeNode root per matched scope directory */
+  roots: FileTreeNode[];
+  /** Whether the persisted scan cache for this HQ root was used */
+  cacheUsed: boolean;
+  /** Number of files re-read and directories re-listed (not served from cache) */
+  refreshed: number;
+  /** Scope patterns that failed to parse (the rest are still scanned) */
+  patternErrors: ScopePatternError[];
+  /** Ignored paths and the rule behind each (null unless explainExclusions was set) */
+  exclusions: ExcludedPath[] | null;
+  /** Per-file problems (e.g. malformed front matter) */
+  warnings: ScanWarning[];
+  /** Entries left out of the tree (and why) plus per-scope timing */
+  scanDiagnostics: ScanDiagnostics;
+}
+
+/** Result of the `list_directory` command. */
+export interface DirectoryListing {
+  /** The listed directory, with children down to the requested depth */
+  node: FileTreeNode;
+  /** Scope patterns that failed to parse */
+  patternErrors: ScopePatternError[];
+  /** Ignored paths in the listed subtree (null unless explainExclusions was set) */
+  exclusions: ExcludedPath[] | null;
+  /** Per-file problems in the listed subtree */
+  warnings: ScanWarning[];
+  /** Entries left out of the listed subtree (and why) plus its timing */
+  scanDiagnostics: ScanDiagnostics;
+}
+
+/** A node added to the tree by a `tree-patch` event. */
+export interface InsertedNode {
+  /** Path of the directory the node was added to */
+  parentPath: string;
+  /** Position among the parent's children, once earlier inserts are applied */
+  index: number;
+  /** The new node with its full subtree */
+  node: FileTreeNode;
+}
+
+/**
+ * Payload of `tree-patch` events, emitted by the watcher as it applies file
+ * changes to the last scanned tree. See `applyTreePatch`.
+ */
+export interface TreePatch {
+  inserted: InsertedNode[];
+  /** Paths of nodes removed along with their subtrees */
+  removed: string[];
+  /** Nodes whose own fields changed; directories come without `children` */
+  updated: FileTreeNode[];
+}
+
+/** Why the scanner left an entry out of the tree. */
+export type SkipReason =
+  | "brokenSymlink"
+  | "permissionDenied"
+  | "depthLimit"
+  | "entryLimit"
+  | "fileLimit"
+  | "excludedName"
+  | "nonUtf8Name"
+  | "emptyScope"
+  | "unreadable";
+
+/** An entry the scanner skipped. */
+export interface SkippedEntry {
+  /** Absolute path, or the scope pattern for patterns that matched nothing */
+  path: string;
+  reason: SkipReason;
+  /** Extra context, e.g. the ignore rule or the I/O error */
+  detail: string | null;
+}
+
+/** How long one scope root took to scan. */
+export interface ScopeTiming {
+  /** Scope directory path */
+  path: string;
+  /** Wall-clock time; scopes are scanned in parallel, so these overlap */
+  durationMs: number;
+  /** Documents found in the scope */
+  fileCount: number;
+}
+
+/** The `scanDiagnostics` section of a scan result. */
+export interface ScanDiagnostics {
+  /** A sample of the skipped entries (the first few of each reason by path), sorted by path */
+  skipped: SkippedEntry[];
+  /** Number of skipped entries per reason, sampled or not */
+  skippedCounts: Partial<Record<SkipReason, number>>;
+  /** One entry per scanned scope root, in scope order */
+  scopes: ScopeTiming[];
+  /** Wall-clock time of the whole scan */
+  totalMs: number;
+}
+
+/** A path hidden by an ignore rule during a scan. */
+export interface ExcludedPath {
+  /** Absolute path as seen through the scope */
+  path: string;
+  /** The pattern as written in its source */
+  rule: string;
+  /** Ignore file the rule came from, or "built-in" */
+  source: string;
+}
+
+/** A scope pattern the Rust side could not parse. */
+export interface ScopePatternError {
+  /** The pattern as given */
+  pattern: string;
+  /** Why it failed to parse */
+  error: string;
+}
+
+/** Options passed to the `scan_hq_directory` command. */
+export interface ScanOptions {
+  /** Maximum number of scanner worker threads (default: one per CPU core) */
+  maxThreads?: number;
+  /** Report every ignored path and the rule that excluded it */
+  explainExclusions?: boolean;
+  /** Deepest directory level scanned below each scope root (default: 15) */
+  maxDepth?: number;
+  /** Entries listed per directory, directories first (default: no limit) */
+  maxEntriesPerDir?: number;
+  /** Cap on documents across all scopes (default: no limit) */
+  maxTotalFiles?: number;
+  /** Per-scope overrides, keyed by scope pattern (not scope ID) */
+  scopeLimits?: Record<string, ScopeLimits>;
+}
+
+/** Traversal limits for one scope pattern; unset fields use the call-wide options. */
+export interface ScopeLimits {
+  maxDepth?: number;
+  maxEntriesPerDir?: number;
+  /** Cap on documents in each directory the pattern matches */
+  maxFiles?: number;
+}
+
+/** Payload of `scan-progress` events from a background scan. */
+export interface ScanProgress {
+  scanId: string;
+  /** Directories listed so far */
+  dirsVisited: number;
+  /** Documents found so far */
+  filesFound: number;
+  /** Scope root of the most recently visited directory ("" once done) */
+  currentScope: string;
+}
+
+/** Payload of `scan-root` events, emitted as each scope root completes. */
+export interface ScanRootEvent {
+  scanId: string;
+  /** Position of this root among the expanded scope directories */
+  index: number;
+  root: FileTreeNode;
+}
+
+/** Payload of the `scan-finished` event that ends every background scan. */
+export interface ScanFinishedEvent {
+  scanId: string;
+  /** The full result (null if the scan failed or was cancelled) */
+  result: ScanResult | null;
+  /** Why the scan failed (null on success or cancellation) */
+  error: string | null;
+  /** Whether the scan was stopped by `cancel_scan` */
+  cancelled: boolean;
+}
+
+/** Callbacks for a background scan started with `startScan`. */
+export interface ScanCallbacks {
+  /** Running totals, throttled on the Rust side */
+  onProgress?: (progress: ScanProgress) => void;
+  /** A scope root finished scanning (roots arrive in completion order) */
+  onRoot?: (root: FileTreeNode, index: number) => void;
+}
+
+/** A running background scan. */
+export interface ScanHandle {
+  scanId: string;
+  /** Resolves with the roots, or null if the scan was cancelled */
+  result: Promise<FileTreeNode[] | null>;
+  /** Ask the scan to stop; `result` then resolves with null */
+  cancel: () => Promise<void>;
 }
 
 /** A scope definition for which directories to scan. */
@@ -41,7 +278,11 @@ export interface ScanScope {
   id: string;
   /** Human-readable label (e.g., "Knowledge (Public)") */
   label: string;
-  /** Relative path pattern from HQ root. `*` matches any single directory level. */
+  /**
+   * Relative glob pattern from HQ root. `*` matches within one directory level,
+   * `**` matches any depth, `{a,b}` matches alternatives, and a leading `!`
+   * excludes matching directories.
+   */
   pattern: string;
   /** Whether this scope is enabled by default */
   defaultEnabled: boolean;
@@ -106,36 +347,227 @@ export const DEFAULT_SCOPES: ScanScope[] = [
 // Scanner
 // ---------------------------------------------------------------------------
 
+/** Resolve scope IDs to their patterns. */
+function scopePatterns(enabledScopeIds: string[]): string[] {
+  return enabledScopeIds
+    .map((id) => DEFAULT_SCOPES.find((s) => s.id === id))
+    .filter((s): s is ScanScope => s !== undefined)
+    .map((s) => s.pattern);
+}
+
+/** Log the non-fatal problems reported with a scan result or listing. */
+function logScanIssues(result: ScanResult | DirectoryListing): void {
+  for (const { pattern, error } of result.patternErrors) {
+    console.warn(`Invalid scope pattern "${pattern}": ${error}`);
+  }
+  for (const { path, message } of result.warnings) {
+    console.warn(`${path}: ${message}`);
+  }
+}
+
 /**
  * Scan the HQ directory for .md files within the given scopes.
  *
  * Invokes the Rust-side `scan_hq_directory` Tauri command for performance
  * (handles symlink resolution, title extraction, and recursive traversal).
+ * Unchanged entries are served from the Rust-side persistent scan cache.
  *
  * @param hqPath - Absolute path to the HQ root folder
  * @param enabledScopeIds - IDs of scopes to include (from DEFAULT_SCOPES)
+ * @param options - Optional scan settings (e.g. worker thread cap)
  * @returns Array of FileTreeNode roots, one per matched scope directory
  */
 export async function scanHqDirectory(
   hqPath: string,
   enabledScopeIds: string[],
+  options?: ScanOptions,
 ): Promise<FileTreeNode[]> {
-  // Resolve scope IDs to patterns
-  const patterns = enabledScopeIds
-    .map((id) => DEFAULT_SCOPES.find((s) => s.id === id))
-    .filter((s): s is ScanScope => s !== undefined)
-    .map((s) => s.pattern);
-
+  const patterns = scopePatterns(enabledScopeIds);
   if (patterns.length === 0) {
     return [];
   }
 
-  const results = await invoke<FileTreeNode[]>("scan_hq_directory", {
+  const result = await invoke<ScanResult>("scan_hq_directory", {
     hqPath,
     scopes: patterns,
+    options: options ?? null,
+  });
+
+  logScanIssues(result);
+  return result.roots;
+}
+
+/**
+ * Start a background scan via the Rust-side `start_scan` command.
+ *
+ * Same scan as `scanHqDirectory`, but returns immediately with a handle that
+ * can cancel it. Progress and completed scope roots are reported through
+ * `callbacks` while the scan runs.
+ *
+ * @param hqPath - Absolute path to the HQ root folder
+ * @param enabledScopeIds - IDs of scopes to include (from DEFAULT_SCOPES)
+ * @param options - Optional scan settings (e.g. worker thread cap)
+ * @param callbacks - Optional progress / partial-root listeners
+ */
+export async function startScan(
+  hqPath: string,
+  enabledScopeIds: string[],
+  options?: ScanOptions,
+  callbacks: ScanCallbacks = {},
+): Promise<ScanHandle> {
+  const patterns = scopePatterns(enabledScopeIds);
+  if (patterns.length === 0) {
+    return {
+      scanId: "",
+      result: Promise.resolve([]),
+      cancel: async () => {},
+    };
+  }
+
+  // Events can arrive before `start_scan` returns the ID; hold them until then
+  let scanId: string | null = null;
+  let pending: Array<() => void> = [];
+  const whenOwn = (id: string, handle: () => void) => {
+    if (scanId === null) {
+      pending.push(() => id === scanId && handle());
+    } else if (id === scanId) {
+      handle();
+    }
+  };
+
+  let settle!: (event: ScanFinishedEvent) => void;
+  const finished = new Promise<ScanFinishedEvent>((resolve) => {
+    settle = resolve;
+  });
+
+  // Scan events only go to the window that started the scan
+  const appWindow = getCurrentWebviewWindow();
+  const unlisteners: UnlistenFn[] = await Promise.all([
+    appWindow.listen<ScanProgress>("scan-progress", ({ payload }) =>
+      whenOwn(payload.scanId, () => callbacks.onProgress?.(payload)),
+    ),
+    appWindow.listen<ScanRootEvent>("scan-root", ({ payload }) =>
+      whenOwn(payload.scanId, () =>
+        callbacks.onRoot?.(payload.root, payload.index),
+      ),
+    ),
+    appWindow.listen<ScanFinishedEvent>("scan-finished", ({ payload }) =>
+      whenOwn(payload.scanId, () => settle(payload)),
+    ),
+  ]);
+  const unlistenAll = () => unlisteners.forEach((fn) => fn());
+
+  try {
+    scanId = await invoke<string>("start_scan", {
+      hqPath,
+      scopes: patterns,
+      options: options ?? null,
+    });
+  } catch (err) {
+    unlistenAll();
+    throw err;
+  }
+  pending.forEach((replay) => replay());
+  pending = [];
+
+  const id = scanId;
+  const result = finished.then((event) => {
+    unlistenAll();
+    if (event.cancelled) return null;
+    if (event.result === null) {
+      throw new Error(event.error ?? "Scan failed");
+    }
+    logScanIssues(event.result);
+    return event.result.roots;
+  });
+
+  return {
+    scanId: id,
+    result,
+    cancel: async () => {
+      await invoke<boolean>("cancel_scan", { scanId: id });
+    },
+  };
+}
+
+/**
+ * List one directory of the tree via the Rust-side `list_directory` command,
+ * for expanding folders on demand instead of scanning the whole tree.
+ *
+ * Directories below `depth` come back `collapsed` (no children, with the
+ * counts of the last scan); list them again to expand them.
+ *
+ * @param hqPath - Absolute path to the HQ root folder
+ * @param enabledScopeIds - IDs of scopes the tree was scanned with
+ * @param path - A scope root or directory node path below one
+ * @param depth - Levels of children to include (default 1)
+ * @param options - Scan settings the tree was scanned with
+ */
+export async function listDirectory(
+  hqPath: string,
+  enabledScopeIds: string[],
+  path: string,
+  depth?: number,
+  options?: ScanOptions,
+): Promise<FileTreeNode> {
+  const listing = await invoke<DirectoryListing>("list_directory", {
+    hqPath,
+    scopes: scopePatterns(enabledScopeIds),
+    path,
+    depth: depth ?? null,
+    options: options ?? null,
   });
 
-  return results;
+  logScanIssues(listing);
+  return listing.node;
+}
+
+/**
+ * Return a copy of the tree with the node at `node.path` replaced by `node`
+ * (e.g. a collapsed directory by its `listDirectory` result). The tree is
+ * returned unchanged if no node has that path.
+ */
+export function replaceNode(
+  roots: FileTreeNode[],
+  node: FileTreeNode,
+): FileTreeNode[] {
+  const replace = (current: FileTreeNode): FileTreeNode => {
+    if (current.path === node.path) return node;
+    if (!current.isDirectory || current.children.length === 0) return current;
+    return { ...current, children: current.children.map(replace) };
+  };
+  return roots.map(replace);
+}
+
+/**
+ * Return a copy of the tree with a `tree-patch` applied: removals first, then
+ * inserts in order, then updates (directories keep their current children).
+ */
+export function applyTreePatch(
+  roots: FileTreeNode[],
+  patch: TreePatch,
+): FileTreeNode[] {
+  const removed = new Set(patch.removed);
+  const updated = new Map(patch.updated.map((node) => [node.path, node]));
+  const inserted = new Map<string, InsertedNode[]>();
+  for (const insert of patch.inserted) {
+    const list = inserted.get(insert.parentPath) ?? [];
+    list.push(insert);
+    inserted.set(insert.parentPath, list);
+  }
+
+  const apply = (node: FileTreeNode): FileTreeNode => {
+    const update = updated.get(node.path);
+    let children = node.children
+      .filter((child) => !removed.has(child.path))
+      .map(apply);
+    for (const { index, node: child } of inserted.get(node.path) ?? []) {
+      children = [...children.slice(0, index), child, ...children.slice(index)];
+    }
+    if (update && !update.isDirectory) return update;
+    return { ...(update ?? node), children };
+  };
+  return roots.filter((root) => !removed.has(root.path)).map(apply);
 }
 
 /**